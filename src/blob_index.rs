@@ -0,0 +1,255 @@
+// Skipping PBF blobs that hold no element types a conversion pass needs
+use crate::feature::ElementType;
+use crate::filter::ElementTypes;
+use anyhow::{Context, Result};
+use osmpbf::{Blob, BlobDecode, BlobReader, Element, HeaderBlock, PrimitiveBlock};
+use std::collections::{BTreeMap, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, mpsc};
+
+/// Header feature of files with all nodes first, then ways, then relations
+const TYPE_SORTED_FEATURE: &str = "Sort.Type_then_ID";
+
+pub fn element_type(element: &Element) -> ElementType {
+    match element {
+        Element::Node(_) | Element::DenseNode(_) => ElementType::Node,
+        Element::Way(_) => ElementType::Way,
+        Element::Relation(_) => ElementType::Relation,
+    }
+}
+
+pub fn is_type_sorted(header: &HeaderBlock) -> bool {
+    header
+        .optional_features()
+        .iter()
+        .any(|feature| feature == TYPE_SORTED_FEATURE)
+}
+
+/// Element types present in a decoded block
+pub fn block_types(block: &PrimitiveBlock) -> ElementTypes {
+    let mut types = ElementTypes::NONE;
+    for group in block.groups() {
+        types.node |= group.nodes().next().is_some() || group.dense_nodes().next().is_some();
+        types.way |= group.ways().next().is_some();
+        types.relation |= group.relations().next().is_some();
+    }
+    types
+}
+
+/// Element types of each blob, by position in the file, learned while a pass
+/// decodes them. Later passes over the same file skip blobs known to hold only
+/// unwanted types without decoding them; the first pass has to decompress
+/// every blob it reaches. In files sorted by type, a pass also stops at the
+/// first blob past all the types it wants.
+#[derive(Debug, Default)]
+pub struct BlobIndex {
+    types: Vec<Option<ElementTypes>>,
+    sorted: bool,
+    pub skipped: u64, // Blobs not decoded or not processed
+}
+
+impl BlobIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `f` for every data block that may hold elements of the `wanted` types
+    pub fn for_each_block<F>(
+        &mut self,
+        input_path: &str,
+        wanted: ElementTypes,
+        mut f: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&PrimitiveBlock) -> Result<()>,
+    {
+        let reader = BlobReader::from_path(input_path).context("Failed to open PBF file")?;
+
+        for (position, blob_result) in reader.enumerate() {
+            let blob = blob_result.context("Failed to read blob")?;
+            if self.known_without(position, wanted) {
+                self.skipped += 1;
+                continue;
+            }
+
+            match blob.decode().context("Failed to decode blob")? {
+                BlobDecode::OsmHeader(header) => {
+                    self.sorted = is_type_sorted(&header);
+                }
+                BlobDecode::OsmData(block) => {
+                    let types = block_types(&block);
+                    self.record(position, types);
+                    if types.intersection(wanted).is_empty() {
+                        self.skipped += 1;
+                        if self.sorted && wanted.all_before(types) {
+                            break;
+                        }
+                        continue;
+                    }
+                    f(&block)?;
+                }
+                BlobDecode::Unknown(_) => {
+                    // Skip unknown blobs
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `for_each_block`, but blocks are decoded and passed to `map` on
+    /// one worker thread per rayon thread while `commit` gets the results on
+    /// the calling thread in file order. Workers keep decoding while a result
+    /// is committed, up to a few blobs per worker ahead of the commits.
+    pub fn for_each_block_ordered<T, M, C>(
+        &mut self,
+        input_path: &str,
+        wanted: ElementTypes,
+        map: M,
+        mut commit: C,
+    ) -> Result<()>
+    where
+        T: Send,
+        M: Fn(&PrimitiveBlock) -> Result<T> + Sync,
+        C: FnMut(T) -> Result<()>,
+    {
+        let reader = BlobReader::from_path(input_path).context("Failed to open PBF file")?;
+        let workers = rayon::current_num_threads();
+        let max_in_flight = workers * 4;
+
+        let (job_sender, job_receiver) = mpsc::channel::<(usize, Blob)>();
+        let job_receiver = Mutex::new(job_receiver);
+        let (done_sender, done_receiver) = mpsc::channel();
+
+        std::thread::scope(|scope| {
+            // Owned by this closure, so returning stops the workers
+            let job_sender = job_sender;
+            let done_receiver = done_receiver;
+            for _ in 0..workers {
+                let job_receiver = &job_receiver;
+                let done_sender = done_sender.clone();
+                let map = &map;
+                scope.spawn(move || {
+                    // Stops once the jobs are dropped or the results are no longer read
+                    while let Ok((sequence, blob)) = job_receiver.lock().unwrap().recv() {
+                        let decoded = panic::catch_unwind(AssertUnwindSafe(|| {
+                            decode_block(blob, wanted, map)
+                        }))
+                        .unwrap_or_else(|_| Err(anyhow::anyhow!("Decoding a blob panicked")));
+                        if done_sender.send((sequence, decoded)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(done_sender);
+
+            // Results arrive in any order; `done` holds them until their turn
+            let mut blobs = reader.enumerate();
+            let mut positions: VecDeque<usize> = VecDeque::new();
+            let mut done: BTreeMap<usize, Result<Decoded<T>>> = BTreeMap::new();
+            let mut sent = 0;
+            let mut committed = 0;
+            let mut reading = true;
+            loop {
+                while reading && sent - committed < max_in_flight {
+                    let Some((position, blob_result)) = blobs.next() else {
+                        reading = false;
+                        break;
+                    };
+                    let blob = blob_result.context("Failed to read blob")?;
+                    if self.known_without(position, wanted) {
+                        self.skipped += 1;
+                        continue;
+                    }
+                    job_sender
+                        .send((sent, blob))
+                        .context("Blob decoding workers stopped")?;
+                    positions.push_back(position);
+                    sent += 1;
+                }
+                if committed == sent {
+                    return Ok(());
+                }
+
+                let Some(decoded) = done.remove(&committed) else {
+                    let (sequence, decoded) = done_receiver
+                        .recv()
+                        .context("Blob decoding workers stopped")?;
+                    done.insert(sequence, decoded);
+                    continue;
+                };
+                let position = positions.pop_front().unwrap();
+                committed += 1;
+
+                match decoded? {
+                    Decoded::Header { sorted } => self.sorted = sorted,
+                    Decoded::Data { types, value } => {
+                        self.record(position, types);
+                        match value {
+                            Some(value) => commit(value)?,
+                            None => {
+                                self.skipped += 1;
+                                if self.sorted && wanted.all_before(types) {
+                                    return Ok(());
+                                }
+                            }
+                        }
+                    }
+                    Decoded::Unknown => {
+                        // Skip unknown blobs
+                    }
+                }
+            }
+        })
+    }
+
+    fn known_without(&self, position: usize, wanted: ElementTypes) -> bool {
+        self.types
+            .get(position)
+            .copied()
+            .flatten()
+            .is_some_and(|types| types.intersection(wanted).is_empty())
+    }
+
+    fn record(&mut self, position: usize, types: ElementTypes) {
+        if self.types.len() <= position {
+            self.types.resize(position + 1, None);
+        }
+        self.types[position] = Some(types);
+    }
+}
+
+/// Decode a blob for `for_each_block_ordered`, mapping blocks with wanted types
+fn decode_block<T>(
+    blob: Blob,
+    wanted: ElementTypes,
+    map: &(impl Fn(&PrimitiveBlock) -> Result<T> + Sync),
+) -> Result<Decoded<T>> {
+    Ok(match blob.decode().context("Failed to decode blob")? {
+        BlobDecode::OsmHeader(header) => Decoded::Header {
+            sorted: is_type_sorted(&header),
+        },
+        BlobDecode::OsmData(block) => {
+            let types = block_types(&block);
+            let value = if types.intersection(wanted).is_empty() {
+                None
+            } else {
+                Some(map(&block)?)
+            };
+            Decoded::Data { types, value }
+        }
+        BlobDecode::Unknown(_) => Decoded::Unknown,
+    })
+}
+
+/// A blob decoded by `for_each_block_ordered`
+enum Decoded<T> {
+    Header {
+        sorted: bool,
+    },
+    Data {
+        types: ElementTypes,
+        value: Option<T>, // None for blocks without wanted types
+    },
+    Unknown,
+}
@@ -0,0 +1,195 @@
+// Boundary polygons for --clip-polygon: .poly and GeoJSON loading and a
+// prepared point-in-polygon test
+use anyhow::{Context, Result};
+use geojson::{GeoJson, Value};
+use std::path::Path;
+
+/// Edge of a boundary ring as `(lon, lat)` endpoints
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    from: (f64, f64),
+    to: (f64, f64),
+}
+
+/// Rings of a (multi)polygon boundary, prepared for fast point-in-polygon tests.
+///
+/// Edges are bucketed into horizontal bands, so a test only looks at the edges
+/// crossing the point's latitude. Containment is even-odd over all rings, which
+/// handles holes and disjoint parts without knowing which ring is which.
+#[derive(Debug, Clone)]
+pub struct PreparedPolygon {
+    pub vertices: Vec<(f64, f64)>, // (lon, lat) of every ring vertex
+    extent: [f64; 4],              // [min_lon, min_lat, max_lon, max_lat]
+    band_height: f64,
+    bands: Vec<Vec<Edge>>,
+}
+
+impl PreparedPolygon {
+    /// Prepare closed rings of `(lon, lat)` vertices
+    pub fn new(rings: &[Vec<(f64, f64)>]) -> Result<Self> {
+        let edges: Vec<Edge> = rings
+            .iter()
+            .filter(|ring| ring.len() >= 3)
+            .flat_map(|ring| {
+                // Rings are closed implicitly when the last vertex isn't the first
+                let closing = ring
+                    .first()
+                    .zip(ring.last())
+                    .filter(|(first, last)| first != last);
+                ring.windows(2)
+                    .map(|pair| Edge {
+                        from: pair[0],
+                        to: pair[1],
+                    })
+                    .chain(closing.map(|(first, last)| Edge {
+                        from: *last,
+                        to: *first,
+                    }))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        if edges.is_empty() {
+            anyhow::bail!("boundary has no ring with at least 3 vertices");
+        }
+
+        let vertices: Vec<(f64, f64)> = rings.iter().flatten().copied().collect();
+        let mut extent = [f64::MAX, f64::MAX, f64::MIN, f64::MIN];
+        for &(lon, lat) in &vertices {
+            extent = [
+                extent[0].min(lon),
+                extent[1].min(lat),
+                extent[2].max(lon),
+                extent[3].max(lat),
+            ];
+        }
+
+        // About four edges per band keeps tests short for boundaries of any size
+        let band_count = (edges.len() / 4).clamp(1, 65_536);
+        let band_height = ((extent[3] - extent[1]) / band_count as f64).max(f64::MIN_POSITIVE);
+        let mut bands = vec![Vec::new(); band_count];
+        for edge in edges {
+            let low = edge.from.1.min(edge.to.1);
+            let high = edge.from.1.max(edge.to.1);
+            let first = band_index(low, extent[1], band_height, band_count);
+            let last = band_index(high, extent[1], band_height, band_count);
+            for band in &mut bands[first..=last] {
+                band.push(edge);
+            }
+        }
+
+        Ok(PreparedPolygon {
+            vertices,
+            extent,
+            band_height,
+            bands,
+        })
+    }
+
+    /// Load an Osmosis `.poly` file or a GeoJSON Polygon/MultiPolygon (also as
+    /// a Feature or FeatureCollection)
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read boundary file {}", path.display()))?;
+        let rings = if content.trim_start().starts_with('{') {
+            geojson_rings(&content)
+        } else {
+            poly_rings(&content)
+        }
+        .with_context(|| format!("Invalid boundary file {}", path.display()))?;
+        PreparedPolygon::new(&rings)
+    }
+
+    /// `[min_lon, min_lat, max_lon, max_lat]` of the boundary
+    pub fn extent(&self) -> [f64; 4] {
+        self.extent
+    }
+
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        if lon < self.extent[0]
+            || lon > self.extent[2]
+            || lat < self.extent[1]
+            || lat > self.extent[3]
+        {
+            return false;
+        }
+
+        let band = band_index(lat, self.extent[1], self.band_height, self.bands.len());
+        let mut inside = false;
+        for edge in &self.bands[band] {
+            let ((x1, y1), (x2, y2)) = (edge.from, edge.to);
+            if (y1 > lat) != (y2 > lat) && lon < x1 + (lat - y1) * (x2 - x1) / (y2 - y1) {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+}
+
+fn band_index(lat: f64, min_lat: f64, band_height: f64, band_count: usize) -> usize {
+    (((lat - min_lat) / band_height) as usize).min(band_count - 1)
+}
+
+/// Rings of an Osmosis polygon filter file: a name line, then sections of
+/// `lon lat` lines each closed by `END` (holes start with `!`), then `END`
+pub fn poly_rings(content: &str) -> Result<Vec<Vec<(f64, f64)>>> {
+    let mut lines = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+    lines.next().context("empty .poly file")?; // Name
+
+    let mut rings = Vec::new();
+    loop {
+        match lines.next() {
+            Some("END") => return Ok(rings),
+            Some(_section) => {
+                let mut ring = Vec::new();
+                loop {
+                    let line = lines.next().context("section without END")?;
+                    if line == "END" {
+                        break;
+                    }
+                    let mut values = line.split_whitespace().map(str::parse::<f64>);
+                    match (values.next(), values.next()) {
+                        (Some(Ok(lon)), Some(Ok(lat))) => ring.push((lon, lat)),
+                        _ => anyhow::bail!("invalid coordinate line '{}'", line),
+                    }
+                }
+                rings.push(ring);
+            }
+            None => anyhow::bail!("missing final END"),
+        }
+    }
+}
+
+/// Rings of every Polygon and MultiPolygon in a GeoJSON document
+pub fn geojson_rings(content: &str) -> Result<Vec<Vec<(f64, f64)>>> {
+    let geojson: GeoJson = content.parse().context("invalid GeoJSON")?;
+    let geometries: Vec<geojson::Geometry> = match geojson {
+        GeoJson::Geometry(geometry) => vec![geometry],
+        GeoJson::Feature(feature) => feature.geometry.into_iter().collect(),
+        GeoJson::FeatureCollection(collection) => collection
+            .features
+            .into_iter()
+            .filter_map(|feature| feature.geometry)
+            .collect(),
+    };
+
+    let to_ring = |ring: &Vec<Vec<f64>>| -> Vec<(f64, f64)> {
+        ring.iter()
+            .map(|position| (position[0], position[1]))
+            .collect()
+    };
+    let mut rings = Vec::new();
+    for geometry in &geometries {
+        match &geometry.value {
+            Value::Polygon(polygon) => rings.extend(polygon.iter().map(to_ring)),
+            Value::MultiPolygon(polygons) => rings.extend(polygons.iter().flatten().map(to_ring)),
+            _ => {}
+        }
+    }
+    if rings.is_empty() {
+        anyhow::bail!("no Polygon or MultiPolygon geometry found");
+    }
+    Ok(rings)
+}
@@ -1,16 +1,23 @@
-use crate::coordinate_storage::CoordinateStorage;
-use crate::osm::{MemberType, OsmElement, OsmNode, OsmRelation, OsmRelationMember, OsmWay};
+use crate::blob_index::{BlobIndex, element_type};
+use crate::coordinate_storage::{CoordinateStorage, create_coordinate_storage};
+use crate::feature::{Feature, relation_feature, way_feature};
+use crate::filter::{ElementTypes, TagFilter};
+use crate::go_compat::Compat;
+use crate::node_cache::NodeCache;
+use crate::options::{ConvertOptions, GeometryLevel};
+use crate::osm::OsmElement;
+use crate::output::{OutputFormat, create_sink};
+use crate::way_storage::{collect_relation_ways, create_way_storage, relation_way_ids};
 use anyhow::{Context, Result};
-use osmpbf::{Element, ElementReader};
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::path::Path;
-use std::sync::{Arc, mpsc};
+use osmpbf::Element;
+use std::sync::mpsc;
 use std::thread;
+use std::time::Instant;
 
 const MEMORY_LIMIT_GB: u64 = 8;
+const OUTPUT_BATCH_SIZE: usize = 1000; // Features per sink write (one Parquet batch)
 
+#[allow(dead_code)]
 pub fn convert_pbf_to_geojson_with_geometry_level(
     input_path: &str,
     output_path: Option<&String>,
@@ -20,6 +27,68 @@ pub fn convert_pbf_to_geojson_with_geometry_level(
     temp_db_path: Option<&String>,
     keep_temp_db: bool,
 ) -> Result<()> {
+    let options = ConvertOptions {
+        tag_filter: tag_filter
+            .as_deref()
+            .map(TagFilter::from_groups)
+            .transpose()?,
+        pretty_print,
+        geometry_level: parse_geometry_level(geometry_level),
+        temp_db_path: temp_db_path.cloned(),
+        keep_temp_db,
+        format: OutputFormat::Jsonl,
+        ..ConvertOptions::default()
+    };
+    convert_pbf_with_options(input_path, output_path, &options)
+}
+
+/// Geometry level of the `&str` based entry points; unknown levels run as auto
+pub(crate) fn parse_geometry_level(geometry_level: &str) -> GeometryLevel {
+    geometry_level.parse().unwrap_or_else(|_| {
+        eprintln!(
+            "Unknown geometry level '{}', defaulting to auto",
+            geometry_level
+        );
+        GeometryLevel::Auto
+    })
+}
+
+/// Single-threaded conversion entry point used by `--no-parallel`
+pub fn convert_pbf_with_options(
+    input_path: &str,
+    output_path: Option<&String>,
+    options: &ConvertOptions,
+) -> Result<()> {
+    convert_pbf(input_path, output_path, options, None)
+}
+
+/// Single-threaded conversion with node coordinates kept in
+/// `coordinate_storage` instead of a new store of the `options.node_store` kind
+#[allow(dead_code)]
+pub fn convert_pbf_with_store(
+    input_path: &str,
+    output_path: Option<&String>,
+    options: &ConvertOptions,
+    coordinate_storage: CoordinateStorage,
+) -> Result<()> {
+    convert_pbf(input_path, output_path, options, Some(coordinate_storage))
+}
+
+fn convert_pbf(
+    input_path: &str,
+    output_path: Option<&String>,
+    options: &ConvertOptions,
+    coordinate_storage: Option<CoordinateStorage>,
+) -> Result<()> {
+    // Go compatible output follows file order, which only the blob-ordered
+    // parallel converter preserves, as for --no-parallel in the CLI
+    if options.compat == Compat::Go {
+        anyhow::bail!(
+            "Go compatible output needs the parallel converter, which keeps records in file order; use convert_pbf_parallel_with_options"
+        );
+    }
+
+    let geometry_level = options.geometry_level;
     let file_size = std::fs::metadata(input_path)
         .context("Failed to get file metadata")?
         .len();
@@ -27,17 +96,17 @@ pub fn convert_pbf_to_geojson_with_geometry_level(
 
     eprintln!("Input file size: {:.1}GB", file_size_gb);
     eprintln!("Geometry level: {}", geometry_level);
-
+    eprintln!("Output format: {}", options.format);
     let use_full_geometry = match geometry_level {
-        "basic" => {
+        GeometryLevel::Basic => {
             eprintln!("Using basic format (no geometry computation)...");
             false
         }
-        "full" => {
+        GeometryLevel::Full => {
             eprintln!("Using full geometry format with disk-based coordinate storage...");
             true
         }
-        "auto" => {
+        GeometryLevel::Auto => {
             if file_size_gb > 1.0 {
                 eprintln!("Large file detected, auto-selecting streaming approach...");
                 false
@@ -46,466 +115,134 @@ pub fn convert_pbf_to_geojson_with_geometry_level(
                 true
             }
         }
-        _ => {
-            eprintln!(
-                "Unknown geometry level '{}', defaulting to auto",
-                geometry_level
-            );
-            file_size_gb <= 1.0
-        }
     };
 
     if use_full_geometry {
-        // For very small files, attempt three-pass processing for complete relation geometry
-        if file_size_gb < 0.1 {
-            eprintln!(
-                "Very small file, attempting three-pass processing with relation geometry..."
-            );
-            convert_pbf_with_complete_geometry(
-                input_path,
-                output_path,
-                tag_filter,
-                pretty_print,
-                temp_db_path,
-                keep_temp_db,
-            )
-        } else {
-            convert_pbf_with_full_geometry(
-                input_path,
-                output_path,
-                tag_filter,
-                pretty_print,
-                temp_db_path,
-                keep_temp_db,
-            )
-        }
+        convert_pbf_with_complete_geometry(input_path, output_path, options, coordinate_storage)
     } else {
-        convert_pbf_streaming_only(input_path, output_path, tag_filter, pretty_print)
+        convert_pbf_streaming_only(input_path, output_path, options)
     }
 }
 
-fn convert_pbf_with_full_geometry(
-    input_path: &str,
-    output_path: Option<&String>,
-    tag_filter: Option<Vec<Vec<String>>>,
-    pretty_print: bool,
-    temp_db_path: Option<&String>,
-    keep_temp_db: bool,
-) -> Result<()> {
-    // TWO-PASS APPROACH with disk-based coordinate storage
-    eprintln!("Pass 1: Collecting all node coordinates to disk database...");
-    let coordinate_storage = create_coordinate_storage(temp_db_path, keep_temp_db)?;
-    let node_count = collect_all_nodes_to_disk(input_path, &coordinate_storage)?;
-    eprintln!("Stored {} node coordinates in disk database", node_count);
-
-    eprintln!("Pass 2: Processing elements with full geometry...");
-    let reader = ElementReader::from_path(input_path).context("Failed to open PBF file")?;
-
-    // Streaming architecture with disk-based geometry computation
-    let (tx, rx) = mpsc::sync_channel::<String>(1000);
-    let tag_filter_clone = tag_filter.clone();
-    let coordinate_storage = Arc::new(coordinate_storage);
-
-    // Spawn background thread for immediate output streaming
-    let output_thread = {
-        let output_path = output_path.cloned();
-        thread::spawn(move || -> Result<(), anyhow::Error> {
-            // Setup output writer in the output thread
-            let mut writer: Box<dyn Write> = match output_path.as_ref() {
-                Some(path) => {
-                    let file = File::create(path)
-                        .with_context(|| format!("Failed to create output file: {}", path))?;
-                    Box::new(BufWriter::new(file))
-                }
-                None => Box::new(std::io::stdout()),
-            };
-
-            let mut feature_count = 0usize;
-
-            while let Ok(json_line) = rx.recv() {
-                writeln!(writer, "{}", json_line)?; // Stream immediately to output
-                feature_count += 1;
+fn process_element(
+    element: Element,
+    options: &ConvertOptions,
+    types: ElementTypes,
+) -> Option<OsmElement> {
+    if !types.contains(element_type(&element)) {
+        return None;
+    }
 
-                // Memory monitoring every 10k features
-                if feature_count % 10000 == 0 {
-                    eprintln!("Streamed {} features", feature_count);
-                    if let Some(memory_usage) = get_memory_usage_mb() {
-                        eprintln!("Current memory usage: {} MB", memory_usage);
-                    }
-                }
+    let osm_element = OsmElement::from_pbf(element);
 
-                // Memory warning
-                if feature_count % 50000 == 0
-                    && let Some(memory_usage) = get_memory_usage_mb()
-                    && memory_usage > MEMORY_LIMIT_GB * 1024
-                {
-                    eprintln!(
-                        "⚠️  Memory usage ({} MB) exceeds limit ({} GB)",
-                        memory_usage, MEMORY_LIMIT_GB
-                    );
-                }
-            }
+    if options.selects(&osm_element) {
+        Some(osm_element)
+    } else {
+        None
+    }
+}
 
-            writer.flush()?;
-            eprintln!(
-                "Streaming output complete. Total features: {}",
-                feature_count
-            );
-            Ok(())
-        })
+/// Store all node coordinates to disk and collect the IDs of ways referenced by
+/// relations that will be emitted, so their node lists can be stored in pass 2.
+/// With `store_nodes` false, as for a complete node cache, only relations are read.
+fn collect_all_nodes_to_disk(
+    input_path: &str,
+    storage: &CoordinateStorage,
+    options: &ConvertOptions,
+    blob_index: &mut BlobIndex,
+    store_nodes: bool,
+) -> Result<(u64, Vec<i64>)> {
+    let types = options.element_types();
+    let wanted = ElementTypes {
+        node: store_nodes,
+        relation: types.relation,
+        ..ElementTypes::NONE
     };
-
-    // PARALLEL PROCESSING: Use par_map_reduce for multi-core processing
-    let coordinate_storage_for_processing = coordinate_storage.clone();
-    reader.par_map_reduce(
-        move |element| {
-            // Parallel map: Process each element on available CPU cores
-            let mut results = Vec::new();
-            if let Some(osm_element) = process_element(element, &tag_filter_clone) {
-                let json_opt = match &osm_element {
-                    OsmElement::Node(node) => {
-                        if !node.tags.is_empty() {
-                            convert_node_to_json(node, pretty_print)
-                        } else {
-                            None
+    let mut node_count = 0u64;
+    let mut way_ids = Vec::new();
+
+    // PARALLEL NODE COLLECTION to disk: blobs are decoded in parallel and
+    // stored in file order, so sorted node IDs are appended
+    if !wanted.is_empty() {
+        blob_index.for_each_block_ordered(
+            input_path,
+            wanted,
+            |block| {
+                let mut batch_nodes = Vec::new();
+                let mut batch_way_ids = Vec::new();
+                for element in block.elements() {
+                    match element {
+                        Element::Node(node) if store_nodes => {
+                            batch_nodes.push((node.id(), node.lat(), node.lon()));
                         }
-                    }
-                    OsmElement::Way(way) => {
-                        if !way.tags.is_empty() {
-                            convert_way_to_json_with_disk_geometry(
-                                way,
-                                &coordinate_storage_for_processing,
-                                pretty_print,
-                            )
-                        } else {
-                            None
+                        Element::DenseNode(dense_node) if store_nodes => {
+                            batch_nodes.push((dense_node.id(), dense_node.lat(), dense_node.lon()));
                         }
-                    }
-                    OsmElement::Relation(relation) => {
-                        if !relation.tags.is_empty() {
-                            convert_relation_to_json_with_disk_geometry(
-                                relation,
-                                &coordinate_storage_for_processing,
-                                pretty_print,
-                            )
-                        } else {
-                            None
+                        Element::Relation(_) => {
+                            // Only relations that pass the filter need their member ways
+                            if let Some(OsmElement::Relation(relation)) =
+                                process_element(element, options, types)
+                            {
+                                batch_way_ids.extend(relation_way_ids(&relation));
+                            }
                         }
+                        _ => {} // Skip ways in pass 1
                     }
-                };
-
-                if let Some(json_str) = json_opt
-                    && !json_str.is_empty()
-                {
-                    results.push(json_str);
                 }
-            }
-            results
-        },
-        Vec::new,
-        |mut acc, mut batch| {
-            // Reduce: Stream results immediately to output thread
-            for json_str in batch.drain(..) {
-                if tx.send(json_str).is_err() {
-                    return acc; // Output thread disconnected
+                Ok((batch_nodes, batch_way_ids))
+            },
+            |(batch_nodes, mut batch_way_ids)| {
+                if !batch_nodes.is_empty() {
+                    storage.store_nodes(&batch_nodes)?;
+                    node_count += batch_nodes.len() as u64;
                 }
-            }
-
-            // Keep memory bounded - don't accumulate
-            acc.clear();
-            acc
-        },
-    )?;
-
-    // Close channel to signal completion
-    drop(tx);
-
-    // Wait for output thread to finish
-    output_thread
-        .join()
-        .map_err(|_| anyhow::anyhow!("Output thread panicked"))??;
-
-    Ok(())
-}
-
-fn process_element(element: Element, tag_filter: &Option<Vec<Vec<String>>>) -> Option<OsmElement> {
-    let osm_element = match element {
-        Element::Node(node) => {
-            let tags: HashMap<String, String> = node
-                .tags()
-                .map(|(k, v)| (k.to_string(), v.to_string()))
-                .collect();
-            OsmElement::Node(OsmNode {
-                id: node.id(),
-                lat: node.lat(),
-                lon: node.lon(),
-                tags,
-            })
-        }
-        Element::DenseNode(dense_node) => {
-            let tags: HashMap<String, String> = dense_node
-                .tags()
-                .map(|(k, v)| (k.to_string(), v.to_string()))
-                .collect();
-            OsmElement::Node(OsmNode {
-                id: dense_node.id(),
-                lat: dense_node.lat(),
-                lon: dense_node.lon(),
-                tags,
-            })
-        }
-        Element::Way(way) => {
-            let tags: HashMap<String, String> = way
-                .tags()
-                .map(|(k, v)| (k.to_string(), v.to_string()))
-                .collect();
-            let node_refs: Vec<i64> = way.refs().collect();
-            OsmElement::Way(OsmWay {
-                id: way.id(),
-                node_refs,
-                tags,
-            })
-        }
-        Element::Relation(relation) => {
-            let tags: HashMap<String, String> = relation
-                .tags()
-                .map(|(k, v)| (k.to_string(), v.to_string()))
-                .collect();
-            let members: Vec<OsmRelationMember> = relation
-                .members()
-                .map(|member| {
-                    let member_type = match member.member_type {
-                        osmpbf::RelMemberType::Node => MemberType::Node,
-                        osmpbf::RelMemberType::Way => MemberType::Way,
-                        osmpbf::RelMemberType::Relation => MemberType::Relation,
-                    };
-                    OsmRelationMember {
-                        member_type,
-                        member_id: member.member_id,
-                        role: member.role().unwrap_or("").to_string(),
-                    }
-                })
-                .collect();
-
-            OsmElement::Relation(OsmRelation {
-                id: relation.id(),
-                members,
-                tags,
-            })
-        }
-    };
-
-    if let Some(filter_tags) = tag_filter {
-        if osm_element.matches_filter(filter_tags) {
-            Some(osm_element)
-        } else {
-            None
-        }
-    } else {
-        Some(osm_element)
-    }
-}
-
-fn convert_node_to_json(node: &OsmNode, pretty_print: bool) -> Option<String> {
-    use serde_json::json;
-
-    let record = json!({
-        "id": node.id,
-        "type": "node",
-        "lat": node.lat,
-        "lon": node.lon,
-        "tags": node.tags
-    });
-
-    if pretty_print {
-        serde_json::to_string_pretty(&record).ok()
-    } else {
-        serde_json::to_string(&record).ok()
-    }
-}
-
-fn convert_way_to_json(way: &OsmWay, pretty_print: bool) -> Option<String> {
-    use serde_json::json;
-
-    let record = json!({
-        "id": way.id,
-        "type": "way",
-        "nodes": way.node_refs,
-        "tags": way.tags
-    });
-
-    if pretty_print {
-        serde_json::to_string_pretty(&record).ok()
-    } else {
-        serde_json::to_string(&record).ok()
-    }
-}
-
-fn convert_relation_to_json(relation: &OsmRelation, pretty_print: bool) -> Option<String> {
-    use serde_json::json;
-
-    let members: Vec<serde_json::Value> = relation
-        .members
-        .iter()
-        .map(|member| {
-            json!({
-                "type": match member.member_type {
-                    MemberType::Node => "node",
-                    MemberType::Way => "way",
-                    MemberType::Relation => "relation",
-                },
-                "ref": member.member_id,
-                "role": member.role
-            })
-        })
-        .collect();
-
-    let record = json!({
-        "id": relation.id,
-        "type": "relation",
-        "members": members,
-        "tags": relation.tags
-    });
-
-    if pretty_print {
-        serde_json::to_string_pretty(&record).ok()
-    } else {
-        serde_json::to_string(&record).ok()
-    }
-}
-
-fn calculate_centroid(coordinates: &[(f64, f64)]) -> (f64, f64) {
-    if coordinates.is_empty() {
-        return (0.0, 0.0);
-    }
-
-    let sum_lat: f64 = coordinates.iter().map(|(lat, _)| lat).sum();
-    let sum_lon: f64 = coordinates.iter().map(|(_, lon)| lon).sum();
-    let count = coordinates.len() as f64;
-
-    (sum_lat / count, sum_lon / count)
-}
-
-#[derive(Debug, Clone)]
-struct Bounds {
-    north: f64,
-    south: f64,
-    east: f64,
-    west: f64,
-}
-
-fn calculate_bounds(coordinates: &[(f64, f64)]) -> Bounds {
-    if coordinates.is_empty() {
-        return Bounds {
-            north: 0.0,
-            south: 0.0,
-            east: 0.0,
-            west: 0.0,
-        };
-    }
-
-    let mut north = f64::NEG_INFINITY;
-    let mut south = f64::INFINITY;
-    let mut east = f64::NEG_INFINITY;
-    let mut west = f64::INFINITY;
-
-    for &(lat, lon) in coordinates {
-        north = north.max(lat);
-        south = south.min(lat);
-        east = east.max(lon);
-        west = west.min(lon);
+                way_ids.append(&mut batch_way_ids);
+                Ok(())
+            },
+        )?;
     }
 
-    Bounds {
-        north,
-        south,
-        east,
-        west,
-    }
-}
+    storage.finalize()?; // Ensure all data is written to disk
 
-fn create_coordinate_storage(
-    temp_db_path: Option<&String>,
-    keep_temp_db: bool,
-) -> Result<CoordinateStorage> {
-    let db_path = temp_db_path.map(Path::new);
-    CoordinateStorage::new_with_cleanup(db_path, keep_temp_db)
-}
-
-fn collect_all_nodes_to_disk(input_path: &str, storage: &CoordinateStorage) -> Result<u64> {
-    let reader = ElementReader::from_path(input_path)
-        .context("Failed to open PBF file for node collection")?;
-
-    // PARALLEL NODE COLLECTION to disk
-    let all_nodes = reader.par_map_reduce(
-        |element| {
-            // Collect nodes for this thread
-            let mut batch_nodes = Vec::new();
-            match element {
-                Element::Node(node) => {
-                    batch_nodes.push((node.id(), node.lat(), node.lon()));
-                }
-                Element::DenseNode(dense_node) => {
-                    batch_nodes.push((dense_node.id(), dense_node.lat(), dense_node.lon()));
-                }
-                _ => {} // Skip ways and relations in pass 1
-            }
-            batch_nodes
-        },
-        Vec::new,
-        |mut acc, mut batch| {
-            // Combine batches from different threads
-            acc.append(&mut batch);
-            acc
-        },
-    )?;
-
-    // Store all nodes to disk in one operation
-    let node_count = all_nodes.len() as u64;
-    if !all_nodes.is_empty() {
-        storage.store_nodes(&all_nodes)?;
-    }
-
-    storage.sync()?; // Ensure all data is written to disk
-    Ok(node_count)
+    // Sorted for binary search membership tests in pass 2
+    way_ids.sort_unstable();
+    way_ids.dedup();
+    Ok((node_count, way_ids))
 }
 
 fn convert_pbf_streaming_only(
     input_path: &str,
     output_path: Option<&String>,
-    tag_filter: Option<Vec<Vec<String>>>,
-    pretty_print: bool,
+    options: &ConvertOptions,
 ) -> Result<()> {
     // SINGLE-PASS STREAMING for large files (no geometry computation)
     eprintln!("Single-pass streaming processing (basic format without full geometry)...");
-    let reader = ElementReader::from_path(input_path).context("Failed to open PBF file")?;
 
     // Streaming architecture without geometry computation
-    let (tx, rx) = mpsc::sync_channel::<String>(1000);
-    let tag_filter_clone = tag_filter.clone();
+    let (tx, rx) = mpsc::sync_channel::<Feature>(1000);
+    let types = options.element_types();
 
     // Spawn background thread for immediate output streaming
     let output_thread = {
         let output_path = output_path.cloned();
+        let options = options.clone();
         thread::spawn(move || -> Result<(), anyhow::Error> {
-            // Setup output writer in the output thread
-            let mut writer: Box<dyn Write> = match output_path.as_ref() {
-                Some(path) => {
-                    let file = File::create(path)
-                        .with_context(|| format!("Failed to create output file: {}", path))?;
-                    Box::new(BufWriter::new(file))
-                }
-                None => Box::new(std::io::stdout()),
-            };
+            // Setup output sink in the output thread
+            let mut sink = create_sink(output_path.as_deref(), &options)?;
 
             let mut feature_count = 0usize;
+            let mut pending = Vec::with_capacity(OUTPUT_BATCH_SIZE);
 
-            while let Ok(json_line) = rx.recv() {
-                writeln!(writer, "{}", json_line)?; // Stream immediately to output
+            while let Ok(feature) = rx.recv() {
+                pending.push(feature);
+                if pending.len() >= OUTPUT_BATCH_SIZE {
+                    sink.write_batch(&pending)?;
+                    pending.clear();
+                }
                 feature_count += 1;
 
                 // Memory monitoring every 100k features for large files
-                if feature_count % 100000 == 0 {
+                if feature_count.is_multiple_of(100000) {
                     eprintln!("Streamed {} features", feature_count);
                     if let Some(memory_usage) = get_memory_usage_mb() {
                         eprintln!("Current memory usage: {} MB", memory_usage);
@@ -513,7 +250,8 @@ fn convert_pbf_streaming_only(
                 }
             }
 
-            writer.flush()?;
+            sink.write_batch(&pending)?;
+            sink.finish()?;
             eprintln!(
                 "Streaming output complete. Total features: {}",
                 feature_count
@@ -522,132 +260,144 @@ fn convert_pbf_streaming_only(
         })
     };
 
-    // PARALLEL PROCESSING: Basic format without geometry computation
-    reader.par_map_reduce(
-        |element| {
-            // Parallel map: Process each element on available CPU cores
+    // PARALLEL PROCESSING: Basic format without geometry computation. Blobs
+    // are decoded in parallel and their features sent on in file order
+    let mut blob_index = BlobIndex::new();
+    let processing_result = blob_index.for_each_block_ordered(
+        input_path,
+        types,
+        |block| {
             let mut results = Vec::new();
-            if let Some(osm_element) = process_element(element, &tag_filter_clone) {
-                let json_opt = match &osm_element {
-                    OsmElement::Node(node) => {
-                        if !node.tags.is_empty() {
-                            convert_node_to_json(node, pretty_print)
-                        } else {
-                            None
-                        }
-                    }
-                    OsmElement::Way(way) => {
-                        if !way.tags.is_empty() {
-                            // Basic format without geometry for large files
-                            convert_way_to_json(way, pretty_print)
-                        } else {
-                            None
-                        }
-                    }
-                    OsmElement::Relation(relation) => {
-                        if !relation.tags.is_empty() {
-                            // Basic format without geometry for large files
-                            convert_relation_to_json(relation, pretty_print)
-                        } else {
-                            None
-                        }
-                    }
-                };
-
-                if let Some(json_str) = json_opt
-                    && !json_str.is_empty()
-                {
-                    results.push(json_str);
+            for element in block.elements() {
+                if let Some(osm_element) = process_element(element, options, types) {
+                    // Basic format without geometry for large files
+                    let feature = match osm_element {
+                        OsmElement::Node(node) => Feature::from_node(node),
+                        OsmElement::Way(way) => Feature::from_way(way),
+                        OsmElement::Relation(relation) => Feature::from_relation(relation),
+                    };
+                    results.extend(options.finish_feature(feature));
                 }
             }
-            results
+            Ok(results)
         },
-        Vec::new,
-        |mut acc, mut batch| {
-            // Reduce: Stream results immediately to output thread
-            for json_str in batch.drain(..) {
-                if tx.send(json_str).is_err() {
-                    return acc; // Output thread disconnected
-                }
-            }
-
-            // Keep memory bounded - don't accumulate
-            acc.clear();
-            acc
-        },
-    )?;
+        |results| send_features(&tx, results),
+    );
 
     // Close channel to signal completion
     drop(tx);
 
-    // Wait for output thread to finish
+    // Wait for output thread to finish; its error explains a closed channel
     output_thread
         .join()
         .map_err(|_| anyhow::anyhow!("Output thread panicked"))??;
+    processing_result?;
 
+    report_skipped_blobs(&blob_index);
     Ok(())
 }
 
 fn convert_pbf_with_complete_geometry(
     input_path: &str,
     output_path: Option<&String>,
-    tag_filter: Option<Vec<Vec<String>>>,
-    pretty_print: bool,
-    temp_db_path: Option<&String>,
-    keep_temp_db: bool,
+    options: &ConvertOptions,
+    coordinate_storage: Option<CoordinateStorage>,
 ) -> Result<()> {
+    let types = options.element_types();
+
     // THREE-PASS APPROACH with disk-based storage for complete relation geometry
     eprintln!("Pass 1: Collecting all node coordinates to disk database...");
-    let coordinate_storage = create_coordinate_storage(temp_db_path, keep_temp_db)?;
-    let node_count = collect_all_nodes_to_disk(input_path, &coordinate_storage)?;
-    eprintln!("Stored {} node coordinates in disk database", node_count);
+    let pass1_started = Instant::now();
+    let (coordinate_storage, mut node_cache) = match coordinate_storage {
+        Some(coordinate_storage) => (coordinate_storage, None),
+        None => create_coordinate_storage(input_path, options)?,
+    };
+    let store_nodes = !node_cache.as_ref().is_some_and(NodeCache::is_complete);
+    if let Some(cache) = &node_cache
+        && cache.is_complete()
+    {
+        eprintln!("Reusing node coordinates from {}", cache.path().display());
+    }
+    let mut blob_index = BlobIndex::new();
+    let (node_count, relation_way_ids) = collect_all_nodes_to_disk(
+        input_path,
+        &coordinate_storage,
+        options,
+        &mut blob_index,
+        store_nodes,
+    )?;
+    if let Some(cache) = &mut node_cache {
+        cache.mark_complete(&coordinate_storage)?;
+    }
+    if store_nodes {
+        eprintln!(
+            "Stored {} node coordinates in disk database in {:.1}s",
+            node_count,
+            pass1_started.elapsed().as_secs_f64()
+        );
+    } else {
+        eprintln!(
+            "Reused cached node coordinates, pass finished in {:.1}s",
+            pass1_started.elapsed().as_secs_f64()
+        );
+    }
 
-    eprintln!("Pass 2: Collecting all way geometries with disk storage...");
-    let all_ways = collect_all_ways_with_disk_geometry(input_path, &coordinate_storage)?;
     eprintln!(
-        "Collected {} way geometries using disk storage",
-        all_ways.len()
+        "Pass 2: Storing {} relation member ways to disk database...",
+        relation_way_ids.len()
     );
+    let way_storage = create_way_storage(options)?;
+    let way_count =
+        collect_relation_ways(&way_storage, input_path, &relation_way_ids, &mut blob_index)?;
+    drop(relation_way_ids);
+    eprintln!("Stored {} relation member ways in disk database", way_count);
 
     eprintln!("Pass 3: Processing all elements with complete geometry...");
-    let reader = ElementReader::from_path(input_path).context("Failed to open PBF file")?;
 
     // Streaming architecture with complete geometry computation
-    let (tx, rx) = mpsc::sync_channel::<String>(1000);
-    let tag_filter_clone = tag_filter.clone();
-    let coordinate_storage = Arc::new(coordinate_storage);
-    let all_ways_clone = all_ways;
+    let (tx, rx) = mpsc::sync_channel::<Feature>(1000);
 
     // Spawn background thread for immediate output streaming
     let output_thread = {
         let output_path = output_path.cloned();
+        let options = options.clone();
         thread::spawn(move || -> Result<(), anyhow::Error> {
-            // Setup output writer in the output thread
-            let mut writer: Box<dyn Write> = match output_path.as_ref() {
-                Some(path) => {
-                    let file = File::create(path)
-                        .with_context(|| format!("Failed to create output file: {}", path))?;
-                    Box::new(BufWriter::new(file))
-                }
-                None => Box::new(std::io::stdout()),
-            };
+            // Setup output sink in the output thread
+            let mut sink = create_sink(output_path.as_deref(), &options)?;
 
             let mut feature_count = 0usize;
+            let mut pending = Vec::with_capacity(OUTPUT_BATCH_SIZE);
 
-            while let Ok(json_line) = rx.recv() {
-                writeln!(writer, "{}", json_line)?; // Stream immediately to output
+            while let Ok(feature) = rx.recv() {
+                pending.push(feature);
+                if pending.len() >= OUTPUT_BATCH_SIZE {
+                    sink.write_batch(&pending)?;
+                    pending.clear();
+                }
                 feature_count += 1;
 
                 // Memory monitoring every 10k features
-                if feature_count % 10000 == 0 {
+                if feature_count.is_multiple_of(10000) {
                     eprintln!("Streamed {} features", feature_count);
                     if let Some(memory_usage) = get_memory_usage_mb() {
                         eprintln!("Current memory usage: {} MB", memory_usage);
                     }
                 }
+
+                // Memory warning
+                if feature_count.is_multiple_of(50000)
+                    && let Some(memory_usage) = get_memory_usage_mb()
+                    && memory_usage > MEMORY_LIMIT_GB * 1024
+                {
+                    eprintln!(
+                        "⚠️  Memory usage ({} MB) exceeds limit ({} GB)",
+                        memory_usage, MEMORY_LIMIT_GB
+                    );
+                }
             }
 
-            writer.flush()?;
+            sink.write_batch(&pending)?;
+            sink.finish()?;
             eprintln!(
                 "Streaming output complete. Total features: {}",
                 feature_count
@@ -656,355 +406,58 @@ fn convert_pbf_with_complete_geometry(
         })
     };
 
-    // PARALLEL PROCESSING: Complete geometry computation
-    let coordinate_storage_for_processing = coordinate_storage.clone();
-    reader.par_map_reduce(
-        move |element| {
-            // Parallel map: Process each element on available CPU cores
+    // PARALLEL PROCESSING: Complete geometry computation. The blob index from
+    // passes 1 and 2 lets blobs without wanted types be skipped undecoded
+    let processing_result = blob_index.for_each_block_ordered(
+        input_path,
+        types,
+        |block| {
             let mut results = Vec::new();
-            if let Some(osm_element) = process_element(element, &tag_filter_clone) {
-                let json_opt = match &osm_element {
-                    OsmElement::Node(node) => {
-                        if !node.tags.is_empty() {
-                            convert_node_to_json(node, pretty_print)
-                        } else {
-                            None
-                        }
-                    }
-                    OsmElement::Way(way) => {
-                        if !way.tags.is_empty() {
-                            convert_way_to_json_with_disk_geometry(
-                                way,
-                                &coordinate_storage_for_processing,
-                                pretty_print,
-                            )
-                        } else {
-                            None
+            for element in block.elements() {
+                if let Some(osm_element) = process_element(element, options, types) {
+                    let feature = match osm_element {
+                        OsmElement::Node(node) => Feature::from_node(node),
+                        OsmElement::Way(way) => way_feature(way, &coordinate_storage),
+                        OsmElement::Relation(relation) => {
+                            relation_feature(relation, &way_storage, &coordinate_storage)
                         }
-                    }
-                    OsmElement::Relation(relation) => {
-                        if !relation.tags.is_empty() {
-                            convert_relation_to_json_with_way_resolution(
-                                relation,
-                                &all_ways_clone,
-                                pretty_print,
-                            )
-                        } else {
-                            None
-                        }
-                    }
-                };
-
-                if let Some(json_str) = json_opt
-                    && !json_str.is_empty()
-                {
-                    results.push(json_str);
-                }
-            }
-            results
-        },
-        Vec::new,
-        |mut acc, mut batch| {
-            // Reduce: Stream results immediately to output thread
-            for json_str in batch.drain(..) {
-                if tx.send(json_str).is_err() {
-                    return acc; // Output thread disconnected
+                    };
+                    results.extend(options.finish_feature(feature));
                 }
             }
-
-            // Keep memory bounded - don't accumulate
-            acc.clear();
-            acc
+            Ok(results)
         },
-    )?;
+        |results| send_features(&tx, results),
+    );
 
     // Close channel to signal completion
     drop(tx);
 
-    // Wait for output thread to finish
+    // Wait for output thread to finish; its error explains a closed channel
     output_thread
         .join()
         .map_err(|_| anyhow::anyhow!("Output thread panicked"))??;
+    processing_result?;
 
+    report_skipped_blobs(&blob_index);
     Ok(())
 }
 
-#[derive(Debug, Clone)]
-struct WayGeometry {
-    #[allow(dead_code)]
-    id: i64,
-    coordinates: Vec<(f64, f64)>,
-    #[allow(dead_code)]
-    centroid: (f64, f64),
-    #[allow(dead_code)]
-    bounds: Bounds,
-}
-
-fn collect_all_ways_with_disk_geometry(
-    input_path: &str,
-    storage: &CoordinateStorage,
-) -> Result<HashMap<i64, WayGeometry>> {
-    let reader = ElementReader::from_path(input_path)
-        .context("Failed to open PBF file for way collection")?;
-
-    let ways = reader.par_map_reduce(
-        |element| {
-            let mut local_ways = HashMap::new();
-            if let Element::Way(way) = element {
-                let node_refs: Vec<i64> = way.refs().collect();
-
-                // Get coordinates from disk storage
-                match storage.get_nodes(&node_refs) {
-                    Ok(coords) => {
-                        let coordinates: Vec<(f64, f64)> = coords.into_iter().flatten().collect();
-
-                        if !coordinates.is_empty() {
-                            let centroid = calculate_centroid(&coordinates);
-                            let bounds = calculate_bounds(&coordinates);
-                            let way_geometry = WayGeometry {
-                                id: way.id(),
-                                coordinates,
-                                centroid,
-                                bounds,
-                            };
-                            local_ways.insert(way.id(), way_geometry);
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!(
-                            "Warning: Failed to get coordinates for way {}: {}",
-                            way.id(),
-                            e
-                        );
-                    }
-                }
-            }
-            local_ways
-        },
-        HashMap::new,
-        |mut acc, batch| {
-            acc.extend(batch);
-            acc
-        },
-    )?;
-
-    Ok(ways)
-}
-
-fn convert_relation_to_json_with_way_resolution(
-    relation: &OsmRelation,
-    all_ways: &HashMap<i64, WayGeometry>,
-    pretty_print: bool,
-) -> Option<String> {
-    use serde_json::json;
-
-    // Collect coordinates from all member ways
-    let mut all_coordinates = Vec::new();
-    for member in &relation.members {
-        if member.member_type == MemberType::Way
-            && let Some(way_geometry) = all_ways.get(&member.member_id)
-        {
-            all_coordinates.extend(way_geometry.coordinates.iter().cloned());
-        }
-    }
-
-    let mut record = json!({
-        "id": relation.id,
-        "type": "relation",
-        "tags": relation.tags
-    });
-
-    if !all_coordinates.is_empty() {
-        let (centroid_lat, centroid_lon) = calculate_centroid(&all_coordinates);
-        let bounds = calculate_bounds(&all_coordinates);
-
-        record.as_object_mut().unwrap().insert(
-            "centroid".to_string(),
-            json!({
-                "lat": format!("{:.7}", centroid_lat),
-                "lon": format!("{:.7}", centroid_lon),
-                "type": "entrance"  // Match GoLang pbf2json format
-            }),
-        );
-
-        record.as_object_mut().unwrap().insert(
-            "bounds".to_string(),
-            json!({
-                "n": format!("{:.7}", bounds.north),
-                "s": format!("{:.7}", bounds.south),
-                "e": format!("{:.7}", bounds.east),
-                "w": format!("{:.7}", bounds.west)
-            }),
-        );
-    } else {
-        // Fall back to including members if no geometry available
-        let members_json: Vec<serde_json::Value> = relation
-            .members
-            .iter()
-            .map(|member| {
-                json!({
-                    "type": match member.member_type {
-                        MemberType::Node => "node",
-                        MemberType::Way => "way",
-                        MemberType::Relation => "relation",
-                    },
-                    "ref": member.member_id,
-                    "role": member.role
-                })
-            })
-            .collect();
-
-        record
-            .as_object_mut()
-            .unwrap()
-            .insert("members".to_string(), json!(members_json));
-    }
-
-    if pretty_print {
-        serde_json::to_string_pretty(&record).ok()
-    } else {
-        serde_json::to_string(&record).ok()
-    }
-}
-
-fn convert_way_to_json_with_disk_geometry(
-    way: &OsmWay,
-    storage: &Arc<CoordinateStorage>,
-    pretty_print: bool,
-) -> Option<String> {
-    use serde_json::json;
-
-    // Get coordinates from disk storage
-    let coordinates: Vec<(f64, f64)> = match storage.get_nodes(&way.node_refs) {
-        Ok(coords) => coords.into_iter().flatten().collect(),
-        Err(e) => {
-            eprintln!(
-                "Warning: Failed to get coordinates for way {}: {}",
-                way.id, e
-            );
-            return convert_way_to_json(way, pretty_print);
-        }
-    };
-
-    if coordinates.is_empty() {
-        return convert_way_to_json(way, pretty_print);
-    }
-
-    let (centroid_lat, centroid_lon) = calculate_centroid(&coordinates);
-    let bounds = calculate_bounds(&coordinates);
-
-    let record = json!({
-        "id": way.id,
-        "type": "way",
-        "nodes": way.node_refs,
-        "tags": way.tags,
-        "centroid": {
-            "lat": format!("{:.7}", centroid_lat),
-            "lon": format!("{:.7}", centroid_lon),
-            "type": "centroid"
-        },
-        "bounds": {
-            "n": format!("{:.7}", bounds.north),
-            "s": format!("{:.7}", bounds.south),
-            "e": format!("{:.7}", bounds.east),
-            "w": format!("{:.7}", bounds.west)
-        }
-    });
-
-    if pretty_print {
-        serde_json::to_string_pretty(&record).ok()
-    } else {
-        serde_json::to_string(&record).ok()
+/// Send a block's features to the output thread
+fn send_features(tx: &mpsc::SyncSender<Feature>, features: Vec<Feature>) -> Result<()> {
+    for feature in features {
+        tx.send(feature)
+            .map_err(|_| anyhow::anyhow!("Output channel closed"))?;
     }
+    Ok(())
 }
 
-fn convert_relation_to_json_with_disk_geometry(
-    relation: &OsmRelation,
-    storage: &Arc<CoordinateStorage>,
-    pretty_print: bool,
-) -> Option<String> {
-    use serde_json::json;
-
-    // For relations, collect coordinates from any node members
-    let mut node_ids = Vec::new();
-    for member in &relation.members {
-        if member.member_type == MemberType::Node {
-            node_ids.push(member.member_id);
-        }
-    }
-
-    let mut all_coordinates = Vec::new();
-    if !node_ids.is_empty() {
-        match storage.get_nodes(&node_ids) {
-            Ok(coords) => {
-                all_coordinates.extend(coords.into_iter().flatten());
-            }
-            Err(e) => {
-                eprintln!(
-                    "Warning: Failed to get coordinates for relation {}: {}",
-                    relation.id, e
-                );
-            }
-        }
-    }
-
-    let mut record = json!({
-        "id": relation.id,
-        "type": "relation",
-        "tags": relation.tags
-    });
-
-    // If we have coordinates, compute centroid and bounds
-    if !all_coordinates.is_empty() {
-        let (centroid_lat, centroid_lon) = calculate_centroid(&all_coordinates);
-        let bounds = calculate_bounds(&all_coordinates);
-
-        record.as_object_mut().unwrap().insert(
-            "centroid".to_string(),
-            json!({
-                "lat": format!("{:.7}", centroid_lat),
-                "lon": format!("{:.7}", centroid_lon),
-                "type": "entrance"  // Match GoLang pbf2json format
-            }),
+fn report_skipped_blobs(blob_index: &BlobIndex) {
+    if blob_index.skipped > 0 {
+        eprintln!(
+            "Skipped {} blobs without wanted element types",
+            blob_index.skipped
         );
-
-        record.as_object_mut().unwrap().insert(
-            "bounds".to_string(),
-            json!({
-                "n": format!("{:.7}", bounds.north),
-                "s": format!("{:.7}", bounds.south),
-                "e": format!("{:.7}", bounds.east),
-                "w": format!("{:.7}", bounds.west)
-            }),
-        );
-    } else {
-        // Fall back to including members if no geometry available
-        let members_json: Vec<serde_json::Value> = relation
-            .members
-            .iter()
-            .map(|member| {
-                json!({
-                    "type": match member.member_type {
-                        MemberType::Node => "node",
-                        MemberType::Way => "way",
-                        MemberType::Relation => "relation",
-                    },
-                    "ref": member.member_id,
-                    "role": member.role
-                })
-            })
-            .collect();
-
-        record
-            .as_object_mut()
-            .unwrap()
-            .insert("members".to_string(), json!(members_json));
-    }
-
-    if pretty_print {
-        serde_json::to_string_pretty(&record).ok()
-    } else {
-        serde_json::to_string(&record).ok()
     }
 }
 
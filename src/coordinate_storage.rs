@@ -1,14 +1,19 @@
+use crate::db_file::DbFile;
+use crate::node_cache::NodeCache;
+use crate::node_store::{
+    DenseNodeArray, HashMapNodeStore, LmdbNodeStore, NodeLocationStore, NodeStore, SparseNodeArray,
+};
+use crate::options::ConvertOptions;
 use anyhow::Result;
-use lmdb::{Database, Environment, Transaction, WriteFlags};
-use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 
-/// Disk-based coordinate storage using LMDB for memory-efficient geometry computation
+/// Node coordinate storage for geometry computation: a `NodeLocationStore`
+/// (LMDB on disk by default) plus cleanup of the temp directory it lives in.
+/// The store is a trait object since `--node-store` picks it at run time and
+/// calls are made per block or way, not per node.
 pub struct CoordinateStorage {
-    env: Environment,
-    db: Database,
-    temp_path: Option<PathBuf>, // Track if we created a temp directory for cleanup
-    keep_temp_db: bool,         // Whether to keep the temp database on drop
+    store: Box<dyn NodeLocationStore>,
+    _db_file: Option<DbFile>, // Dropped after the store, removing its temp directory
 }
 
 impl CoordinateStorage {
@@ -20,37 +25,41 @@ impl CoordinateStorage {
 
     /// Create coordinate storage with specified cleanup behavior
     pub fn new_with_cleanup(db_path: Option<&Path>, keep_temp_db: bool) -> Result<Self> {
-        let (path, temp_path) = match db_path {
-            Some(path) => (path.to_path_buf(), None),
-            None => {
-                let temp_dir = tempfile::tempdir()?;
-                let path = temp_dir.path().join("coordinates");
-                (path, Some(temp_dir.path().to_path_buf()))
-            }
-        };
+        Self::with_store(NodeStore::Lmdb, db_path, keep_temp_db)
+    }
 
-        // Create directory if it doesn't exist
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
+    /// Create coordinate storage with a built-in store. `db_path` is the LMDB
+    /// or dense array file; the in-memory stores don't use it.
+    pub fn with_store(
+        store: NodeStore,
+        db_path: Option<&Path>,
+        keep_temp_db: bool,
+    ) -> Result<Self> {
+        match store {
+            NodeStore::SparseMem => return Ok(Self::from_store(Box::new(SparseNodeArray::new()))),
+            NodeStore::HashMem => return Ok(Self::from_store(Box::new(HashMapNodeStore::new()))),
+            _ => {}
         }
 
-        // Configure LMDB environment for high performance
-        let env = Environment::new()
-            .set_flags(lmdb::EnvironmentFlags::NO_SUB_DIR) // Use single file, not directory
-            .set_max_readers(126) // Support multiple readers
-            .set_map_size(500 * 1024 * 1024 * 1024) // 500GB max map size for planet files
-            .open(&path)?;
-
-        let db = env.open_db(None)?;
-
+        let db_file = DbFile::new(db_path, "coordinates", keep_temp_db, "coordinate")?;
+        let store: Box<dyn NodeLocationStore> = match store {
+            NodeStore::Dense => Box::new(DenseNodeArray::create(db_file.path())?),
+            _ => Box::new(LmdbNodeStore::open(db_file.path())?),
+        };
         Ok(CoordinateStorage {
-            env,
-            db,
-            temp_path,
-            keep_temp_db,
+            store,
+            _db_file: Some(db_file),
         })
     }
 
+    /// Wrap any node location store, such as one of your own
+    pub fn from_store(store: Box<dyn NodeLocationStore>) -> Self {
+        CoordinateStorage {
+            store,
+            _db_file: None,
+        }
+    }
+
     /// Create coordinate storage in default temp location
     #[allow(dead_code)]
     pub fn new_temp() -> Result<Self> {
@@ -60,74 +69,34 @@ impl CoordinateStorage {
     /// Store coordinates for a node ID
     #[allow(dead_code)]
     pub fn store_node(&self, node_id: i64, lat: f64, lon: f64) -> Result<()> {
-        let mut txn = self.env.begin_rw_txn()?;
-        let key = node_id.to_be_bytes();
-        let value = [lat.to_be_bytes(), lon.to_be_bytes()].concat();
-        txn.put(self.db, &key, &value, WriteFlags::empty())?;
-        txn.commit()?;
-        Ok(())
+        self.store_nodes(&[(node_id, lat, lon)])
     }
 
     /// Store multiple coordinates efficiently in a single transaction
     pub fn store_nodes(&self, nodes: &[(i64, f64, f64)]) -> Result<()> {
-        let mut txn = self.env.begin_rw_txn()?;
-        for &(node_id, lat, lon) in nodes {
-            let key = node_id.to_be_bytes();
-            let value = [lat.to_be_bytes(), lon.to_be_bytes()].concat();
-            txn.put(self.db, &key, &value, WriteFlags::empty())?;
-        }
-        txn.commit()?;
-        Ok(())
+        self.store.put_nodes(nodes)
     }
 
     /// Retrieve coordinates for a node ID
     #[allow(dead_code)]
     pub fn get_node(&self, node_id: i64) -> Result<Option<(f64, f64)>> {
-        let txn = self.env.begin_ro_txn()?;
-        let key = node_id.to_be_bytes();
-
-        match txn.get(self.db, &key) {
-            Ok(value) if value.len() == 16 => {
-                let lat_bytes: [u8; 8] = value[0..8].try_into().unwrap();
-                let lon_bytes: [u8; 8] = value[8..16].try_into().unwrap();
-                let lat = f64::from_be_bytes(lat_bytes);
-                let lon = f64::from_be_bytes(lon_bytes);
-                Ok(Some((lat, lon)))
-            }
-            Ok(_) => Ok(None), // Invalid data
-            Err(lmdb::Error::NotFound) => Ok(None),
-            Err(e) => Err(e.into()),
-        }
+        Ok(self.store.get_nodes(&[node_id])?[0])
     }
 
     /// Retrieve coordinates for multiple node IDs efficiently in a single transaction
     pub fn get_nodes(&self, node_ids: &[i64]) -> Result<Vec<Option<(f64, f64)>>> {
-        let txn = self.env.begin_ro_txn()?;
-        let mut result = Vec::with_capacity(node_ids.len());
-
-        for &node_id in node_ids {
-            let key = node_id.to_be_bytes();
-            match txn.get(self.db, &key) {
-                Ok(value) if value.len() == 16 => {
-                    let lat_bytes: [u8; 8] = value[0..8].try_into().unwrap();
-                    let lon_bytes: [u8; 8] = value[8..16].try_into().unwrap();
-                    let lat = f64::from_be_bytes(lat_bytes);
-                    let lon = f64::from_be_bytes(lon_bytes);
-                    result.push(Some((lat, lon)));
-                }
-                Ok(_) => result.push(None), // Invalid data
-                Err(lmdb::Error::NotFound) => result.push(None),
-                Err(e) => return Err(e.into()),
-            }
-        }
-
-        Ok(result)
+        self.store.get_nodes(node_ids)
     }
 
     /// Sync all pending writes to disk
     pub fn sync(&self) -> Result<()> {
-        self.env.sync(true)?;
-        Ok(())
+        self.store.sync()
+    }
+
+    /// Sync and prepare the store for lookups once all nodes are stored
+    pub fn finalize(&self) -> Result<()> {
+        self.store.sync()?;
+        self.store.finalize()
     }
 }
 
@@ -135,30 +104,22 @@ impl Drop for CoordinateStorage {
     fn drop(&mut self) {
         // Ensure all data is synced before cleanup
         let _ = self.sync();
+    }
+}
 
-        // Clean up temporary directory only if we created one AND keep_temp_db is false
-        if let Some(temp_path) = &self.temp_path {
-            if !self.keep_temp_db {
-                if let Err(e) = fs::remove_dir_all(temp_path) {
-                    eprintln!(
-                        "Warning: Failed to remove temp database {}: {}",
-                        temp_path.display(),
-                        e
-                    );
-                } else {
-                    eprintln!(
-                        "✅ Temporary coordinate database deleted: {}",
-                        temp_path.display()
-                    );
-                }
-            } else {
-                eprintln!(
-                    "📁 Temporary coordinate database preserved: {}",
-                    temp_path.display()
-                );
-            }
-        }
+/// Coordinate storage for a conversion: the `--node-cache` file when given,
+/// otherwise a new store of the `options.node_store` kind
+pub(crate) fn create_coordinate_storage(
+    input_path: &str,
+    options: &ConvertOptions,
+) -> Result<(CoordinateStorage, Option<NodeCache>)> {
+    if let Some(cache_path) = &options.node_cache {
+        let cache = NodeCache::open(Path::new(cache_path), input_path, options.node_store)?;
+        return Ok((cache.storage()?, Some(cache)));
     }
+    let db_path = options.temp_db_path.as_deref().map(Path::new);
+    let storage = CoordinateStorage::with_store(options.node_store, db_path, options.keep_temp_db)?;
+    Ok((storage, None))
 }
 
 #[cfg(test)]
@@ -0,0 +1,139 @@
+// CSV/TSV output with user-selected columns
+use crate::feature::Feature;
+use crate::output::FeatureSink;
+use anyhow::Result;
+use rayon::prelude::*;
+use std::io::Write;
+
+/// Columns used when `--columns` is not given
+pub const DEFAULT_COLUMNS: [&str; 5] = ["id", "type", "lat", "lon", "name"];
+
+/// A CSV column: one of the built-in element fields, or a tag value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    Id,
+    Type,
+    Lat,
+    Lon,
+    Tag(String),
+}
+
+impl Column {
+    /// `id`, `type`, `lat` and `lon` are element fields, anything else is a tag key
+    pub fn parse(name: &str) -> Column {
+        match name {
+            "id" => Column::Id,
+            "type" => Column::Type,
+            "lat" => Column::Lat,
+            "lon" => Column::Lon,
+            tag => Column::Tag(tag.to_string()),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Column::Id => "id",
+            Column::Type => "type",
+            Column::Lat => "lat",
+            Column::Lon => "lon",
+            Column::Tag(key) => key,
+        }
+    }
+
+    /// Field value for a feature; lat/lon come from the node location or centroid
+    fn value(&self, feature: &Feature) -> String {
+        match self {
+            Column::Id => feature.id.to_string(),
+            Column::Type => feature.element_type.as_str().to_string(),
+            Column::Lat => feature
+                .point()
+                .map(|(lat, _)| format!("{:.7}", lat))
+                .unwrap_or_default(),
+            Column::Lon => feature
+                .point()
+                .map(|(_, lon)| format!("{:.7}", lon))
+                .unwrap_or_default(),
+            Column::Tag(key) => feature.tags.get(key).cloned().unwrap_or_default(),
+        }
+    }
+}
+
+/// Parse a comma-separated `--columns` list
+pub fn parse_columns(spec: &str) -> Vec<Column> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(Column::parse)
+        .collect()
+}
+
+/// Quote a field per RFC 4180 when it contains the delimiter, a quote or a line break
+pub fn quote_field(field: &str, delimiter: u8) -> std::borrow::Cow<'_, str> {
+    let needs_quotes = field
+        .bytes()
+        .any(|b| b == delimiter || b == b'"' || b == b'\n' || b == b'\r');
+
+    if needs_quotes {
+        format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+        field.into()
+    }
+}
+
+/// Delimited text sink writing a header row followed by one row per feature.
+///
+/// Rows end with `\n`; fields holding line breaks are quoted, so multi-line
+/// tag values stay within one record.
+pub struct DelimitedSink {
+    writer: Box<dyn Write>,
+    columns: Vec<Column>,
+    delimiter: u8,
+}
+
+impl DelimitedSink {
+    pub fn new(mut writer: Box<dyn Write>, columns: Vec<Column>, delimiter: u8) -> Result<Self> {
+        let header: Vec<&str> = columns.iter().map(Column::name).collect();
+        writer.write_all(encode_row(header.into_iter(), delimiter).as_bytes())?;
+
+        Ok(DelimitedSink {
+            writer,
+            columns,
+            delimiter,
+        })
+    }
+}
+
+fn encode_row<'a>(fields: impl Iterator<Item = &'a str>, delimiter: u8) -> String {
+    let mut row = String::new();
+    for (index, field) in fields.enumerate() {
+        if index > 0 {
+            row.push(delimiter as char);
+        }
+        row.push_str(&quote_field(field, delimiter));
+    }
+    row.push('\n');
+    row
+}
+
+impl FeatureSink for DelimitedSink {
+    fn write_batch(&mut self, features: &[Feature]) -> Result<()> {
+        let (columns, delimiter) = (&self.columns, self.delimiter);
+        let rows: Vec<String> = features
+            .par_iter()
+            .map(|feature| {
+                let values: Vec<String> = columns.iter().map(|c| c.value(feature)).collect();
+                encode_row(values.iter().map(String::as_str), delimiter)
+            })
+            .collect();
+
+        for row in rows {
+            self.writer.write_all(row.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+// Database files for the node and way stores: temp directory handling and LMDB setup
+use anyhow::Result;
+use lmdb::Environment;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Path of a store's database file: a user-supplied path, or a file in a new
+/// temp directory that is removed on drop unless `keep_temp_db` is set
+pub struct DbFile {
+    path: PathBuf,
+    temp_path: Option<PathBuf>, // Track if we created a temp directory for cleanup
+    keep_temp_db: bool,         // Whether to keep the temp database on drop
+    label: &'static str,        // "coordinate" or "way", for messages
+}
+
+impl DbFile {
+    /// `file_name` names the file in the temp directory when `db_path` is None
+    pub fn new(
+        db_path: Option<&Path>,
+        file_name: &str,
+        keep_temp_db: bool,
+        label: &'static str,
+    ) -> Result<Self> {
+        let (path, temp_path) = match db_path {
+            Some(path) => (path.to_path_buf(), None),
+            None => {
+                let temp_dir = tempfile::tempdir()?.keep();
+                (temp_dir.join(file_name), Some(temp_dir))
+            }
+        };
+
+        // Create directory if it doesn't exist
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        Ok(DbFile {
+            path,
+            temp_path,
+            keep_temp_db,
+            label,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for DbFile {
+    fn drop(&mut self) {
+        // Clean up temporary directory only if we created one AND keep_temp_db is false
+        let Some(temp_path) = &self.temp_path else {
+            return;
+        };
+        if self.keep_temp_db {
+            eprintln!(
+                "📁 Temporary {} database preserved: {}",
+                self.label,
+                temp_path.display()
+            );
+        } else if let Err(e) = fs::remove_dir_all(temp_path) {
+            eprintln!(
+                "Warning: Failed to remove temp {} database {}: {}",
+                self.label,
+                temp_path.display(),
+                e
+            );
+        } else {
+            eprintln!(
+                "✅ Temporary {} database deleted: {}",
+                self.label,
+                temp_path.display()
+            );
+        }
+    }
+}
+
+/// Open an LMDB environment in a single file at `path`
+pub fn open_lmdb_env(path: &Path, map_size: usize, max_dbs: u32) -> Result<Environment> {
+    // Configure LMDB environment for high performance
+    let env = Environment::new()
+        .set_flags(lmdb::EnvironmentFlags::NO_SUB_DIR) // Use single file, not directory
+        .set_max_readers(126) // Support multiple readers
+        .set_max_dbs(max_dbs)
+        .set_map_size(map_size)
+        .open(path)?;
+    Ok(env)
+}
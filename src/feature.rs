@@ -0,0 +1,339 @@
+// Structured output records shared by all output formats
+use crate::coordinate_storage::CoordinateStorage;
+use crate::geometry::{Bounds, calculate_bounds, calculate_centroid, way_geometry};
+use crate::multipolygon::{MemberWay, assemble_multipolygon, is_multipolygon_relation};
+use crate::osm::{MemberType, OsmNode, OsmRelation, OsmRelationMember, OsmWay};
+use crate::way_storage::{ResolvedWay, WayStorage, resolve_relation_ways};
+use geojson::{Geometry, JsonObject, Value};
+use serde_json::json;
+use std::collections::HashMap;
+
+/// OSM element type of a feature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementType {
+    Node,
+    Way,
+    Relation,
+}
+
+impl ElementType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ElementType::Node => "node",
+            ElementType::Way => "way",
+            ElementType::Relation => "relation",
+        }
+    }
+}
+
+/// Computed centroid of a way or relation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Centroid {
+    pub lat: f64,
+    pub lon: f64,
+    pub kind: &'static str, // "centroid" for ways, "entrance" for relations (pbf2json format)
+}
+
+/// A converted OSM element, ready to be encoded by an output sink
+#[derive(Debug, Clone)]
+pub struct Feature {
+    pub id: i64,
+    pub element_type: ElementType,
+    pub tags: HashMap<String, String>,
+    pub location: Option<(f64, f64)>, // Node (lat, lon)
+    pub node_refs: Vec<i64>,          // Way node refs
+    pub way_nodes: Vec<(f64, f64)>,   // Denormalized way node (lat, lon), Go compatible output
+    pub members: Vec<OsmRelationMember>,
+    pub centroid: Option<Centroid>,
+    pub bounds: Option<Bounds>,
+    pub geometry: Option<Geometry>,
+    pub group: Option<usize>, // Filter group that matched, recorded before tags are projected
+}
+
+impl Feature {
+    fn new(id: i64, element_type: ElementType, tags: HashMap<String, String>) -> Self {
+        Feature {
+            id,
+            element_type,
+            tags,
+            location: None,
+            node_refs: Vec::new(),
+            way_nodes: Vec::new(),
+            members: Vec::new(),
+            centroid: None,
+            bounds: None,
+            geometry: None,
+            group: None,
+        }
+    }
+
+    pub fn from_node(node: OsmNode) -> Self {
+        let mut feature = Feature::new(node.id, ElementType::Node, node.tags);
+        feature.location = Some((node.lat, node.lon));
+        feature
+    }
+
+    /// Way without geometry (basic format)
+    pub fn from_way(way: OsmWay) -> Self {
+        let mut feature = Feature::new(way.id, ElementType::Way, way.tags);
+        feature.node_refs = way.node_refs;
+        feature
+    }
+
+    /// Relation without geometry (basic format)
+    pub fn from_relation(relation: OsmRelation) -> Self {
+        let mut feature = Feature::new(relation.id, ElementType::Relation, relation.tags);
+        feature.members = relation.members;
+        feature
+    }
+
+    /// Way with centroid, bounds and shape from its resolved `(lat, lon)` coordinates
+    pub fn from_way_with_coordinates(way: OsmWay, coordinates: &[(f64, f64)]) -> Self {
+        if coordinates.is_empty() {
+            return Feature::from_way(way);
+        }
+
+        let (lat, lon) = calculate_centroid(coordinates);
+        let geometry = way_geometry(&way, coordinates);
+
+        let mut feature = Feature::from_way(way);
+        feature.centroid = Some(Centroid {
+            lat,
+            lon,
+            kind: "centroid",
+        });
+        feature.bounds = Some(calculate_bounds(coordinates));
+        feature.geometry = geometry;
+        feature
+    }
+
+    /// Relation with centroid and bounds from its resolved member ways and
+    /// member node coordinates. Multipolygon relations also get their assembled
+    /// MultiPolygon geometry.
+    pub fn from_relation_with_members(
+        relation: OsmRelation,
+        member_ways: &[ResolvedWay],
+        node_coordinates: &[(f64, f64)],
+    ) -> Self {
+        let all_coordinates: Vec<(f64, f64)> = member_ways
+            .iter()
+            .flat_map(|way| way.coordinates.iter().copied())
+            .chain(node_coordinates.iter().copied())
+            .collect();
+
+        if all_coordinates.is_empty() {
+            // Fall back to including members if no geometry available
+            return Feature::from_relation(relation);
+        }
+
+        let mut geometry = None;
+        if is_multipolygon_relation(&relation) {
+            let members: Vec<MemberWay> = member_ways
+                .iter()
+                .map(|way| MemberWay {
+                    way_id: way.way_id,
+                    role: &way.role,
+                    node_ids: &way.node_ids,
+                    coordinates: &way.coordinates,
+                })
+                .collect();
+
+            match assemble_multipolygon(&members) {
+                Ok(multipolygon) => geometry = Some(multipolygon),
+                Err(e) => {
+                    eprintln!(
+                        "Warning: Failed to assemble multipolygon for relation {}: {}",
+                        relation.id, e
+                    );
+                }
+            }
+        }
+
+        let (lat, lon) = calculate_centroid(&all_coordinates);
+        let mut feature = Feature::from_relation(relation);
+        feature.centroid = Some(Centroid {
+            lat,
+            lon,
+            kind: "entrance", // Match GoLang pbf2json format
+        });
+        feature.bounds = Some(calculate_bounds(&all_coordinates));
+        feature.geometry = geometry;
+        feature
+    }
+
+    /// Point used for the feature's position: node location, otherwise centroid
+    pub fn point(&self) -> Option<(f64, f64)> {
+        self.location
+            .or_else(|| self.centroid.map(|centroid| (centroid.lat, centroid.lon)))
+    }
+
+    /// `[xmin, ymin, xmax, ymax]` extent: the bounds of ways and relations,
+    /// or a degenerate box at the node location
+    pub fn bbox(&self) -> Option<[f64; 4]> {
+        self.bounds
+            .map(|b| [b.west, b.south, b.east, b.north])
+            .or_else(|| self.point().map(|(lat, lon)| [lon, lat, lon, lat]))
+    }
+
+    /// Record in the original pbf2json JSON Lines layout
+    pub fn to_jsonl_value(&self) -> serde_json::Value {
+        let mut record = json!({
+            "id": self.id,
+            "type": self.element_type.as_str(),
+            "tags": self.tags
+        });
+        let object = record.as_object_mut().unwrap();
+
+        if let Some((lat, lon)) = self.location {
+            object.insert("lat".to_string(), json!(lat));
+            object.insert("lon".to_string(), json!(lon));
+        }
+
+        if self.element_type == ElementType::Way {
+            object.insert("nodes".to_string(), json!(self.node_refs));
+        }
+
+        // Relations list their members only when no geometry could be computed
+        if self.element_type == ElementType::Relation && self.centroid.is_none() {
+            object.insert("members".to_string(), members_to_json(&self.members));
+        }
+
+        if let Some(centroid) = &self.centroid {
+            object.insert(
+                "centroid".to_string(),
+                json!({
+                    "lat": format!("{:.7}", centroid.lat),
+                    "lon": format!("{:.7}", centroid.lon),
+                    "type": centroid.kind
+                }),
+            );
+        }
+
+        if let Some(bounds) = &self.bounds {
+            object.insert(
+                "bounds".to_string(),
+                json!({
+                    "n": format!("{:.7}", bounds.north),
+                    "s": format!("{:.7}", bounds.south),
+                    "e": format!("{:.7}", bounds.east),
+                    "w": format!("{:.7}", bounds.west)
+                }),
+            );
+        }
+
+        if let Some(geometry) = &self.geometry {
+            object.insert("geometry".to_string(), json!(geometry));
+        }
+
+        record
+    }
+
+    /// Geometry for geometry-typed outputs: the way or multipolygon shape when
+    /// available, otherwise the node location or centroid as a Point
+    pub fn output_geometry(&self) -> Option<Geometry> {
+        self.geometry.clone().or_else(|| {
+            self.point()
+                .map(|(lat, lon)| Geometry::new(Value::Point(vec![lon, lat])))
+        })
+    }
+
+    /// GeoJSON Feature with `"node/123"` style id and tags as properties.
+    /// The geometry is `null` when nothing could be resolved.
+    pub fn to_geojson_feature(&self) -> geojson::Feature {
+        let geometry = self.output_geometry();
+
+        let properties: JsonObject = self
+            .tags
+            .iter()
+            .map(|(key, value)| (key.clone(), json!(value)))
+            .collect();
+
+        geojson::Feature {
+            bbox: self
+                .bounds
+                .map(|bounds| vec![bounds.west, bounds.south, bounds.east, bounds.north]),
+            geometry,
+            id: Some(geojson::feature::Id::String(format!(
+                "{}/{}",
+                self.element_type.as_str(),
+                self.id
+            ))),
+            properties: Some(properties),
+            foreign_members: None,
+        }
+    }
+}
+
+fn members_to_json(members: &[OsmRelationMember]) -> serde_json::Value {
+    members
+        .iter()
+        .map(|member| {
+            json!({
+                "type": match member.member_type {
+                    MemberType::Node => "node",
+                    MemberType::Way => "way",
+                    MemberType::Relation => "relation",
+                },
+                "ref": member.member_id,
+                "role": member.role
+            })
+        })
+        .collect()
+}
+
+/// Build a way feature, resolving its node coordinates from disk storage
+pub fn way_feature(way: OsmWay, storage: &CoordinateStorage) -> Feature {
+    match storage.get_nodes(&way.node_refs) {
+        Ok(coords) => {
+            let coordinates: Vec<(f64, f64)> = coords.into_iter().flatten().collect();
+            Feature::from_way_with_coordinates(way, &coordinates)
+        }
+        Err(e) => {
+            eprintln!(
+                "Warning: Failed to get coordinates for way {}: {}",
+                way.id, e
+            );
+            Feature::from_way(way)
+        }
+    }
+}
+
+/// Build a relation feature, resolving member ways and nodes from disk storage
+pub fn relation_feature(
+    relation: OsmRelation,
+    way_storage: &WayStorage,
+    storage: &CoordinateStorage,
+) -> Feature {
+    let member_ways = match resolve_relation_ways(&relation, way_storage, storage) {
+        Ok(ways) => ways,
+        Err(e) => {
+            eprintln!(
+                "Warning: Failed to resolve member ways for relation {}: {}",
+                relation.id, e
+            );
+            Vec::new()
+        }
+    };
+
+    let node_ids: Vec<i64> = relation
+        .members
+        .iter()
+        .filter(|member| member.member_type == MemberType::Node)
+        .map(|member| member.member_id)
+        .collect();
+
+    let mut node_coordinates = Vec::new();
+    if !node_ids.is_empty() {
+        match storage.get_nodes(&node_ids) {
+            Ok(coords) => node_coordinates.extend(coords.into_iter().flatten()),
+            Err(e) => {
+                eprintln!(
+                    "Warning: Failed to get coordinates for relation {}: {}",
+                    relation.id, e
+                );
+            }
+        }
+    }
+
+    Feature::from_relation_with_members(relation, &member_ways, &node_coordinates)
+}
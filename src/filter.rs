@@ -0,0 +1,609 @@
+// Parsed `--tags` filter expressions over tag conditions
+use crate::feature::ElementType;
+use anyhow::{Result, anyhow};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Key or value pattern: exact text, `*` wildcards or a regex
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Exact(String),
+    /// Literal parts between the wildcards; the first is anchored at the
+    /// start and the last at the end
+    Glob(Vec<String>),
+    /// Unanchored like `grep`; compiled once and shared by all workers
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn parse(pattern: &str) -> Self {
+        if pattern.contains('*') {
+            Pattern::Glob(pattern.split('*').map(str::to_string).collect())
+        } else {
+            Pattern::Exact(pattern.to_string())
+        }
+    }
+
+    pub fn regex(source: &str) -> Result<Self> {
+        // Syntax errors span several lines; the last one holds the reason
+        Regex::new(source).map(Pattern::Regex).map_err(|e| {
+            let message = e.to_string();
+            let reason = message.lines().last().unwrap_or_default();
+            anyhow!(
+                "invalid regex /{}/: {}",
+                source,
+                reason.trim_start_matches("error: ")
+            )
+        })
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        match self {
+            Pattern::Exact(exact) => text == exact,
+            Pattern::Glob(parts) => glob_match(parts, text),
+            Pattern::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+fn glob_match(parts: &[String], text: &str) -> bool {
+    let Some((first, rest)) = parts.split_first() else {
+        return true;
+    };
+    let Some(mut remaining) = text.strip_prefix(first.as_str()) else {
+        return false;
+    };
+    let Some((last, middle)) = rest.split_last() else {
+        return remaining.is_empty();
+    };
+
+    // Leftmost matches of the middle parts leave the most room for the rest
+    for part in middle {
+        match remaining.find(part.as_str()) {
+            Some(position) => remaining = &remaining[position + part.len()..],
+            None => return false,
+        }
+    }
+    remaining.ends_with(last.as_str())
+}
+
+/// One tag condition of the filter language:
+///
+/// - `key`: a tag with a matching key exists (`*` matches any tagged element)
+/// - `key=value`, `key=v1|v2`: a matching key has one of the values
+/// - `key!=value`: no matching key has the value (elements without the key pass)
+/// - `key~/regex/`, `key!~/regex/`: value regex, e.g. `name~/^St\.? /`
+/// - `key~value`: the Go pbf2json form of `key=value`
+///
+/// Keys and values may use `*` wildcards, e.g. `name=*Street` or `addr:*=*`.
+/// A leading `~` makes the key a regex: `~name:(en|de|fr)`, or `~/name:.+/`
+/// when it contains filter operators.
+#[derive(Debug, Clone)]
+pub struct TagCondition {
+    pub key: Pattern,
+    pub values: Option<Vec<Pattern>>, // None: the key only has to exist
+    pub negated: bool,
+}
+
+impl TagCondition {
+    pub fn parse(condition: &str) -> Result<Self> {
+        let condition = condition.trim();
+
+        let (key, rest) = match condition.strip_prefix('~') {
+            Some(rest) => {
+                let (source, rest) = match rest.strip_prefix('/') {
+                    Some(quoted) => split_regex(quoted)?,
+                    None => rest.split_at(operator_position(rest)),
+                };
+                (Pattern::regex(source.trim())?, rest)
+            }
+            None => {
+                let (key, rest) = condition.split_at(operator_position(condition));
+                (Pattern::parse(key.trim()), rest)
+            }
+        };
+
+        let (values, negated) = if let Some(values) = rest.strip_prefix("!=") {
+            (Some(values.split('|').map(Pattern::parse).collect()), true)
+        } else if let Some(values) = rest.strip_prefix('=') {
+            (Some(values.split('|').map(Pattern::parse).collect()), false)
+        } else if let Some(value) = rest.strip_prefix("!~") {
+            (Some(vec![value_regex(value)?]), true)
+        } else if let Some(value) = rest.strip_prefix('~') {
+            (Some(vec![value_regex(value)?]), false)
+        } else if rest.trim().is_empty() {
+            (None, false)
+        } else {
+            anyhow::bail!("unexpected '{}' after tag key", rest.trim());
+        };
+
+        Ok(TagCondition {
+            key,
+            values,
+            negated,
+        })
+    }
+
+    pub fn matches(&self, tags: &HashMap<String, String>) -> bool {
+        let found = tags.iter().any(|(key, value)| {
+            self.key.matches(key)
+                && self
+                    .values
+                    .as_ref()
+                    .is_none_or(|values| values.iter().any(|pattern| pattern.matches(value)))
+        });
+        found != self.negated
+    }
+}
+
+/// Start of the `=`, `!=`, `~` or `!~` operator, or the end of `text`
+fn operator_position(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    (0..bytes.len())
+        .find(|&i| match bytes[i] {
+            b'=' | b'~' => true,
+            b'!' => matches!(bytes.get(i + 1), Some(b'=' | b'~')),
+            _ => false,
+        })
+        .unwrap_or(text.len())
+}
+
+/// Split `regex/rest` at the closing unescaped `/`
+fn split_regex(quoted: &str) -> Result<(&str, &str)> {
+    let mut escaped = false;
+    for (i, c) in quoted.char_indices() {
+        match c {
+            '/' if !escaped => return Ok((&quoted[..i], &quoted[i + 1..])),
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+    anyhow::bail!("unterminated regex /{}", quoted)
+}
+
+/// `/regex/` after `~`; without slashes the value is matched exactly (Go syntax)
+fn value_regex(value: &str) -> Result<Pattern> {
+    match value.strip_prefix('/') {
+        Some(quoted) => {
+            let (source, rest) = split_regex(quoted)?;
+            if !rest.trim().is_empty() {
+                anyhow::bail!("unexpected '{}' after regex", rest.trim());
+            }
+            Pattern::regex(source)
+        }
+        None => Ok(Pattern::Exact(value.to_string())),
+    }
+}
+
+/// Set of OSM element types, from `--types` or `n:`/`w:`/`r:` qualifiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElementTypes {
+    pub node: bool,
+    pub way: bool,
+    pub relation: bool,
+}
+
+impl ElementTypes {
+    pub const ALL: ElementTypes = ElementTypes {
+        node: true,
+        way: true,
+        relation: true,
+    };
+    pub const NONE: ElementTypes = ElementTypes {
+        node: false,
+        way: false,
+        relation: false,
+    };
+
+    pub fn only(element_type: ElementType) -> Self {
+        let mut types = ElementTypes::NONE;
+        match element_type {
+            ElementType::Node => types.node = true,
+            ElementType::Way => types.way = true,
+            ElementType::Relation => types.relation = true,
+        }
+        types
+    }
+
+    /// Parse a comma-separated list such as `node,way` (`n`, `nodes` etc. also work)
+    pub fn parse(list: &str) -> Result<Self> {
+        let mut types = ElementTypes::NONE;
+        for name in list
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            match name {
+                "n" | "node" | "nodes" => types.node = true,
+                "w" | "way" | "ways" => types.way = true,
+                "r" | "relation" | "relations" => types.relation = true,
+                _ => anyhow::bail!(
+                    "unknown element type '{}', expected node, way or relation",
+                    name
+                ),
+            }
+        }
+        if types.is_empty() {
+            anyhow::bail!("no element types given");
+        }
+        Ok(types)
+    }
+
+    pub fn contains(&self, element_type: ElementType) -> bool {
+        match element_type {
+            ElementType::Node => self.node,
+            ElementType::Way => self.way,
+            ElementType::Relation => self.relation,
+        }
+    }
+
+    pub fn intersection(&self, other: ElementTypes) -> Self {
+        ElementTypes {
+            node: self.node && other.node,
+            way: self.way && other.way,
+            relation: self.relation && other.relation,
+        }
+    }
+
+    pub fn union(&self, other: ElementTypes) -> Self {
+        ElementTypes {
+            node: self.node || other.node,
+            way: self.way || other.way,
+            relation: self.relation || other.relation,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.node && !self.way && !self.relation
+    }
+
+    /// Whether every type in `other` sorts after all of these types, so a
+    /// file sorted by type holds none of these types past a blob of `other`
+    pub fn all_before(&self, other: ElementTypes) -> bool {
+        let ranks = |types: ElementTypes| {
+            [types.node, types.way, types.relation]
+                .into_iter()
+                .enumerate()
+                .filter(|(_, present)| *present)
+                .map(|(rank, _)| rank)
+        };
+        match (ranks(*self).max(), ranks(other).min()) {
+            (Some(last), Some(first)) => last < first,
+            _ => false,
+        }
+    }
+}
+
+impl Default for ElementTypes {
+    fn default() -> Self {
+        ElementTypes::ALL
+    }
+}
+
+impl fmt::Display for ElementTypes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = [ElementType::Node, ElementType::Way, ElementType::Relation]
+            .into_iter()
+            .filter(|element_type| self.contains(*element_type))
+            .map(|element_type| element_type.as_str())
+            .collect();
+        write!(f, "{}", names.join(","))
+    }
+}
+
+/// Boolean expression over tag conditions
+#[derive(Debug, Clone)]
+pub enum FilterExpr {
+    Condition(TagCondition),
+    Not(Box<FilterExpr>),
+    And(Vec<FilterExpr>),
+    Or(Vec<FilterExpr>),
+    /// `n:`, `w:`, `r:` (or combined, e.g. `nw:`): only elements of these types match
+    Typed(ElementTypes, Box<FilterExpr>),
+}
+
+impl FilterExpr {
+    pub fn matches(&self, element_type: ElementType, tags: &HashMap<String, String>) -> bool {
+        match self {
+            FilterExpr::Condition(condition) => condition.matches(tags),
+            FilterExpr::Not(expr) => !expr.matches(element_type, tags),
+            FilterExpr::And(exprs) => exprs.iter().all(|expr| expr.matches(element_type, tags)),
+            FilterExpr::Or(exprs) => exprs.iter().any(|expr| expr.matches(element_type, tags)),
+            FilterExpr::Typed(types, expr) => {
+                types.contains(element_type) && expr.matches(element_type, tags)
+            }
+        }
+    }
+
+    /// Element types the expression can match. Negations are treated as
+    /// matching every type.
+    pub fn element_types(&self) -> ElementTypes {
+        match self {
+            FilterExpr::Condition(_) | FilterExpr::Not(_) => ElementTypes::ALL,
+            FilterExpr::And(exprs) => exprs.iter().fold(ElementTypes::ALL, |types, expr| {
+                types.intersection(expr.element_types())
+            }),
+            FilterExpr::Or(exprs) => exprs.iter().fold(ElementTypes::NONE, |types, expr| {
+                types.union(expr.element_types())
+            }),
+            FilterExpr::Typed(types, expr) => types.intersection(expr.element_types()),
+        }
+    }
+}
+
+/// One top-level `,` alternative of a filter, or one `--filter-file` rule
+#[derive(Debug, Clone)]
+pub struct FilterGroup {
+    pub source: String, // Text of the alternative or rule name, used to name layers
+    pub expr: FilterExpr,
+    pub layer: Option<String>,           // Layer name overriding the source
+    pub keep_tags: Option<Vec<Pattern>>, // Keys written for matching features
+}
+
+/// A parsed `--tags` filter: top-level alternatives, any of which must match.
+///
+/// Grammar, loosest binding first:
+///
+/// ```text
+/// filter    := and ("," and)*
+/// and       := unary ("+" unary)*
+/// unary     := "!" unary | types ":" unary | "(" filter ")" | condition
+/// types     := one or more of "n", "w", "r"
+/// ```
+///
+/// e.g. `building+!building=no,(shop,amenity)+name` or
+/// `n:amenity,w:building,r:type=multipolygon`. Conditions are described at
+/// `TagCondition`.
+#[derive(Debug, Clone, Default)]
+pub struct TagFilter {
+    pub groups: Vec<FilterGroup>,
+}
+
+impl TagFilter {
+    /// Parse a `--tags` expression. Errors name the column of the problem.
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut parser = Parser {
+            chars: input.chars().collect(),
+            position: 0,
+        };
+
+        let mut groups = Vec::new();
+        loop {
+            let start = parser.position;
+            let expr = parser.and_expr();
+            let expr = expr.map_err(|(column, message)| syntax_error(input, column, &message))?;
+            let source: String = parser.chars[start..parser.position].iter().collect();
+            groups.push(FilterGroup {
+                source: source.trim().to_string(),
+                expr,
+                layer: None,
+                keep_tags: None,
+            });
+
+            parser.skip_whitespace();
+            match parser.peek() {
+                None => break,
+                Some(',') => parser.position += 1,
+                Some(c) => {
+                    let column = parser.position + 1;
+                    return Err(syntax_error(input, column, &format!("unexpected '{}'", c)));
+                }
+            }
+        }
+
+        Ok(TagFilter { groups })
+    }
+
+    /// Filter from already split OR groups of AND-ed conditions
+    pub fn from_groups(groups: &[Vec<String>]) -> Result<Self> {
+        let groups = groups
+            .iter()
+            .map(|group| {
+                let conditions = group
+                    .iter()
+                    .map(|c| TagCondition::parse(c).map(FilterExpr::Condition))
+                    .collect::<Result<_>>()?;
+                Ok(FilterGroup {
+                    source: group.join("+"),
+                    expr: FilterExpr::And(conditions),
+                    layer: None,
+                    keep_tags: None,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(TagFilter { groups })
+    }
+
+    /// An empty filter matches everything
+    pub fn matches(&self, element_type: ElementType, tags: &HashMap<String, String>) -> bool {
+        self.groups.is_empty()
+            || self
+                .groups
+                .iter()
+                .any(|group| group.expr.matches(element_type, tags))
+    }
+
+    /// Index of the first group matching the element
+    pub fn group_of(
+        &self,
+        element_type: ElementType,
+        tags: &HashMap<String, String>,
+    ) -> Option<usize> {
+        self.groups
+            .iter()
+            .position(|group| group.expr.matches(element_type, tags))
+    }
+
+    /// The alternatives as a single expression
+    pub fn into_expr(self) -> FilterExpr {
+        let mut exprs: Vec<FilterExpr> = self.groups.into_iter().map(|group| group.expr).collect();
+        match exprs.len() {
+            1 => exprs.remove(0),
+            _ => FilterExpr::Or(exprs),
+        }
+    }
+
+    /// Element types any alternative can match; converters skip the others
+    pub fn element_types(&self) -> ElementTypes {
+        if self.groups.is_empty() {
+            return ElementTypes::ALL;
+        }
+        self.groups.iter().fold(ElementTypes::NONE, |types, group| {
+            types.union(group.expr.element_types())
+        })
+    }
+}
+
+fn syntax_error(input: &str, column: usize, message: &str) -> anyhow::Error {
+    anyhow!(
+        "{} at column {}\n  {}\n  {}^",
+        message,
+        column,
+        input,
+        " ".repeat(column - 1)
+    )
+}
+
+/// Recursive descent parser; errors are `(column, message)`
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+type ParseResult = std::result::Result<FilterExpr, (usize, String)>;
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    fn or_expr(&mut self) -> ParseResult {
+        let mut exprs = vec![self.and_expr()?];
+        while self.peek() == Some(',') {
+            self.position += 1;
+            exprs.push(self.and_expr()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            FilterExpr::Or(exprs)
+        })
+    }
+
+    fn and_expr(&mut self) -> ParseResult {
+        let mut exprs = vec![self.unary()?];
+        while self.peek() == Some('+') {
+            self.position += 1;
+            exprs.push(self.unary()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            FilterExpr::And(exprs)
+        })
+    }
+
+    fn unary(&mut self) -> ParseResult {
+        self.skip_whitespace();
+        let column = self.position + 1;
+        let expr = match self.peek() {
+            Some('!') => {
+                self.position += 1;
+                FilterExpr::Not(Box::new(self.unary()?))
+            }
+            Some('(') => {
+                self.position += 1;
+                let expr = self.or_expr()?;
+                if self.peek() != Some(')') {
+                    return Err((column, "unclosed '('".to_string()));
+                }
+                self.position += 1;
+                expr
+            }
+            Some(',' | '+' | ')') | None => {
+                return Err((column, "expected a tag condition".to_string()));
+            }
+            Some(_) => match self.type_qualifier() {
+                Some(types) => FilterExpr::Typed(types, Box::new(self.unary()?)),
+                None => self.condition()?,
+            },
+        };
+        self.skip_whitespace();
+        Ok(expr)
+    }
+
+    /// Consume an `n:`, `w:`, `r:` or combined (`nw:`) element type qualifier
+    fn type_qualifier(&mut self) -> Option<ElementTypes> {
+        let mut types = ElementTypes::NONE;
+        let mut position = self.position;
+        loop {
+            let flag = match self.chars.get(position) {
+                Some('n') => &mut types.node,
+                Some('w') => &mut types.way,
+                Some('r') => &mut types.relation,
+                Some(':') if position > self.position => break,
+                _ => return None,
+            };
+            if *flag {
+                return None;
+            }
+            *flag = true;
+            position += 1;
+        }
+        self.position = position + 1;
+        Some(types)
+    }
+
+    /// A condition runs up to the next `,`, `+` or unmatched `)`; parentheses
+    /// opened inside it and `~/regex/` literals belong to the condition
+    fn condition(&mut self) -> ParseResult {
+        let start = self.position;
+        let mut depth = 0usize;
+        while let Some(c) = self.peek() {
+            match c {
+                '/' if self.position > start && self.chars[self.position - 1] == '~' => {
+                    self.skip_regex()?;
+                    continue;
+                }
+                '(' => depth += 1,
+                ')' if depth == 0 => break,
+                ')' => depth -= 1,
+                ',' | '+' if depth == 0 => break,
+                _ => {}
+            }
+            self.position += 1;
+        }
+
+        let text: String = self.chars[start..self.position].iter().collect();
+        let text = text.trim();
+        if text.starts_with('=') || text.starts_with("!=") {
+            return Err((start + 1, "missing tag key".to_string()));
+        }
+        TagCondition::parse(text)
+            .map(FilterExpr::Condition)
+            .map_err(|e| (start + 1, e.to_string()))
+    }
+
+    /// Move past a `/regex/` literal, honoring `\/` escapes
+    fn skip_regex(&mut self) -> std::result::Result<(), (usize, String)> {
+        let column = self.position + 1;
+        let mut escaped = false;
+        self.position += 1;
+        while let Some(c) = self.peek() {
+            self.position += 1;
+            match c {
+                '/' if !escaped => return Ok(()),
+                '\\' => escaped = !escaped,
+                _ => escaped = false,
+            }
+        }
+        Err((column, "unterminated regex".to_string()))
+    }
+}
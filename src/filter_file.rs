@@ -0,0 +1,110 @@
+// Named filter rules loaded from --filter-file (TOML, YAML or JSON)
+use crate::filter::{ElementTypes, FilterExpr, FilterGroup, Pattern, TagFilter};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FilterFile {
+    rules: Vec<FilterRule>,
+}
+
+/// One named rule; every field but `name` is optional
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FilterRule {
+    name: String,
+    /// `node`, `way`, `relation` as a comma-separated string or a list (default: all)
+    types: Option<OneOrMany>,
+    /// `--tags` expressions; the entries of a list must all match (default: any tags)
+    tags: Option<OneOrMany>,
+    /// Key patterns of the tags to write, e.g. `name` or `addr:*` (default: all)
+    keep_tags: Option<Vec<String>>,
+    /// GeoPackage table or MBTiles layer name (default: the rule name)
+    layer: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        }
+    }
+}
+
+/// Load rules from a `.toml`, `.yaml`/`.yml` or `.json` file
+pub fn load_filter_file(path: &Path) -> Result<TagFilter> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read filter file {}", path.display()))?;
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default();
+    parse_filter_file(&content, extension)
+        .with_context(|| format!("Invalid filter file {}", path.display()))
+}
+
+/// Compile rules into a filter with one group per rule, in file order
+pub fn parse_filter_file(content: &str, extension: &str) -> Result<TagFilter> {
+    let file: FilterFile = match extension.to_ascii_lowercase().as_str() {
+        "toml" => toml::from_str(content)?,
+        "yaml" | "yml" => serde_yaml::from_str(content)?,
+        "json" => serde_json::from_str(content)?,
+        _ => anyhow::bail!(
+            "unknown filter file format '{}', expected .toml, .yaml, .yml or .json",
+            extension
+        ),
+    };
+    if file.rules.is_empty() {
+        anyhow::bail!("no rules defined");
+    }
+
+    let mut groups: Vec<FilterGroup> = Vec::new();
+    for rule in file.rules {
+        if groups.iter().any(|group| group.source == rule.name) {
+            anyhow::bail!("duplicate rule name '{}'", rule.name);
+        }
+        let name = rule.name.clone();
+        groups.push(compile_rule(rule).with_context(|| format!("in rule '{}'", name))?);
+    }
+    Ok(TagFilter { groups })
+}
+
+fn compile_rule(rule: FilterRule) -> Result<FilterGroup> {
+    let conditions = rule
+        .tags
+        .map(OneOrMany::into_vec)
+        .unwrap_or_default()
+        .iter()
+        .map(|tags| TagFilter::parse(tags).map(|filter| filter.into_expr()))
+        .collect::<Result<Vec<_>>>()?;
+    let mut expr = match conditions.len() {
+        1 => conditions.into_iter().next().unwrap(),
+        _ => FilterExpr::And(conditions),
+    };
+
+    if let Some(types) = rule.types {
+        let types = ElementTypes::parse(&types.into_vec().join(","))?;
+        if types != ElementTypes::ALL {
+            expr = FilterExpr::Typed(types, Box::new(expr));
+        }
+    }
+
+    Ok(FilterGroup {
+        source: rule.name,
+        expr,
+        layer: rule.layer,
+        keep_tags: rule
+            .keep_tags
+            .map(|keys| keys.iter().map(|key| Pattern::parse(key)).collect()),
+    })
+}
@@ -0,0 +1,127 @@
+// FlatGeobuf output writer with a packed Hilbert R-tree index
+use crate::feature::Feature;
+use crate::geometry::to_wkb;
+use crate::output::FeatureSink;
+use anyhow::{Context, Result};
+use flatgeobuf::{ColumnType, FgbCrs, FgbWriter, FgbWriterOptions, GeometryType};
+use geozero::wkb::Wkb;
+use geozero::{ColumnValue, PropertyProcessor};
+use rayon::prelude::*;
+use std::io::Write;
+
+/// FlatGeobuf sink.
+///
+/// The spatial index can only be built once every feature is known, so
+/// features are spooled to a temporary file by `FgbWriter` and the header,
+/// index and Hilbert-sorted features are written to the output on finish.
+/// Features without geometry cannot be indexed and are skipped.
+pub struct FlatGeobufSink {
+    writer: Option<FgbWriter<'static>>,
+    output: Option<Box<dyn Write + Send>>,
+    written: usize,
+    skipped: usize,
+}
+
+impl FlatGeobufSink {
+    pub fn new(output: Box<dyn Write + Send>) -> Result<Self> {
+        // Mixed geometry types, so the header type stays Unknown and every
+        // feature records its own type
+        let options = FgbWriterOptions {
+            write_index: true,
+            detect_type: false,
+            promote_to_multi: false,
+            crs: FgbCrs {
+                code: 4326,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut writer = FgbWriter::create_with_options("osm", GeometryType::Unknown, options)
+            .context("Failed to create FlatGeobuf writer")?;
+
+        writer.add_column("id", ColumnType::Long, |_, column| column.nullable = false);
+        writer.add_column("type", ColumnType::String, |_, column| {
+            column.nullable = false
+        });
+        writer.add_column("tags", ColumnType::Json, |_, _| {});
+
+        Ok(FlatGeobufSink {
+            writer: Some(writer),
+            output: Some(output),
+            written: 0,
+            skipped: 0,
+        })
+    }
+}
+
+impl FeatureSink for FlatGeobufSink {
+    fn write_batch(&mut self, features: &[Feature]) -> Result<()> {
+        // WKB and tag JSON are encoded in parallel, features are added in order
+        let encoded: Vec<Option<(Vec<u8>, String)>> = features
+            .par_iter()
+            .map(|feature| {
+                let geometry = feature.output_geometry()?;
+                let mut tags: Vec<_> = feature.tags.iter().collect();
+                tags.sort();
+                let tags: serde_json::Map<String, serde_json::Value> = tags
+                    .into_iter()
+                    .map(|(key, value)| (key.clone(), value.clone().into()))
+                    .collect();
+                Some((
+                    to_wkb(&geometry),
+                    serde_json::Value::Object(tags).to_string(),
+                ))
+            })
+            .collect();
+
+        let writer = self
+            .writer
+            .as_mut()
+            .context("FlatGeobuf writer already closed")?;
+
+        for (feature, encoded) in features.iter().zip(encoded) {
+            let Some((wkb, tags)) = encoded else {
+                self.skipped += 1;
+                continue;
+            };
+
+            let mut properties_written = Ok(false);
+            writer.add_feature_geom(Wkb(wkb), |properties| {
+                properties_written = properties
+                    .property(0, "id", &ColumnValue::Long(feature.id))
+                    .and_then(|_| {
+                        properties.property(
+                            1,
+                            "type",
+                            &ColumnValue::String(feature.element_type.as_str()),
+                        )
+                    })
+                    .and_then(|_| properties.property(2, "tags", &ColumnValue::Json(&tags)));
+            })?;
+            properties_written?;
+            self.written += 1;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let (Some(writer), Some(mut output)) = (self.writer.take(), self.output.take()) {
+            eprintln!(
+                "Building FlatGeobuf spatial index for {} features...",
+                self.written
+            );
+            writer
+                .write(&mut output)
+                .context("Failed to write FlatGeobuf file")?;
+            output.flush()?;
+
+            if self.skipped > 0 {
+                eprintln!(
+                    "Warning: Skipped {} features without geometry in FlatGeobuf output",
+                    self.skipped
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,200 @@
+// GeoJSON geometry construction from resolved node coordinates
+use crate::osm::{OsmWay, is_area};
+use geojson::{Geometry, Position, Value};
+
+/// Build the GeoJSON geometry for a way from its resolved node coordinates.
+///
+/// `coordinates` are `(lat, lon)` pairs in way order, as returned by
+/// `CoordinateStorage::get_nodes` with missing nodes removed. Closed ways that
+/// `osm::is_area` classifies as areas become Polygons, everything else becomes a
+/// LineString. Returns `None` when fewer than two coordinates could be resolved.
+pub fn way_geometry(way: &OsmWay, coordinates: &[(f64, f64)]) -> Option<Geometry> {
+    if coordinates.len() < 2 {
+        return None;
+    }
+
+    let positions = to_positions(coordinates);
+
+    if is_area(way) && is_closed_ring(&positions) {
+        let mut ring = positions;
+        orient_ring(&mut ring, true);
+        return Some(Geometry::new(Value::Polygon(vec![ring])));
+    }
+
+    Some(Geometry::new(Value::LineString(positions)))
+}
+
+/// Convert `(lat, lon)` pairs into GeoJSON `[lon, lat]` positions
+pub fn to_positions(coordinates: &[(f64, f64)]) -> Vec<Position> {
+    coordinates
+        .iter()
+        .map(|&(lat, lon)| vec![lon, lat])
+        .collect()
+}
+
+/// A ring is closed when it has at least four positions and ends where it starts
+pub fn is_closed_ring(ring: &[Position]) -> bool {
+    ring.len() >= 4 && ring.first() == ring.last()
+}
+
+/// Signed area of a ring using the shoelace formula (positive = counter-clockwise)
+pub fn ring_signed_area(ring: &[Position]) -> f64 {
+    if ring.len() < 3 {
+        return 0.0;
+    }
+
+    let mut area = 0.0;
+    for pair in ring.windows(2) {
+        area += pair[0][0] * pair[1][1] - pair[1][0] * pair[0][1];
+    }
+    area / 2.0
+}
+
+/// Orient a ring per RFC 7946: exterior rings counter-clockwise, holes clockwise
+pub fn orient_ring(ring: &mut [Position], exterior: bool) {
+    let counter_clockwise = ring_signed_area(ring) > 0.0;
+    if counter_clockwise != exterior {
+        ring.reverse();
+    }
+}
+
+/// Average of `(lat, lon)` coordinates, `(0.0, 0.0)` when empty
+pub fn calculate_centroid(coordinates: &[(f64, f64)]) -> (f64, f64) {
+    if coordinates.is_empty() {
+        return (0.0, 0.0);
+    }
+
+    let sum_lat: f64 = coordinates.iter().map(|(lat, _)| lat).sum();
+    let sum_lon: f64 = coordinates.iter().map(|(_, lon)| lon).sum();
+    let count = coordinates.len() as f64;
+
+    (sum_lat / count, sum_lon / count)
+}
+
+/// Bounding box of a set of coordinates in degrees
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub north: f64,
+    pub south: f64,
+    pub east: f64,
+    pub west: f64,
+}
+
+/// Bounding box of `(lat, lon)` coordinates, all zero when empty
+pub fn calculate_bounds(coordinates: &[(f64, f64)]) -> Bounds {
+    if coordinates.is_empty() {
+        return Bounds {
+            north: 0.0,
+            south: 0.0,
+            east: 0.0,
+            west: 0.0,
+        };
+    }
+
+    let mut north = f64::NEG_INFINITY;
+    let mut south = f64::INFINITY;
+    let mut east = f64::NEG_INFINITY;
+    let mut west = f64::INFINITY;
+
+    for &(lat, lon) in coordinates {
+        north = north.max(lat);
+        south = south.min(lat);
+        east = east.max(lon);
+        west = west.min(lon);
+    }
+
+    Bounds {
+        north,
+        south,
+        east,
+        west,
+    }
+}
+
+/// Encode a geometry as little-endian ISO WKB (2D)
+pub fn to_wkb(geometry: &Geometry) -> Vec<u8> {
+    let mut wkb = Vec::new();
+    write_wkb_value(&mut wkb, &geometry.value);
+    wkb
+}
+
+/// GeoParquet / WKB name of a geometry type
+pub fn geometry_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Point(_) => "Point",
+        Value::MultiPoint(_) => "MultiPoint",
+        Value::LineString(_) => "LineString",
+        Value::MultiLineString(_) => "MultiLineString",
+        Value::Polygon(_) => "Polygon",
+        Value::MultiPolygon(_) => "MultiPolygon",
+        Value::GeometryCollection(_) => "GeometryCollection",
+    }
+}
+
+fn write_wkb_header(wkb: &mut Vec<u8>, wkb_type: u32) {
+    wkb.push(1); // Little endian
+    wkb.extend_from_slice(&wkb_type.to_le_bytes());
+}
+
+fn write_wkb_points(wkb: &mut Vec<u8>, positions: &[Position]) {
+    wkb.extend_from_slice(&(positions.len() as u32).to_le_bytes());
+    for position in positions {
+        wkb.extend_from_slice(&position[0].to_le_bytes());
+        wkb.extend_from_slice(&position[1].to_le_bytes());
+    }
+}
+
+fn write_wkb_rings(wkb: &mut Vec<u8>, rings: &[Vec<Position>]) {
+    wkb.extend_from_slice(&(rings.len() as u32).to_le_bytes());
+    for ring in rings {
+        write_wkb_points(wkb, ring);
+    }
+}
+
+fn write_wkb_value(wkb: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Point(position) => {
+            write_wkb_header(wkb, 1);
+            wkb.extend_from_slice(&position[0].to_le_bytes());
+            wkb.extend_from_slice(&position[1].to_le_bytes());
+        }
+        Value::LineString(positions) => {
+            write_wkb_header(wkb, 2);
+            write_wkb_points(wkb, positions);
+        }
+        Value::Polygon(rings) => {
+            write_wkb_header(wkb, 3);
+            write_wkb_rings(wkb, rings);
+        }
+        Value::MultiPoint(positions) => {
+            write_wkb_header(wkb, 4);
+            wkb.extend_from_slice(&(positions.len() as u32).to_le_bytes());
+            for position in positions {
+                write_wkb_value(wkb, &Value::Point(position.clone()));
+            }
+        }
+        Value::MultiLineString(lines) => {
+            write_wkb_header(wkb, 5);
+            wkb.extend_from_slice(&(lines.len() as u32).to_le_bytes());
+            for line in lines {
+                write_wkb_header(wkb, 2);
+                write_wkb_points(wkb, line);
+            }
+        }
+        Value::MultiPolygon(polygons) => {
+            write_wkb_header(wkb, 6);
+            wkb.extend_from_slice(&(polygons.len() as u32).to_le_bytes());
+            for polygon in polygons {
+                write_wkb_header(wkb, 3);
+                write_wkb_rings(wkb, polygon);
+            }
+        }
+        Value::GeometryCollection(geometries) => {
+            write_wkb_header(wkb, 7);
+            wkb.extend_from_slice(&(geometries.len() as u32).to_le_bytes());
+            for geometry in geometries {
+                write_wkb_value(wkb, &geometry.value);
+            }
+        }
+    }
+}
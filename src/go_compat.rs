@@ -0,0 +1,411 @@
+// Output in the record layout of the Go pelias/pbf2json tool (`--compat go`)
+use crate::coordinate_storage::CoordinateStorage;
+use crate::feature::{Centroid, Feature};
+use crate::geometry::calculate_bounds;
+use crate::osm::{MemberType, OsmElement, OsmRelation, OsmWay};
+use crate::way_storage::WayStorage;
+use anyhow::{Context, Result};
+use osmpbf::{Element, ElementReader};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+/// Tools whose jsonl record layout `--compat` reproduces
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compat {
+    /// This tool's own layout
+    #[default]
+    None,
+    /// The Go pelias/pbf2json tool
+    Go,
+}
+
+impl Compat {
+    /// Names accepted by `--compat`
+    pub const NAMES: [&'static str; 2] = ["none", "go"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compat::None => "none",
+            Compat::Go => "go",
+        }
+    }
+}
+
+impl fmt::Display for Compat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Compat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(Compat::None),
+            "go" => Ok(Compat::Go),
+            _ => anyhow::bail!(
+                "Unknown compat mode '{}', expected one of: {}",
+                s,
+                Compat::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+/// A node tagged `entrance`, which the Go tool prefers as a way's centroid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Entrance {
+    pub main: bool,       // entrance=main
+    pub accessible: bool, // wheelchair=yes
+}
+
+/// Entrance nodes by ID
+pub type EntranceIndex = HashMap<i64, Entrance>;
+
+/// Entrance flags of a node, `None` when it is not an entrance
+pub fn entrance_of(tags: &HashMap<String, String>) -> Option<Entrance> {
+    let entrance = tags.get("entrance")?;
+    Some(Entrance {
+        main: entrance.trim() == "main",
+        accessible: tags.get("wheelchair").is_some_and(|v| v.trim() == "yes"),
+    })
+}
+
+/// Collect all entrance nodes of a PBF file. Entrances are rare, so the index
+/// stays small even for large extracts.
+pub fn collect_entrances(input_path: &str) -> Result<EntranceIndex> {
+    let reader = ElementReader::from_path(input_path)
+        .context("Failed to open PBF file for entrance collection")?;
+
+    let entrances = reader.par_map_reduce(
+        |element| {
+            let owned = |(k, v): (&str, &str)| (k.to_string(), v.to_string());
+            let (id, tags): (i64, HashMap<String, String>) = match element {
+                Element::Node(node) if node.tags().any(|(k, _)| k == "entrance") => {
+                    (node.id(), node.tags().map(owned).collect())
+                }
+                Element::DenseNode(node) if node.tags().any(|(k, _)| k == "entrance") => {
+                    (node.id(), node.tags().map(owned).collect())
+                }
+                _ => return Vec::new(),
+            };
+            entrance_of(&tags)
+                .map(|entrance| vec![(id, entrance)])
+                .unwrap_or_default()
+        },
+        Vec::new,
+        |mut acc, mut batch| {
+            acc.append(&mut batch);
+            acc
+        },
+    )?;
+
+    Ok(entrances.into_iter().collect())
+}
+
+/// Tags with surrounding whitespace removed from keys and values, as the Go tool does
+pub fn trim_tags(tags: HashMap<String, String>) -> HashMap<String, String> {
+    tags.into_iter()
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect()
+}
+
+/// The Go tool stores node locations as 7 decimal strings, so all of its
+/// geometry is computed from these rounded values
+fn go_round(value: f64) -> f64 {
+    format!("{:.7}", value).parse().unwrap_or(value)
+}
+
+/// All node locations of a way, or `None` if any node is missing: the Go tool
+/// skips ways it cannot fully denormalize
+fn resolve_way_nodes(node_refs: &[i64], storage: &CoordinateStorage) -> Option<Vec<(f64, f64)>> {
+    let coordinates = match storage.get_nodes(node_refs) {
+        Ok(coordinates) => coordinates,
+        Err(e) => {
+            eprintln!("Warning: Failed to get way coordinates: {}", e);
+            return None;
+        }
+    };
+
+    coordinates
+        .into_iter()
+        .map(|coordinate| coordinate.map(|(lat, lon)| (go_round(lat), go_round(lon))))
+        .collect()
+}
+
+/// Feature for an element as the Go tool would emit it, `None` for ways and
+/// relations it would skip
+pub fn go_feature(
+    element: OsmElement,
+    storage: &CoordinateStorage,
+    way_storage: &WayStorage,
+    entrances: &EntranceIndex,
+) -> Option<Feature> {
+    match element {
+        OsmElement::Node(mut node) => {
+            node.tags = trim_tags(node.tags);
+            Some(Feature::from_node(node))
+        }
+        OsmElement::Way(way) => go_way_feature(way, storage, entrances),
+        OsmElement::Relation(relation) => go_relation_feature(relation, way_storage, storage),
+    }
+}
+
+/// Way with its denormalized nodes; the centroid is its main (or wheelchair
+/// accessible, or first) entrance node when it has one
+pub fn go_way_feature(
+    mut way: OsmWay,
+    storage: &CoordinateStorage,
+    entrances: &EntranceIndex,
+) -> Option<Feature> {
+    let coordinates = resolve_way_nodes(&way.node_refs, storage)?;
+    if coordinates.is_empty() {
+        return None;
+    }
+
+    let way_entrances: Vec<(Entrance, (f64, f64))> = way
+        .node_refs
+        .iter()
+        .zip(&coordinates)
+        .filter_map(|(id, &coordinate)| entrances.get(id).map(|&e| (e, coordinate)))
+        .collect();
+    let entrance = way_entrances
+        .iter()
+        .find(|(e, _)| e.main)
+        .or_else(|| way_entrances.iter().find(|(e, _)| e.accessible))
+        .or_else(|| way_entrances.first());
+
+    let centroid = match entrance {
+        Some(&(_, (lat, lon))) => Centroid {
+            lat,
+            lon,
+            kind: "entrance",
+        },
+        None => {
+            let (lat, lon) = go_centroid(&coordinates);
+            Centroid {
+                lat,
+                lon,
+                kind: "centroid",
+            }
+        }
+    };
+
+    way.tags = trim_tags(way.tags);
+    let mut feature = Feature::from_way(way);
+    feature.centroid = Some(centroid);
+    feature.bounds = Some(calculate_bounds(&coordinates));
+    feature.way_nodes = coordinates;
+    Some(feature)
+}
+
+/// Relation with the centroid of its largest member way (by bounding box area)
+/// and the bounds of all member ways. Member nodes are ignored and relations
+/// without any fully resolvable member way are skipped.
+pub fn go_relation_feature(
+    mut relation: OsmRelation,
+    way_storage: &WayStorage,
+    storage: &CoordinateStorage,
+) -> Option<Feature> {
+    let way_ids: Vec<i64> = relation
+        .members
+        .iter()
+        .filter(|member| member.member_type == MemberType::Way)
+        .map(|member| member.member_id)
+        .collect();
+    let node_lists = match way_storage.get_ways(&way_ids) {
+        Ok(node_lists) => node_lists,
+        Err(e) => {
+            eprintln!(
+                "Warning: Failed to resolve member ways for relation {}: {}",
+                relation.id, e
+            );
+            return None;
+        }
+    };
+
+    let member_ways: Vec<Vec<(f64, f64)>> = node_lists
+        .into_iter()
+        .flatten()
+        .filter_map(|node_refs| resolve_way_nodes(&node_refs, storage))
+        .filter(|coordinates| !coordinates.is_empty())
+        .collect();
+
+    let mut largest: Option<(f64, &[(f64, f64)])> = None;
+    for coordinates in &member_ways {
+        let bounds = calculate_bounds(coordinates);
+        let area = (bounds.east - bounds.west) * (bounds.north - bounds.south);
+        if largest.is_none_or(|(largest_area, _)| area > largest_area) {
+            largest = Some((area, coordinates));
+        }
+    }
+    let (_, largest_way) = largest?;
+    let (lat, lon) = go_centroid(largest_way);
+
+    let all_coordinates: Vec<(f64, f64)> = member_ways.concat();
+    relation.tags = trim_tags(relation.tags);
+    let mut feature = Feature::from_relation(relation);
+    feature.centroid = Some(Centroid {
+        lat,
+        lon,
+        kind: "centroid",
+    });
+    feature.bounds = Some(calculate_bounds(&all_coordinates));
+    Some(feature)
+}
+
+/// Centroid of a way's `(lat, lon)` coordinates: the area centroid of closed
+/// rings, otherwise the length weighted centroid of the line
+pub fn go_centroid(coordinates: &[(f64, f64)]) -> (f64, f64) {
+    let closed = coordinates.len() > 2 && coordinates.first() == coordinates.last();
+    if closed && let Some(centroid) = polygon_centroid(coordinates) {
+        return centroid;
+    }
+    line_centroid(coordinates)
+}
+
+fn polygon_centroid(ring: &[(f64, f64)]) -> Option<(f64, f64)> {
+    let (mut area, mut lat, mut lon) = (0.0, 0.0, 0.0);
+    for pair in ring.windows(2) {
+        let ((y0, x0), (y1, x1)) = (pair[0], pair[1]);
+        let cross = x0 * y1 - x1 * y0;
+        area += cross;
+        lon += (x0 + x1) * cross;
+        lat += (y0 + y1) * cross;
+    }
+    if area == 0.0 {
+        return None;
+    }
+    // area is twice the signed area, so 6 * signed area = 3 * area
+    Some((lat / (3.0 * area), lon / (3.0 * area)))
+}
+
+fn line_centroid(line: &[(f64, f64)]) -> (f64, f64) {
+    let (mut length, mut lat, mut lon) = (0.0, 0.0, 0.0);
+    for pair in line.windows(2) {
+        let ((y0, x0), (y1, x1)) = (pair[0], pair[1]);
+        let segment = (x1 - x0).hypot(y1 - y0);
+        length += segment;
+        lon += (x0 + x1) / 2.0 * segment;
+        lat += (y0 + y1) / 2.0 * segment;
+    }
+    if length == 0.0 {
+        return line[0];
+    }
+    (lat / length, lon / length)
+}
+
+/// Encode a feature as a Go pbf2json record: struct fields in declaration
+/// order, map keys sorted, numbers and strings formatted like Go's
+/// `encoding/json`
+pub fn to_go_json(feature: &Feature) -> String {
+    let mut out = String::new();
+    write!(
+        out,
+        "{{\"id\":{},\"type\":\"{}\"",
+        feature.id,
+        feature.element_type.as_str()
+    )
+    .unwrap();
+
+    if let Some((lat, lon)) = feature.location {
+        write!(out, ",\"lat\":{},\"lon\":{}", go_float(lat), go_float(lon)).unwrap();
+    }
+
+    out.push_str(",\"tags\":");
+    let tags: BTreeMap<&str, &str> = feature
+        .tags
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    write_string_map(&mut out, tags);
+
+    if let Some(centroid) = &feature.centroid {
+        let mut fields = vec![
+            ("lat", format!("{:.7}", centroid.lat)),
+            ("lon", format!("{:.7}", centroid.lon)),
+        ];
+        if centroid.kind == "entrance" {
+            fields.push(("type", centroid.kind.to_string()));
+        }
+        out.push_str(",\"centroid\":");
+        write_string_map(&mut out, fields.iter().map(|(k, v)| (*k, v.as_str())));
+    }
+
+    if let Some(bounds) = &feature.bounds {
+        let fields = [
+            ("e", format!("{:.7}", bounds.east)),
+            ("n", format!("{:.7}", bounds.north)),
+            ("s", format!("{:.7}", bounds.south)),
+            ("w", format!("{:.7}", bounds.west)),
+        ];
+        out.push_str(",\"bounds\":");
+        write_string_map(&mut out, fields.iter().map(|(k, v)| (*k, v.as_str())));
+    }
+
+    if !feature.way_nodes.is_empty() {
+        out.push_str(",\"nodes\":[");
+        for (i, (lat, lon)) in feature.way_nodes.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write!(out, "{{\"lat\":\"{:.7}\",\"lon\":\"{:.7}\"}}", lat, lon).unwrap();
+        }
+        out.push(']');
+    }
+
+    out.push('}');
+    out
+}
+
+/// JSON object of string values, keys already in order
+fn write_string_map<'a>(out: &mut String, entries: impl IntoIterator<Item = (&'a str, &'a str)>) {
+    out.push('{');
+    for (i, (key, value)) in entries.into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_go_string(out, key);
+        out.push(':');
+        write_go_string(out, value);
+    }
+    out.push('}');
+}
+
+/// JSON string escaped like Go's `encoding/json`, including its HTML escaping
+/// of `<`, `>` and `&`
+pub fn write_go_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '<' | '>' | '&' | '\u{2028}' | '\u{2029}' => {
+                write!(out, "\\u{:04x}", c as u32).unwrap()
+            }
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Number formatted like Go's `encoding/json`: shortest round-trip digits,
+/// exponent notation only for very small or large magnitudes
+pub fn go_float(value: f64) -> String {
+    let abs = value.abs();
+    if abs != 0.0 && !(1e-6..1e21).contains(&abs) {
+        let formatted = format!("{:e}", value);
+        match formatted.contains("e-") {
+            true => formatted,
+            false => formatted.replace('e', "e+"),
+        }
+    } else {
+        format!("{}", value)
+    }
+}
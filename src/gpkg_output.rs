@@ -0,0 +1,348 @@
+// GeoPackage output: one feature table per layer with an R-tree spatial index
+use crate::feature::Feature;
+use crate::geometry::to_wkb;
+use crate::layers::LayerScheme;
+use crate::output::FeatureSink;
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use rusqlite::{Connection, params};
+use std::path::Path;
+
+/// Rows inserted per SQLite transaction
+const ROWS_PER_TRANSACTION: usize = 100_000;
+
+const GPKG_APPLICATION_ID: i32 = 0x4750_4B47; // "GPKG"
+const GPKG_VERSION: i32 = 10400; // GeoPackage 1.4.0
+const SRS_ID: i32 = 4326;
+
+const WGS84_DEFINITION: &str = r#"GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563,AUTHORITY["EPSG","7030"]],AUTHORITY["EPSG","6326"]],PRIMEM["Greenwich",0,AUTHORITY["EPSG","8901"]],UNIT["degree",0.0174532925199433,AUTHORITY["EPSG","9122"]],AXIS["Latitude",NORTH],AXIS["Longitude",EAST],AUTHORITY["EPSG","4326"]]"#;
+
+/// GeoPackage core tables, plus the schema extension tables used to mark
+/// `tags` as JSON
+const GPKG_SCHEMA: &str = "
+CREATE TABLE gpkg_spatial_ref_sys (
+    srs_name TEXT NOT NULL,
+    srs_id INTEGER PRIMARY KEY,
+    organization TEXT NOT NULL,
+    organization_coordsys_id INTEGER NOT NULL,
+    definition TEXT NOT NULL,
+    description TEXT
+);
+CREATE TABLE gpkg_contents (
+    table_name TEXT NOT NULL PRIMARY KEY,
+    data_type TEXT NOT NULL,
+    identifier TEXT UNIQUE,
+    description TEXT DEFAULT '',
+    last_change DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+    min_x DOUBLE,
+    min_y DOUBLE,
+    max_x DOUBLE,
+    max_y DOUBLE,
+    srs_id INTEGER,
+    CONSTRAINT fk_gc_r_srs_id FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys(srs_id)
+);
+CREATE TABLE gpkg_geometry_columns (
+    table_name TEXT NOT NULL,
+    column_name TEXT NOT NULL,
+    geometry_type_name TEXT NOT NULL,
+    srs_id INTEGER NOT NULL,
+    z TINYINT NOT NULL,
+    m TINYINT NOT NULL,
+    CONSTRAINT pk_geom_cols PRIMARY KEY (table_name, column_name),
+    CONSTRAINT uk_gc_table_name UNIQUE (table_name),
+    CONSTRAINT fk_gc_tn FOREIGN KEY (table_name) REFERENCES gpkg_contents(table_name),
+    CONSTRAINT fk_gc_srs FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys (srs_id)
+);
+CREATE TABLE gpkg_extensions (
+    table_name TEXT,
+    column_name TEXT,
+    extension_name TEXT NOT NULL,
+    definition TEXT NOT NULL,
+    scope TEXT NOT NULL,
+    CONSTRAINT ge_tce UNIQUE (table_name, column_name, extension_name)
+);
+CREATE TABLE gpkg_data_columns (
+    table_name TEXT NOT NULL,
+    column_name TEXT NOT NULL,
+    name TEXT,
+    title TEXT,
+    description TEXT,
+    mime_type TEXT,
+    constraint_name TEXT,
+    CONSTRAINT pk_gdc PRIMARY KEY (table_name, column_name),
+    CONSTRAINT gdc_tn UNIQUE (table_name, name)
+);
+CREATE TABLE gpkg_data_column_constraints (
+    constraint_name TEXT NOT NULL,
+    constraint_type TEXT NOT NULL,
+    value TEXT,
+    min NUMERIC,
+    min_is_inclusive BOOLEAN,
+    max NUMERIC,
+    max_is_inclusive BOOLEAN,
+    description TEXT,
+    CONSTRAINT gdcc_ntv UNIQUE (constraint_name, constraint_type, value)
+);
+INSERT INTO gpkg_extensions VALUES
+    ('gpkg_data_columns', NULL, 'gpkg_schema', 'http://www.geopackage.org/spec/#extension_schema', 'read-write'),
+    ('gpkg_data_column_constraints', NULL, 'gpkg_schema', 'http://www.geopackage.org/spec/#extension_schema', 'read-write');
+";
+
+/// GeoPackage sink.
+///
+/// Each layer (element type or filter group) becomes a feature table with
+/// `osm_id`, `osm_type`, `tags` (JSON) and `geom` columns. R-tree rows are
+/// inserted alongside the features; the spec's maintenance triggers call
+/// SpatiaLite/GDAL `ST_*` functions, so they are only created once loading
+/// is done.
+pub struct GpkgSink {
+    connection: Connection,
+    scheme: LayerScheme,
+    tables: Vec<String>,
+    extents: Vec<Option<[f64; 4]>>,
+    rows_in_transaction: usize,
+    skipped: usize,
+}
+
+impl GpkgSink {
+    pub fn create(path: &str, scheme: LayerScheme) -> Result<Self> {
+        // SQLite would otherwise open and extend an existing file
+        if Path::new(path).exists() {
+            std::fs::remove_file(path)
+                .with_context(|| format!("Failed to replace output file: {}", path))?;
+        }
+
+        let connection = Connection::open(path)
+            .with_context(|| format!("Failed to create GeoPackage: {}", path))?;
+        connection.execute_batch(&format!(
+            "PRAGMA application_id = {};
+             PRAGMA user_version = {};
+             PRAGMA journal_mode = OFF;
+             PRAGMA synchronous = OFF;",
+            GPKG_APPLICATION_ID, GPKG_VERSION
+        ))?;
+        connection.execute_batch(GPKG_SCHEMA)?;
+        connection.execute(
+            "INSERT INTO gpkg_spatial_ref_sys VALUES
+                ('WGS 84 geodetic', ?1, 'EPSG', ?1, ?2, 'longitude/latitude coordinates in decimal degrees on the WGS 84 spheroid'),
+                ('Undefined cartesian SRS', -1, 'NONE', -1, 'undefined', 'undefined cartesian coordinate reference system'),
+                ('Undefined geographic SRS', 0, 'NONE', 0, 'undefined', 'undefined geographic coordinate reference system')",
+            params![SRS_ID, WGS84_DEFINITION],
+        )?;
+
+        let tables = scheme.names();
+        for table in &tables {
+            create_feature_table(&connection, table)?;
+        }
+
+        connection.execute_batch("BEGIN")?;
+        Ok(GpkgSink {
+            connection,
+            scheme,
+            extents: vec![None; tables.len()],
+            tables,
+            rows_in_transaction: 0,
+            skipped: 0,
+        })
+    }
+}
+
+fn create_feature_table(connection: &Connection, table: &str) -> Result<()> {
+    connection.execute_batch(&format!(
+        "CREATE TABLE \"{table}\" (
+            fid INTEGER PRIMARY KEY AUTOINCREMENT,
+            geom GEOMETRY,
+            osm_id INTEGER NOT NULL,
+            osm_type TEXT NOT NULL,
+            tags TEXT
+        );
+        CREATE VIRTUAL TABLE \"rtree_{table}_geom\" USING rtree(id, minx, maxx, miny, maxy);"
+    ))?;
+    connection.execute(
+        "INSERT INTO gpkg_contents (table_name, data_type, identifier, srs_id)
+         VALUES (?1, 'features', ?1, ?2)",
+        params![table, SRS_ID],
+    )?;
+    connection.execute(
+        "INSERT INTO gpkg_geometry_columns VALUES (?1, 'geom', 'GEOMETRY', ?2, 0, 0)",
+        params![table, SRS_ID],
+    )?;
+    connection.execute(
+        "INSERT INTO gpkg_extensions VALUES
+            (?1, 'geom', 'gpkg_rtree_index', 'http://www.geopackage.org/spec120/#extension_rtree', 'write-only')",
+        params![table],
+    )?;
+    connection.execute(
+        "INSERT INTO gpkg_data_columns (table_name, column_name, name, mime_type)
+         VALUES (?1, 'tags', 'tags', 'application/json')",
+        params![table],
+    )?;
+    Ok(())
+}
+
+/// R-tree maintenance triggers from the GeoPackage spec (F.3)
+fn create_rtree_triggers(connection: &Connection, table: &str) -> Result<()> {
+    let rtree = format!("rtree_{}_geom", table);
+    let values =
+        "NEW.fid, ST_MinX(NEW.geom), ST_MaxX(NEW.geom), ST_MinY(NEW.geom), ST_MaxY(NEW.geom)";
+    connection.execute_batch(&format!(
+        "CREATE TRIGGER \"{rtree}_insert\" AFTER INSERT ON \"{table}\"
+         WHEN (NEW.geom NOT NULL AND NOT ST_IsEmpty(NEW.geom))
+         BEGIN
+            INSERT OR REPLACE INTO \"{rtree}\" VALUES ({values});
+         END;
+         CREATE TRIGGER \"{rtree}_update1\" AFTER UPDATE OF geom ON \"{table}\"
+         WHEN OLD.fid = NEW.fid AND (NEW.geom NOTNULL AND NOT ST_IsEmpty(NEW.geom))
+         BEGIN
+            INSERT OR REPLACE INTO \"{rtree}\" VALUES ({values});
+         END;
+         CREATE TRIGGER \"{rtree}_update2\" AFTER UPDATE OF geom ON \"{table}\"
+         WHEN OLD.fid = NEW.fid AND (NEW.geom IS NULL OR ST_IsEmpty(NEW.geom))
+         BEGIN
+            DELETE FROM \"{rtree}\" WHERE id = OLD.fid;
+         END;
+         CREATE TRIGGER \"{rtree}_update3\" AFTER UPDATE ON \"{table}\"
+         WHEN OLD.fid != NEW.fid AND (NEW.geom NOTNULL AND NOT ST_IsEmpty(NEW.geom))
+         BEGIN
+            DELETE FROM \"{rtree}\" WHERE id = OLD.fid;
+            INSERT OR REPLACE INTO \"{rtree}\" VALUES ({values});
+         END;
+         CREATE TRIGGER \"{rtree}_update4\" AFTER UPDATE ON \"{table}\"
+         WHEN OLD.fid != NEW.fid AND (NEW.geom IS NULL OR ST_IsEmpty(NEW.geom))
+         BEGIN
+            DELETE FROM \"{rtree}\" WHERE id IN (OLD.fid, NEW.fid);
+         END;
+         CREATE TRIGGER \"{rtree}_delete\" AFTER DELETE ON \"{table}\"
+         WHEN OLD.geom NOT NULL
+         BEGIN
+            DELETE FROM \"{rtree}\" WHERE id = OLD.fid;
+         END;"
+    ))?;
+    Ok(())
+}
+
+/// StandardGeoPackageBinary: `GP` header with little-endian flags, SRS id and
+/// the `[minx, maxx, miny, maxy]` envelope, followed by the WKB geometry
+pub fn gpkg_geometry_blob(geometry: &geojson::Geometry, bbox: Option<[f64; 4]>) -> Vec<u8> {
+    let wkb = to_wkb(geometry);
+    let mut blob = Vec::with_capacity(8 + 32 + wkb.len());
+    blob.extend_from_slice(b"GP");
+    blob.push(0); // Version 1
+    match bbox {
+        Some([xmin, ymin, xmax, ymax]) => {
+            blob.push(0b0000_0011); // Little endian, XY envelope
+            blob.extend_from_slice(&SRS_ID.to_le_bytes());
+            for value in [xmin, xmax, ymin, ymax] {
+                blob.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        None => {
+            blob.push(0b0000_0001); // Little endian, no envelope
+            blob.extend_from_slice(&SRS_ID.to_le_bytes());
+        }
+    }
+    blob.extend_from_slice(&wkb);
+    blob
+}
+
+struct Row {
+    layer: usize,
+    geometry: Option<Vec<u8>>,
+    bbox: Option<[f64; 4]>,
+    tags: String,
+}
+
+impl FeatureSink for GpkgSink {
+    fn write_batch(&mut self, features: &[Feature]) -> Result<()> {
+        let scheme = &self.scheme;
+        let rows: Vec<Option<Row>> = features
+            .par_iter()
+            .map(|feature| {
+                let layer = scheme.layer_of(feature)?;
+                let bbox = feature.bbox();
+                let geometry = feature
+                    .output_geometry()
+                    .map(|geometry| gpkg_geometry_blob(&geometry, bbox));
+                let mut tags: Vec<_> = feature.tags.iter().collect();
+                tags.sort();
+                let tags: serde_json::Map<String, serde_json::Value> = tags
+                    .into_iter()
+                    .map(|(key, value)| (key.clone(), value.clone().into()))
+                    .collect();
+                Some(Row {
+                    layer,
+                    bbox: geometry.as_ref().and(bbox),
+                    geometry,
+                    tags: serde_json::Value::Object(tags).to_string(),
+                })
+            })
+            .collect();
+
+        for (feature, row) in features.iter().zip(rows) {
+            let Some(row) = row else {
+                self.skipped += 1;
+                continue;
+            };
+            let table = &self.tables[row.layer];
+
+            let mut insert = self.connection.prepare_cached(&format!(
+                "INSERT INTO \"{}\" (geom, osm_id, osm_type, tags) VALUES (?1, ?2, ?3, ?4)",
+                table
+            ))?;
+            insert.execute(params![
+                row.geometry,
+                feature.id,
+                feature.element_type.as_str(),
+                row.tags
+            ])?;
+            let fid = self.connection.last_insert_rowid();
+
+            if let Some([xmin, ymin, xmax, ymax]) = row.bbox {
+                let mut index = self.connection.prepare_cached(&format!(
+                    "INSERT INTO \"rtree_{}_geom\" VALUES (?1, ?2, ?3, ?4, ?5)",
+                    table
+                ))?;
+                index.execute(params![fid, xmin, xmax, ymin, ymax])?;
+
+                let extent = self.extents[row.layer].get_or_insert([xmin, ymin, xmax, ymax]);
+                extent[0] = extent[0].min(xmin);
+                extent[1] = extent[1].min(ymin);
+                extent[2] = extent[2].max(xmax);
+                extent[3] = extent[3].max(ymax);
+            }
+
+            self.rows_in_transaction += 1;
+            if self.rows_in_transaction >= ROWS_PER_TRANSACTION {
+                self.connection.execute_batch("COMMIT; BEGIN")?;
+                self.rows_in_transaction = 0;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.connection.is_autocommit() {
+            return Ok(()); // Already finished
+        }
+
+        for (table, extent) in self.tables.iter().zip(&self.extents) {
+            if let Some([xmin, ymin, xmax, ymax]) = extent {
+                self.connection.execute(
+                    "UPDATE gpkg_contents SET min_x = ?2, min_y = ?3, max_x = ?4, max_y = ?5
+                     WHERE table_name = ?1",
+                    params![table, xmin, ymin, xmax, ymax],
+                )?;
+            }
+            create_rtree_triggers(&self.connection, table)?;
+        }
+        self.connection.execute_batch("COMMIT")?;
+
+        if self.skipped > 0 {
+            eprintln!(
+                "Warning: Skipped {} features matching no GeoPackage layer",
+                self.skipped
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+// Element ID selection with --ids and --ids-file, and --with-dependencies
+use crate::blob_index::BlobIndex;
+use crate::feature::ElementType;
+use crate::filter::ElementTypes;
+use crate::options::ConvertOptions;
+use crate::osm::{MemberType, OsmElement};
+use anyhow::{Context, Result};
+use osmpbf::Element;
+use std::fmt;
+use std::path::Path;
+
+/// Element IDs to convert, kept as one sorted, deduplicated list per element
+/// type for binary search membership tests (8 bytes per ID)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IdSet {
+    nodes: Vec<i64>,
+    ways: Vec<i64>,
+    relations: Vec<i64>,
+}
+
+impl IdSet {
+    /// Parse IDs separated by commas or whitespace: `n123,w456,r789`, or
+    /// `node/123 way/456 relation/789` as in osm.org URLs
+    pub fn parse(list: &str) -> Result<Self> {
+        let mut ids = IdSet::default();
+        for token in list
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|token| !token.is_empty())
+        {
+            let (element_type, id) = parse_id(token)?;
+            ids.list_mut(element_type).push(id);
+        }
+        ids.normalize();
+        Ok(ids)
+    }
+
+    /// Read an ID list file: IDs as in `--ids`, any number per line, `#` comments
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read ID file {}", path.display()))?;
+        let mut ids = IdSet::default();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let line_ids = IdSet::parse(line)
+                .with_context(|| format!("{} line {}", path.display(), number + 1))?;
+            ids.extend(line_ids);
+        }
+        ids.normalize();
+        Ok(ids)
+    }
+
+    pub fn contains(&self, element_type: ElementType, id: i64) -> bool {
+        self.list(element_type).binary_search(&id).is_ok()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len() + self.ways.len() + self.relations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Element types with at least one listed ID
+    pub fn element_types(&self) -> ElementTypes {
+        ElementTypes {
+            node: !self.nodes.is_empty(),
+            way: !self.ways.is_empty(),
+            relation: !self.relations.is_empty(),
+        }
+    }
+
+    /// Add every ID of `other`
+    pub fn merge(&mut self, other: IdSet) {
+        self.extend(other);
+        self.normalize();
+    }
+
+    /// Add the elements referenced by listed elements: members of listed
+    /// relations, recursively through sub-relations, and nodes of listed ways
+    pub fn add_referenced(&mut self, input_path: &str) -> Result<()> {
+        let mut blob_index = BlobIndex::new();
+
+        // Sub-relations may come after their parents in the file, so relation
+        // blobs are read again until no new relations turn up
+        loop {
+            let relation_count = self.relations.len();
+            let mut members = IdSet::default();
+            blob_index.for_each_block(
+                input_path,
+                ElementTypes::only(ElementType::Relation),
+                |block| {
+                    for relation in block.groups().flat_map(|group| group.relations()) {
+                        if !self.contains(ElementType::Relation, relation.id()) {
+                            continue;
+                        }
+                        for member in relation.members() {
+                            let element_type = match MemberType::from(member.member_type) {
+                                MemberType::Node => ElementType::Node,
+                                MemberType::Way => ElementType::Way,
+                                MemberType::Relation => ElementType::Relation,
+                            };
+                            members.list_mut(element_type).push(member.member_id);
+                        }
+                    }
+                    Ok(())
+                },
+            )?;
+            self.merge(members);
+            if self.relations.len() == relation_count {
+                break;
+            }
+        }
+
+        if !self.ways.is_empty() {
+            let mut way_nodes = Vec::new();
+            blob_index.for_each_block(
+                input_path,
+                ElementTypes::only(ElementType::Way),
+                |block| {
+                    for way in block.groups().flat_map(|group| group.ways()) {
+                        if self.contains(ElementType::Way, way.id()) {
+                            way_nodes.extend(way.refs());
+                        }
+                    }
+                    Ok(())
+                },
+            )?;
+            self.nodes.append(&mut way_nodes);
+            self.normalize();
+        }
+        Ok(())
+    }
+
+    fn list(&self, element_type: ElementType) -> &Vec<i64> {
+        match element_type {
+            ElementType::Node => &self.nodes,
+            ElementType::Way => &self.ways,
+            ElementType::Relation => &self.relations,
+        }
+    }
+
+    fn list_mut(&mut self, element_type: ElementType) -> &mut Vec<i64> {
+        match element_type {
+            ElementType::Node => &mut self.nodes,
+            ElementType::Way => &mut self.ways,
+            ElementType::Relation => &mut self.relations,
+        }
+    }
+
+    fn extend(&mut self, mut other: IdSet) {
+        self.nodes.append(&mut other.nodes);
+        self.ways.append(&mut other.ways);
+        self.relations.append(&mut other.relations);
+    }
+
+    fn normalize(&mut self) {
+        for list in [&mut self.nodes, &mut self.ways, &mut self.relations] {
+            list.sort_unstable();
+            list.dedup();
+            list.shrink_to_fit();
+        }
+    }
+}
+
+/// Elements referenced by the elements `options` selects: node and way
+/// members of selected relations, and the nodes of selected ways and of those
+/// member ways. Sub-relations are not followed.
+pub fn collect_dependencies(input_path: &str, options: &ConvertOptions) -> Result<IdSet> {
+    let types = options.element_types();
+    let mut blob_index = BlobIndex::new();
+    let mut dependencies = IdSet::default();
+
+    if types.relation {
+        blob_index.for_each_block(
+            input_path,
+            ElementTypes::only(ElementType::Relation),
+            |block| {
+                for relation in block.groups().flat_map(|group| group.relations()) {
+                    let element = OsmElement::from_pbf(Element::Relation(relation));
+                    if options.selects(&element)
+                        && let OsmElement::Relation(relation) = element
+                    {
+                        for member in relation.members {
+                            match member.member_type {
+                                MemberType::Node => dependencies.nodes.push(member.member_id),
+                                MemberType::Way => dependencies.ways.push(member.member_id),
+                                MemberType::Relation => {}
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            },
+        )?;
+        dependencies.normalize();
+    }
+
+    if types.way || !dependencies.ways.is_empty() {
+        let mut way_nodes = Vec::new();
+        blob_index.for_each_block(input_path, ElementTypes::only(ElementType::Way), |block| {
+            for way in block.groups().flat_map(|group| group.ways()) {
+                if dependencies.contains(ElementType::Way, way.id()) {
+                    way_nodes.extend(way.refs());
+                } else if types.way
+                    && let element = OsmElement::from_pbf(Element::Way(way))
+                    && options.selects(&element)
+                    && let OsmElement::Way(way) = element
+                {
+                    way_nodes.extend(way.node_refs);
+                }
+            }
+            Ok(())
+        })?;
+        dependencies.nodes.append(&mut way_nodes);
+        dependencies.normalize();
+    }
+    Ok(dependencies)
+}
+
+impl fmt::Display for IdSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} nodes, {} ways, {} relations",
+            self.nodes.len(),
+            self.ways.len(),
+            self.relations.len()
+        )
+    }
+}
+
+/// `n123`, `w456`, `r789` or `node/123`, `way/456`, `relation/789`
+fn parse_id(token: &str) -> Result<(ElementType, i64)> {
+    let (element_type, number) = match token.split_once('/') {
+        Some(("node", number)) => (ElementType::Node, number),
+        Some(("way", number)) => (ElementType::Way, number),
+        Some(("relation", number)) => (ElementType::Relation, number),
+        Some(_) => anyhow::bail!("unknown element type in '{}'", token),
+        None => match token.split_at(token.chars().next().map_or(0, char::len_utf8)) {
+            ("n", number) => (ElementType::Node, number),
+            ("w", number) => (ElementType::Way, number),
+            ("r", number) => (ElementType::Relation, number),
+            _ => anyhow::bail!("expected n<id>, w<id> or r<id>, got '{}'", token),
+        },
+    };
+    let id = number
+        .parse()
+        .with_context(|| format!("invalid element ID '{}'", token))?;
+    Ok((element_type, id))
+}
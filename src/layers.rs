@@ -0,0 +1,138 @@
+// Assignment of features to named layers for multi-layer outputs
+use crate::feature::{ElementType, Feature};
+use crate::filter::{FilterExpr, TagFilter};
+use crate::options::ConvertOptions;
+use std::fmt;
+use std::str::FromStr;
+
+/// Layer scheme requested with `--layers`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layers {
+    /// `nodes`, `ways` and `relations`
+    #[default]
+    Type,
+    /// One layer per `--tags` OR group
+    Filter,
+}
+
+impl Layers {
+    /// Names accepted by `--layers`
+    pub const NAMES: [&'static str; 2] = ["type", "filter"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Layers::Type => "type",
+            Layers::Filter => "filter",
+        }
+    }
+}
+
+impl fmt::Display for Layers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Layers {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "type" => Ok(Layers::Type),
+            "filter" => Ok(Layers::Filter),
+            _ => anyhow::bail!(
+                "Unknown layer scheme '{}', expected one of: {}",
+                s,
+                Layers::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+/// How features are split into layers (GeoPackage tables, MBTiles layers)
+#[derive(Debug, Clone)]
+pub enum LayerScheme {
+    /// `nodes`, `ways` and `relations`
+    ByType,
+    /// One layer per top-level `--tags` alternative, named after its conditions
+    ByFilter(Vec<(String, FilterExpr)>),
+}
+
+impl LayerScheme {
+    /// Layer scheme for `--layers`: `filter` uses the alternatives of the tag
+    /// filter, `type` (and `filter` without a tag filter) splits by type
+    pub fn from_options(options: &ConvertOptions) -> Self {
+        match (&options.tag_filter, options.layers) {
+            (Some(filter), Layers::Filter) => LayerScheme::by_filter(filter),
+            _ => LayerScheme::ByType,
+        }
+    }
+
+    /// One layer per top-level alternative, or by type when there are none
+    pub fn by_filter(filter: &TagFilter) -> Self {
+        if filter.groups.is_empty() {
+            return LayerScheme::ByType;
+        }
+
+        let mut named: Vec<(String, FilterExpr)> = Vec::new();
+        for (index, group) in filter.groups.iter().enumerate() {
+            let source = group.layer.as_deref().unwrap_or(&group.source);
+            let mut name = layer_name(source).unwrap_or_else(|| format!("group_{}", index + 1));
+            if named.iter().any(|(existing, _)| *existing == name) {
+                name = format!("{}_{}", name, index + 1);
+            }
+            named.push((name, group.expr.clone()));
+        }
+        LayerScheme::ByFilter(named)
+    }
+
+    /// Layer names in index order
+    pub fn names(&self) -> Vec<String> {
+        match self {
+            LayerScheme::ByType => vec![
+                "nodes".to_string(),
+                "ways".to_string(),
+                "relations".to_string(),
+            ],
+            LayerScheme::ByFilter(groups) => groups.iter().map(|(name, _)| name.clone()).collect(),
+        }
+    }
+
+    /// Index of the layer a feature belongs to: its element type, or the
+    /// filter group it matched before its tags were projected
+    pub fn layer_of(&self, feature: &Feature) -> Option<usize> {
+        match self {
+            LayerScheme::ByType => Some(match feature.element_type {
+                ElementType::Node => 0,
+                ElementType::Way => 1,
+                ElementType::Relation => 2,
+            }),
+            LayerScheme::ByFilter(groups) => feature.group.or_else(|| {
+                groups
+                    .iter()
+                    .position(|(_, expr)| expr.matches(feature.element_type, &feature.tags))
+            }),
+        }
+    }
+}
+
+/// Identifier-safe layer name from an alternative's text, e.g. `addr*+name` -> `addr_name`
+/// or `highway=primary` -> `highway_primary`
+fn layer_name(source: &str) -> Option<String> {
+    let mut name = String::new();
+    for c in source.chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c.to_ascii_lowercase());
+        } else if !name.is_empty() && !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    let name = name.trim_end_matches('_').to_string();
+
+    // Names must not start with a digit to be usable as SQL identifiers
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        None
+    } else {
+        Some(name)
+    }
+}
@@ -1,9 +1,57 @@
+pub mod blob_index;
+pub mod boundary;
 pub mod converter;
 pub mod coordinate_storage;
+pub mod csv_output;
+pub mod db_file;
+pub mod feature;
+pub mod filter;
+pub mod filter_file;
+pub mod flatgeobuf_output;
+pub mod geometry;
+pub mod go_compat;
+pub mod gpkg_output;
+pub mod id_filter;
+pub mod layers;
+pub mod mbtiles_output;
+pub mod multipolygon;
+pub mod mvt;
+pub mod node_cache;
+pub mod node_store;
+pub mod options;
 pub mod osm;
+pub mod output;
 pub mod parallel_converter;
+pub mod parquet_output;
+pub mod spatial_filter;
+pub mod tag_projection;
+pub mod way_storage;
 
+pub use blob_index::*;
+pub use boundary::*;
 pub use converter::*;
 pub use coordinate_storage::*;
+pub use csv_output::*;
+pub use db_file::*;
+pub use feature::*;
+pub use filter::*;
+pub use filter_file::*;
+pub use flatgeobuf_output::*;
+pub use geometry::*;
+pub use go_compat::*;
+pub use gpkg_output::*;
+pub use id_filter::*;
+pub use layers::*;
+pub use mbtiles_output::*;
+pub use multipolygon::*;
+pub use mvt::*;
+pub use node_cache::*;
+pub use node_store::*;
+pub use options::*;
 pub use osm::*;
+pub use output::*;
 pub use parallel_converter::*;
+pub use parquet_output::*;
+pub use spatial_filter::*;
+pub use tag_projection::*;
+pub use way_storage::*;
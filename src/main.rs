@@ -1,11 +1,35 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::{Arg, Command};
 use std::path::Path;
 
+mod blob_index;
+mod boundary;
 mod converter;
 mod coordinate_storage;
+mod csv_output;
+mod db_file;
+mod feature;
+mod filter;
+mod filter_file;
+mod flatgeobuf_output;
+mod geometry;
+mod go_compat;
+mod gpkg_output;
+mod id_filter;
+mod layers;
+mod mbtiles_output;
+mod multipolygon;
+mod mvt;
+mod node_cache;
+mod node_store;
+mod options;
 mod osm;
+mod output;
 mod parallel_converter;
+mod parquet_output;
+mod spatial_filter;
+mod tag_projection;
+mod way_storage;
 
 fn main() -> Result<()> {
     let matches = Command::new("pbf2json")
@@ -22,14 +46,98 @@ fn main() -> Result<()> {
                 .short('o')
                 .long("output")
                 .value_name("FILE")
-                .help("Output GeoJSON file (stdout if not specified)"),
+                .help("Output file (stdout if not specified)"),
         )
         .arg(
             Arg::new("tags")
                 .short('t')
                 .long("tags")
                 .value_name("TAGS")
-                .help("Comma-separated list of tags to filter (e.g., highway,building)"),
+                .help("Tag filter expression: , (or), + (and), ! (not), parentheses, ~ regexes, n:/w:/r: type qualifiers (e.g., highway,building+!building=no,n:amenity)"),
+        )
+        .arg(
+            Arg::new("filter-file")
+                .long("filter-file")
+                .value_name("FILE")
+                .conflicts_with("tags")
+                .help("Named filter rules from a .toml, .yaml or .json file, in place of --tags"),
+        )
+        .arg(
+            Arg::new("keep-tags")
+                .long("keep-tags")
+                .value_name("KEYS")
+                .help("Comma-separated tag keys to write, * wildcards allowed (e.g., name,addr*,amenity)"),
+        )
+        .arg(
+            Arg::new("drop-tags")
+                .long("drop-tags")
+                .value_name("KEYS")
+                .help("Comma-separated tag keys to leave out, * wildcards allowed (e.g., source*,note,fixme)"),
+        )
+        .arg(
+            Arg::new("rename-tags")
+                .long("rename-tags")
+                .value_name("RENAMES")
+                .help("Comma-separated from=to tag key renames (e.g., addr:street=street)"),
+        )
+        .arg(
+            Arg::new("types")
+                .long("types")
+                .value_name("TYPES")
+                .help("Comma-separated element types to convert: node, way, relation (default: all)"),
+        )
+        .arg(
+            Arg::new("ids")
+                .long("ids")
+                .value_name("IDS")
+                .help("Convert only these elements, comma-separated (e.g., n123,w456,r789)"),
+        )
+        .arg(
+            Arg::new("ids-file")
+                .long("ids-file")
+                .value_name("FILE")
+                .help("Convert only the elements listed in a file, as in --ids, one or more per line"),
+        )
+        .arg(
+            Arg::new("add-referenced")
+                .long("add-referenced")
+                .action(clap::ArgAction::SetTrue)
+                .help("With --ids, also convert the nodes of listed ways and the members of listed relations, recursively"),
+        )
+        .arg(
+            Arg::new("with-dependencies")
+                .long("with-dependencies")
+                .action(clap::ArgAction::SetTrue)
+                .help("Also convert the nodes and member ways that converted ways and relations reference, even when untagged"),
+        )
+        .arg(
+            Arg::new("bbox")
+                .long("bbox")
+                .value_name("BBOX")
+                .allow_hyphen_values(true)
+                .help("Keep features inside minlon,minlat,maxlon,maxlat (e.g., 12.4,41.8,12.6,42.0)"),
+        )
+        .arg(
+            Arg::new("bbox-mode")
+                .long("bbox-mode")
+                .value_name("MODE")
+                .help("How ways and relations meet --bbox: intersects (any coordinate inside), within (all inside), centroid")
+                .value_parser(spatial_filter::SpatialMode::NAMES)
+                .default_value("intersects"),
+        )
+        .arg(
+            Arg::new("clip-polygon")
+                .long("clip-polygon")
+                .value_name("FILE")
+                .help("Keep features inside an Osmosis .poly or GeoJSON (Multi)Polygon boundary"),
+        )
+        .arg(
+            Arg::new("clip-mode")
+                .long("clip-mode")
+                .value_name("MODE")
+                .help("How ways and relations meet --clip-polygon: intersects, within, centroid")
+                .value_parser(spatial_filter::SpatialMode::NAMES)
+                .default_value("intersects"),
         )
         .arg(
             Arg::new("pretty")
@@ -57,15 +165,76 @@ fn main() -> Result<()> {
                 .long("geometry")
                 .value_name("LEVEL")
                 .help("Geometry computation level: auto, basic, full")
-                .value_parser(["auto", "basic", "full"])
+                .value_parser(options::GeometryLevel::NAMES)
                 .default_value("auto"),
         )
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .value_name("FORMAT")
+                .help("Output format: jsonl (pbf2json records), geojson (FeatureCollection), geojsonseq (RFC 8142), csv, tsv, parquet (GeoParquet), fgb (FlatGeobuf), gpkg (GeoPackage), mbtiles (vector tiles)")
+                .value_parser(output::OutputFormat::NAMES)
+                .default_value("jsonl"),
+        )
+        .arg(
+            Arg::new("columns")
+                .long("columns")
+                .value_name("COLUMNS")
+                .help("Comma-separated csv/tsv columns: id, type, lat, lon or tag keys (default: id,type,lat,lon,name)"),
+        )
+        .arg(
+            Arg::new("layers")
+                .long("layers")
+                .value_name("SCHEME")
+                .help("GeoPackage tables: type (nodes, ways, relations) or filter (one per --tags OR group)")
+                .value_parser(layers::Layers::NAMES)
+                .default_value("type"),
+        )
+        .arg(
+            Arg::new("min-zoom")
+                .long("min-zoom")
+                .value_name("ZOOM")
+                .help("Lowest mbtiles zoom level")
+                .value_parser(clap::value_parser!(u8).range(0..=22))
+                .default_value("0"),
+        )
+        .arg(
+            Arg::new("max-zoom")
+                .long("max-zoom")
+                .value_name("ZOOM")
+                .help("Highest mbtiles zoom level")
+                .value_parser(clap::value_parser!(u8).range(0..=22))
+                .default_value("14"),
+        )
+        .arg(
+            Arg::new("compat")
+                .long("compat")
+                .value_name("TOOL")
+                .help("Write jsonl records in another tool's layout: none, go (pelias/pbf2json)")
+                .value_parser(go_compat::Compat::NAMES)
+                .default_value("none"),
+        )
         .arg(
             Arg::new("temp-db")
                 .long("temp-db")
                 .value_name("PATH")
                 .help("Directory for temporary coordinate database (default: system temp)"),
         )
+        .arg(
+            Arg::new("node-store")
+                .long("node-store")
+                .value_name("STORE")
+                .help("Node coordinate store for geometry: lmdb (B-tree on disk), dense (memory-mapped array indexed by node ID, best for planet files), sparse-mem (in memory, best for small extracts), hash-mem (in memory, exact coordinates)")
+                .value_parser(node_store::NodeStore::NAMES)
+                .default_value("lmdb"),
+        )
+        .arg(
+            Arg::new("node-cache")
+                .long("node-cache")
+                .value_name("PATH")
+                .help("Keep the node coordinate store at PATH and reuse it in later runs on the same input; rebuilt when the input changes"),
+        )
         .arg(
             Arg::new("keep-temp-db")
                 .long("keep-temp-db")
@@ -77,49 +246,249 @@ fn main() -> Result<()> {
     let input_path = matches.get_one::<String>("input").unwrap();
     let output_path = matches.get_one::<String>("output");
     let tag_filter = matches.get_one::<String>("tags");
+    let filter_file = matches.get_one::<String>("filter-file");
+    let types = matches.get_one::<String>("types");
+    let id_list = matches.get_one::<String>("ids");
+    let ids_file = matches.get_one::<String>("ids-file");
+    let add_referenced = matches.get_flag("add-referenced");
+    let with_dependencies = matches.get_flag("with-dependencies");
+    let keep_tags = matches.get_one::<String>("keep-tags");
+    let drop_tags = matches.get_one::<String>("drop-tags");
+    let rename_tags = matches.get_one::<String>("rename-tags");
+    let bbox = matches.get_one::<String>("bbox");
+    let bbox_mode: spatial_filter::SpatialMode =
+        matches.get_one::<String>("bbox-mode").unwrap().parse()?;
+    let clip_polygon = matches.get_one::<String>("clip-polygon");
+    let clip_mode: spatial_filter::SpatialMode =
+        matches.get_one::<String>("clip-mode").unwrap().parse()?;
     let pretty_print = matches.get_flag("pretty");
     let use_parallel = !matches.get_flag("no-parallel");
-    let geometry_level = matches.get_one::<String>("geometry").unwrap();
+    let geometry_level: options::GeometryLevel =
+        matches.get_one::<String>("geometry").unwrap().parse()?;
     let temp_db_path = matches.get_one::<String>("temp-db");
     let keep_temp_db = matches.get_flag("keep-temp-db");
+    let node_cache = matches.get_one::<String>("node-cache");
+    let node_store: node_store::NodeStore =
+        matches.get_one::<String>("node-store").unwrap().parse()?;
+    let format: output::OutputFormat = matches.get_one::<String>("format").unwrap().parse()?;
+    let columns = matches.get_one::<String>("columns");
+    let layers: layers::Layers = matches.get_one::<String>("layers").unwrap().parse()?;
+    let min_zoom = *matches.get_one::<u8>("min-zoom").unwrap();
+    let max_zoom = *matches.get_one::<u8>("max-zoom").unwrap();
+    let compat: go_compat::Compat = matches.get_one::<String>("compat").unwrap().parse()?;
 
     if !Path::new(input_path).exists() {
         anyhow::bail!("Input file does not exist: {}", input_path);
     }
 
-    // Parse tag filter supporting both AND (+) and OR (,) logic
-    // Format: "tag1+tag2,tag3,tag4+tag5" means (tag1 AND tag2) OR tag3 OR (tag4 AND tag5)
-    let tags: Option<Vec<Vec<String>>> = tag_filter.map(|t| {
-        t.split(',') // Split by comma for OR groups
-            .map(|group| {
-                group
-                    .split('+') // Split by plus for AND within each group
-                    .map(|tag| tag.trim().to_string())
-                    .collect::<Vec<String>>()
-            })
-            .collect::<Vec<Vec<String>>>()
-    });
+    // Parse the tag filter expression once, up front
+    // Format: "tag1+!tag2,(tag3,tag4)+tag5" means (tag1 AND NOT tag2) OR ((tag3 OR tag4) AND tag5)
+    let tags = match filter_file {
+        Some(path) => Some(filter_file::load_filter_file(Path::new(path))?),
+        None => tag_filter
+            .map(|t| filter::TagFilter::parse(t))
+            .transpose()
+            .context("Invalid --tags filter")?,
+    };
+
+    let types = types
+        .map(|t| filter::ElementTypes::parse(t))
+        .transpose()
+        .context("Invalid --types")?
+        .unwrap_or_default();
+
+    let mut ids = id_list
+        .map(|list| id_filter::IdSet::parse(list))
+        .transpose()
+        .context("Invalid --ids")?;
+    if let Some(path) = ids_file {
+        let file_ids = id_filter::IdSet::from_file(Path::new(path))?;
+        match &mut ids {
+            Some(ids) => ids.merge(file_ids),
+            None => ids = Some(file_ids),
+        }
+    }
+    if let Some(ids) = &mut ids {
+        if ids.is_empty() {
+            anyhow::bail!("--ids and --ids-file need at least one element ID");
+        }
+        if add_referenced {
+            eprintln!("Adding elements referenced by {}...", ids);
+            ids.add_referenced(input_path)?;
+        }
+        eprintln!("Selected {}", ids);
+    } else if add_referenced {
+        anyhow::bail!("--add-referenced needs --ids or --ids-file");
+    }
+
+    let tag_projection = tag_projection::TagProjection::parse(
+        keep_tags.map(String::as_str),
+        drop_tags.map(String::as_str),
+        rename_tags.map(String::as_str),
+    )
+    .context("Invalid --rename-tags")?;
+
+    let mut spatial_filters = Vec::new();
+    if let Some(bbox) = bbox {
+        let bbox: spatial_filter::BoundingBox = bbox.parse().context("Invalid --bbox")?;
+        spatial_filters.push(spatial_filter::SpatialFilter {
+            region: spatial_filter::Region::Bbox(bbox),
+            mode: bbox_mode,
+        });
+    }
+    if let Some(path) = clip_polygon {
+        let polygon = boundary::PreparedPolygon::from_file(Path::new(path))?;
+        spatial_filters.push(spatial_filter::SpatialFilter {
+            region: spatial_filter::Region::Polygon(std::sync::Arc::new(polygon)),
+            mode: clip_mode,
+        });
+    }
+
+    let mut options = options::ConvertOptions {
+        tag_filter: tags,
+        types,
+        ids,
+        spatial_filters,
+        tag_projection,
+        pretty_print,
+        geometry_level,
+        temp_db_path: temp_db_path.cloned(),
+        keep_temp_db,
+        node_store,
+        node_cache: node_cache.cloned(),
+        format,
+        layers,
+        min_zoom,
+        max_zoom,
+        compat,
+        ..options::ConvertOptions::default()
+    };
+
+    if let Some(columns) = columns {
+        if !matches!(
+            format,
+            output::OutputFormat::Csv | output::OutputFormat::Tsv
+        ) {
+            eprintln!("Warning: --columns only applies to csv and tsv output");
+        }
+        options.columns = csv_output::parse_columns(columns);
+        if options.columns.is_empty() {
+            anyhow::bail!("--columns needs at least one column name");
+        }
+    }
+
+    // Name the option that left no element type to convert
+    if options.element_types().is_empty() {
+        let matched = match &options.tag_filter {
+            Some(filter) => options.types.intersection(filter.element_types()),
+            None => options.types,
+        };
+        if matched.is_empty() {
+            eprintln!("Warning: --types excludes every element type the --tags filter can match");
+        } else {
+            eprintln!(
+                "Warning: --ids lists no element of the types that --types and --tags can match"
+            );
+        }
+    }
+
+    if layers == layers::Layers::Filter && options.tag_filter.is_none() {
+        anyhow::bail!("--layers filter needs a --tags filter or --filter-file");
+    }
+
+    // A rule's layer name only names GeoPackage tables and MBTiles layers
+    if let Some(filter) = &options.tag_filter
+        && let Some(group) = filter.groups.iter().find(|group| group.layer.is_some())
+    {
+        match format {
+            output::OutputFormat::Mbtiles => {}
+            output::OutputFormat::Gpkg if layers == layers::Layers::Filter => {}
+            output::OutputFormat::Gpkg => anyhow::bail!(
+                "filter rule '{}' sets a layer, which -f gpkg only uses with --layers filter",
+                group.source
+            ),
+            _ => anyhow::bail!(
+                "filter rule '{}' sets a layer, which only -f gpkg and -f mbtiles use",
+                group.source
+            ),
+        }
+    }
+
+    if min_zoom > max_zoom {
+        anyhow::bail!("--min-zoom {} is above --max-zoom {}", min_zoom, max_zoom);
+    }
+
+    // Spatial indexes and tiles are built from real way and relation geometries
+    if format.requires_geometry() {
+        match geometry_level {
+            options::GeometryLevel::Basic => anyhow::bail!(
+                "{} output needs way and relation geometry, use --geometry full",
+                format
+            ),
+            options::GeometryLevel::Auto => options.geometry_level = options::GeometryLevel::Full,
+            options::GeometryLevel::Full => {}
+        }
+    }
+
+    // Ways and relations are placed by their resolved coordinates
+    let element_types = options.element_types();
+    if !options.spatial_filters.is_empty() && (element_types.way || element_types.relation) {
+        match options.geometry_level {
+            options::GeometryLevel::Basic => anyhow::bail!(
+                "--bbox and --clip-polygon need way and relation geometry, use --geometry full"
+            ),
+            options::GeometryLevel::Auto => options.geometry_level = options::GeometryLevel::Full,
+            options::GeometryLevel::Full => {}
+        }
+    }
+
+    // Listed ways and relations are extracted with their geometry
+    if options.ids.is_some()
+        && (element_types.way || element_types.relation)
+        && options.geometry_level == options::GeometryLevel::Auto
+    {
+        options.geometry_level = options::GeometryLevel::Full;
+    }
+
+    // The Go tool always denormalizes ways and relations from its node cache
+    if compat == go_compat::Compat::Go {
+        if format != output::OutputFormat::Jsonl {
+            anyhow::bail!("--compat go only applies to jsonl output");
+        }
+        if !use_parallel {
+            anyhow::bail!(
+                "--compat go needs the parallel converter, which keeps records in file order; drop --no-parallel"
+            );
+        }
+        match geometry_level {
+            options::GeometryLevel::Basic => {
+                anyhow::bail!("--compat go needs way and relation geometry")
+            }
+            options::GeometryLevel::Auto => options.geometry_level = options::GeometryLevel::Full,
+            options::GeometryLevel::Full => {}
+        }
+        if options.pretty_print {
+            eprintln!("Warning: --pretty is ignored with --compat go");
+        }
+    }
+
+    // Reference collection pass: what the selected ways and relations need
+    if with_dependencies {
+        if !(element_types.way || element_types.relation) {
+            eprintln!(
+                "Warning: --with-dependencies has no effect when no ways or relations are converted"
+            );
+        }
+        eprintln!("Collecting dependencies of selected ways and relations...");
+        let dependencies = id_filter::collect_dependencies(input_path, &options)?;
+        eprintln!("Dependencies: {}", dependencies);
+        options.dependencies = Some(dependencies);
+    }
 
     if use_parallel {
-        parallel_converter::convert_pbf_to_geojson_parallel(
-            input_path,
-            output_path,
-            tags,
-            pretty_print,
-            geometry_level,
-            temp_db_path,
-            keep_temp_db,
-        )?;
+        parallel_converter::convert_pbf_parallel_with_options(input_path, output_path, &options)?;
     } else {
-        converter::convert_pbf_to_geojson_with_geometry_level(
-            input_path,
-            output_path,
-            tags,
-            pretty_print,
-            geometry_level,
-            temp_db_path,
-            keep_temp_db,
-        )?;
+        converter::convert_pbf_with_options(input_path, output_path, &options)?;
     }
 
     Ok(())
@@ -0,0 +1,368 @@
+// MBTiles output: features clipped into z/x/y vector tiles
+use crate::feature::Feature;
+use crate::layers::LayerScheme;
+use crate::mvt::{GeomType, LayerBuilder, encode_tile, project_geometry, tile_range};
+use crate::output::FeatureSink;
+use anyhow::{Context, Result};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use rayon::prelude::*;
+use rusqlite::{Connection, params};
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::path::Path;
+use tempfile::NamedTempFile;
+
+/// Staged rows or tiles inserted per SQLite transaction
+const ROWS_PER_TRANSACTION: usize = 100_000;
+
+/// Tag keys listed per layer in the `vector_layers` metadata
+const MAX_LAYER_FIELDS: usize = 1000;
+
+/// A feature clipped into every tile it touches
+struct TiledFeature {
+    layer: usize,
+    tags: String,
+    tiles: Vec<(u8, u32, u32, GeomType, Vec<u32>)>,
+    bbox: [f64; 4],
+}
+
+/// MBTiles sink.
+///
+/// Tiles need every feature that touches them, so clipped and encoded tile
+/// geometries are staged in a temporary SQLite database while converting.
+/// `finish` reads them back in tile order, builds each tile's layers and
+/// writes the gzipped MVT tiles to the MBTiles file.
+pub struct MbtilesSink {
+    output_path: String,
+    staging: Connection,
+    _staging_file: NamedTempFile, // Deleted on drop
+    scheme: LayerScheme,
+    layer_names: Vec<String>,
+    layer_fields: Vec<BTreeSet<String>>,
+    min_zoom: u8,
+    max_zoom: u8,
+    bounds: Option<[f64; 4]>,
+    rows_in_transaction: usize,
+    feature_count: usize,
+    skipped: usize,
+    finished: bool,
+}
+
+impl MbtilesSink {
+    pub fn create(
+        output_path: &str,
+        scheme: LayerScheme,
+        min_zoom: u8,
+        max_zoom: u8,
+        temp_dir: Option<&str>,
+    ) -> Result<Self> {
+        let staging_file = match temp_dir {
+            Some(dir) => NamedTempFile::new_in(dir),
+            None => NamedTempFile::new(),
+        }
+        .context("Failed to create tile staging database")?;
+
+        let staging = Connection::open(staging_file.path())?;
+        staging.execute_batch(
+            "PRAGMA journal_mode = OFF;
+             PRAGMA synchronous = OFF;
+             CREATE TABLE features (fid INTEGER PRIMARY KEY, osm_id INTEGER NOT NULL, tags TEXT NOT NULL);
+             CREATE TABLE staged (
+                zoom INTEGER NOT NULL,
+                x INTEGER NOT NULL,
+                y INTEGER NOT NULL,
+                layer INTEGER NOT NULL,
+                fid INTEGER NOT NULL,
+                geom_type INTEGER NOT NULL,
+                geometry BLOB NOT NULL
+             );
+             BEGIN;",
+        )?;
+
+        let layer_names = scheme.names();
+        Ok(MbtilesSink {
+            output_path: output_path.to_string(),
+            staging,
+            _staging_file: staging_file,
+            scheme,
+            layer_fields: vec![BTreeSet::new(); layer_names.len()],
+            layer_names,
+            min_zoom,
+            max_zoom,
+            bounds: None,
+            rows_in_transaction: 0,
+            feature_count: 0,
+            skipped: 0,
+            finished: false,
+        })
+    }
+
+    fn commit_if_full(&mut self) -> Result<()> {
+        self.rows_in_transaction += 1;
+        if self.rows_in_transaction >= ROWS_PER_TRANSACTION {
+            self.staging.execute_batch("COMMIT; BEGIN")?;
+            self.rows_in_transaction = 0;
+        }
+        Ok(())
+    }
+
+    /// Read staged tile features in tile order and write the MBTiles file
+    fn write_tiles(&self) -> Result<usize> {
+        if Path::new(&self.output_path).exists() {
+            std::fs::remove_file(&self.output_path)
+                .with_context(|| format!("Failed to replace output file: {}", self.output_path))?;
+        }
+        let output = Connection::open(&self.output_path)
+            .with_context(|| format!("Failed to create MBTiles file: {}", self.output_path))?;
+        output.execute_batch(
+            "PRAGMA journal_mode = OFF;
+             PRAGMA synchronous = OFF;
+             CREATE TABLE metadata (name TEXT, value TEXT);
+             CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER, tile_data BLOB);
+             CREATE UNIQUE INDEX tile_index ON tiles (zoom_level, tile_column, tile_row);",
+        )?;
+        for (name, value) in self.metadata() {
+            output.execute(
+                "INSERT INTO metadata (name, value) VALUES (?1, ?2)",
+                params![name, value],
+            )?;
+        }
+
+        let mut select = self.staging.prepare(
+            "SELECT s.zoom, s.x, s.y, s.layer, f.osm_id, s.geom_type, s.geometry, f.tags
+             FROM staged s JOIN features f ON f.fid = s.fid
+             ORDER BY s.zoom, s.x, s.y, s.layer",
+        )?;
+        let mut rows = select.query([])?;
+
+        output.execute_batch("BEGIN")?;
+        let mut insert = output.prepare(
+            "INSERT INTO tiles (zoom_level, tile_column, tile_row, tile_data) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        let mut tile_count = 0usize;
+        let mut current: Option<(u8, u32, u32)> = None;
+        let mut layers: Vec<LayerBuilder> = Vec::new();
+
+        let mut flush = |tile: (u8, u32, u32), layers: &[LayerBuilder]| -> Result<()> {
+            let (zoom, x, y) = tile;
+            let tms_row = (1u32 << zoom) - 1 - y; // MBTiles rows count from the south
+            insert.execute(params![zoom, x, tms_row, gzip(&encode_tile(layers))?])?;
+            tile_count += 1;
+            if tile_count.is_multiple_of(ROWS_PER_TRANSACTION) {
+                output.execute_batch("COMMIT; BEGIN")?;
+            }
+            Ok(())
+        };
+
+        while let Some(row) = rows.next()? {
+            let tile: (u8, u32, u32) = (row.get(0)?, row.get(1)?, row.get(2)?);
+            if current != Some(tile) {
+                if let Some(previous) = current {
+                    flush(previous, &layers)?;
+                }
+                current = Some(tile);
+                layers = self
+                    .layer_names
+                    .iter()
+                    .map(|name| LayerBuilder::new(name))
+                    .collect();
+            }
+
+            let layer = row.get::<_, i64>(3)? as usize;
+            let osm_id: i64 = row.get(4)?;
+            let geom_type =
+                GeomType::from_u32(row.get(5)?).context("Invalid staged geometry type")?;
+            let geometry: Vec<u8> = row.get(6)?;
+            let tags: Vec<(String, String)> = serde_json::from_str(&row.get::<_, String>(7)?)?;
+
+            let commands: Vec<u32> = geometry
+                .chunks_exact(4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+                .collect();
+            layers[layer].add_feature(
+                u64::try_from(osm_id).ok(),
+                geom_type,
+                &commands,
+                tags.iter()
+                    .map(|(key, value)| (key.as_str(), value.as_str())),
+            );
+        }
+        if let Some(previous) = current {
+            flush(previous, &layers)?;
+        }
+
+        output.execute_batch("COMMIT")?;
+        Ok(tile_count)
+    }
+
+    /// MBTiles 1.3 metadata rows
+    fn metadata(&self) -> Vec<(&'static str, String)> {
+        let [west, south, east, north] = self.bounds.unwrap_or([-180.0, -85.0511, 180.0, 85.0511]);
+        let vector_layers: Vec<serde_json::Value> = self
+            .layer_names
+            .iter()
+            .zip(&self.layer_fields)
+            .map(|(name, fields)| {
+                let fields: serde_json::Map<String, serde_json::Value> = fields
+                    .iter()
+                    .map(|key| (key.clone(), json!("String")))
+                    .collect();
+                json!({
+                    "id": name,
+                    "fields": fields,
+                    "minzoom": self.min_zoom,
+                    "maxzoom": self.max_zoom
+                })
+            })
+            .collect();
+
+        vec![
+            ("name", "pbf2json".to_string()),
+            ("format", "pbf".to_string()),
+            ("type", "overlay".to_string()),
+            ("minzoom", self.min_zoom.to_string()),
+            ("maxzoom", self.max_zoom.to_string()),
+            (
+                "bounds",
+                format!("{:.7},{:.7},{:.7},{:.7}", west, south, east, north),
+            ),
+            (
+                "center",
+                format!(
+                    "{:.7},{:.7},{}",
+                    (west + east) / 2.0,
+                    (south + north) / 2.0,
+                    self.min_zoom
+                ),
+            ),
+            (
+                "json",
+                json!({ "vector_layers": vector_layers }).to_string(),
+            ),
+        ]
+    }
+}
+
+/// Clip a feature into every tile it touches from `min_zoom` to `max_zoom`
+fn tile_feature(
+    feature: &Feature,
+    scheme: &LayerScheme,
+    min_zoom: u8,
+    max_zoom: u8,
+) -> Option<TiledFeature> {
+    let layer = scheme.layer_of(feature)?;
+    let bbox = feature.bbox()?;
+    let projected = project_geometry(&feature.output_geometry()?)?;
+
+    let mut tiles = Vec::new();
+    for zoom in min_zoom..=max_zoom {
+        let (x_min, y_min, x_max, y_max) = tile_range(bbox, zoom);
+        for x in x_min..=x_max {
+            for y in y_min..=y_max {
+                if let Some(commands) = projected.tile_commands(zoom, x, y) {
+                    tiles.push((zoom, x, y, projected.geom_type, commands));
+                }
+            }
+        }
+    }
+
+    let mut tags: Vec<_> = feature.tags.iter().collect();
+    tags.sort();
+    Some(TiledFeature {
+        layer,
+        tags: serde_json::to_string(&tags).ok()?,
+        tiles,
+        bbox,
+    })
+}
+
+fn gzip(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+impl FeatureSink for MbtilesSink {
+    fn write_batch(&mut self, features: &[Feature]) -> Result<()> {
+        // Clipping and encoding is the expensive part, so it runs in parallel
+        let (scheme, min_zoom, max_zoom) = (&self.scheme, self.min_zoom, self.max_zoom);
+        let tiled: Vec<Option<TiledFeature>> = features
+            .par_iter()
+            .map(|feature| tile_feature(feature, scheme, min_zoom, max_zoom))
+            .collect();
+
+        for (feature, tiled) in features.iter().zip(tiled) {
+            let Some(tiled) = tiled else {
+                self.skipped += 1;
+                continue;
+            };
+
+            self.staging.execute(
+                "INSERT INTO features (osm_id, tags) VALUES (?1, ?2)",
+                params![feature.id, tiled.tags],
+            )?;
+            let fid = self.staging.last_insert_rowid();
+            self.commit_if_full()?;
+
+            for (zoom, x, y, geom_type, commands) in tiled.tiles {
+                let geometry: Vec<u8> = commands.iter().flat_map(|c| c.to_le_bytes()).collect();
+                self.staging
+                    .prepare_cached(
+                        "INSERT INTO staged (zoom, x, y, layer, fid, geom_type, geometry)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    )?
+                    .execute(params![
+                        zoom,
+                        x,
+                        y,
+                        tiled.layer as i64,
+                        fid,
+                        geom_type as u32,
+                        geometry
+                    ])?;
+                self.commit_if_full()?;
+            }
+
+            let fields = &mut self.layer_fields[tiled.layer];
+            for key in feature.tags.keys() {
+                if fields.len() >= MAX_LAYER_FIELDS {
+                    break;
+                }
+                fields.insert(key.clone());
+            }
+
+            let bounds = self.bounds.get_or_insert(tiled.bbox);
+            bounds[0] = bounds[0].min(tiled.bbox[0]);
+            bounds[1] = bounds[1].min(tiled.bbox[1]);
+            bounds[2] = bounds[2].max(tiled.bbox[2]);
+            bounds[3] = bounds[3].max(tiled.bbox[3]);
+            self.feature_count += 1;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        self.staging.execute_batch("COMMIT")?;
+
+        eprintln!(
+            "Building vector tiles for {} features (zoom {}-{})...",
+            self.feature_count, self.min_zoom, self.max_zoom
+        );
+        let tile_count = self.write_tiles()?;
+        eprintln!("Wrote {} tiles to {}", tile_count, self.output_path);
+
+        if self.skipped > 0 {
+            eprintln!(
+                "Warning: Skipped {} features without geometry or matching no tile layer",
+                self.skipped
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+// Ring assembly for multipolygon and boundary relations
+use crate::geometry::{orient_ring, ring_signed_area, to_positions};
+use crate::osm::OsmRelation;
+use geojson::{Geometry, Position, Value};
+use std::collections::HashMap;
+use std::fmt;
+
+/// A way member of a relation with its resolved nodes.
+///
+/// `node_ids` and `coordinates` are parallel slices: `coordinates[i]` is the
+/// `(lat, lon)` of `node_ids[i]`. Nodes that could not be resolved must be
+/// removed from both.
+#[derive(Debug, Clone)]
+pub struct MemberWay<'a> {
+    pub way_id: i64,
+    pub role: &'a str,
+    pub node_ids: &'a [i64],
+    pub coordinates: &'a [(f64, f64)],
+}
+
+/// Reasons a relation could not be assembled into a valid MultiPolygon
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssemblyError {
+    /// Member ways could not be stitched into a closed ring
+    UnclosedRing { way_ids: Vec<i64> },
+    /// An inner ring is not contained by any outer ring
+    InnerWithoutOuter { way_ids: Vec<i64> },
+    /// No usable outer rings were found among the members
+    NoOuterRings,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssemblyError::UnclosedRing { way_ids } => {
+                write!(f, "ring built from ways {:?} does not close", way_ids)
+            }
+            AssemblyError::InnerWithoutOuter { way_ids } => {
+                write!(
+                    f,
+                    "inner ring built from ways {:?} is not inside any outer ring",
+                    way_ids
+                )
+            }
+            AssemblyError::NoOuterRings => write!(f, "relation has no outer rings"),
+        }
+    }
+}
+
+impl std::error::Error for AssemblyError {}
+
+/// Relations whose member ways describe an area: `type=multipolygon` and `type=boundary`
+pub fn is_multipolygon_relation(relation: &OsmRelation) -> bool {
+    relation
+        .tags
+        .get("type")
+        .is_some_and(|t| t == "multipolygon" || t == "boundary")
+}
+
+/// A closed ring stitched together from one or more member ways
+#[derive(Debug, Clone)]
+struct Ring {
+    way_ids: Vec<i64>,
+    positions: Vec<Position>,
+}
+
+/// Assemble member ways into a GeoJSON MultiPolygon.
+///
+/// Ways with role `outer` (or an empty role) and `inner` are stitched by shared
+/// endpoint node IDs into closed rings. Each inner ring is assigned to the
+/// smallest outer ring that contains it. Exterior rings are wound
+/// counter-clockwise and holes clockwise, as RFC 7946 recommends. Members with
+/// any other role are ignored.
+pub fn assemble_multipolygon(members: &[MemberWay]) -> Result<Geometry, AssemblyError> {
+    let outer_members: Vec<&MemberWay> = members
+        .iter()
+        .filter(|m| m.role.is_empty() || m.role == "outer")
+        .collect();
+    let inner_members: Vec<&MemberWay> = members.iter().filter(|m| m.role == "inner").collect();
+
+    let outer_rings = build_rings(&outer_members)?;
+    let inner_rings = build_rings(&inner_members)?;
+
+    if outer_rings.is_empty() {
+        return Err(AssemblyError::NoOuterRings);
+    }
+
+    let mut polygons: Vec<Vec<Vec<Position>>> = outer_rings
+        .iter()
+        .map(|ring| {
+            let mut exterior = ring.positions.clone();
+            orient_ring(&mut exterior, true);
+            vec![exterior]
+        })
+        .collect();
+    let outer_areas: Vec<f64> = outer_rings
+        .iter()
+        .map(|ring| ring_signed_area(&ring.positions).abs())
+        .collect();
+
+    for inner in inner_rings {
+        let container = outer_rings
+            .iter()
+            .enumerate()
+            .filter(|(_, outer)| ring_contains_ring(&outer.positions, &inner.positions))
+            .min_by(|(a, _), (b, _)| outer_areas[*a].total_cmp(&outer_areas[*b]))
+            .map(|(index, _)| index);
+
+        match container {
+            Some(index) => {
+                let mut hole = inner.positions;
+                orient_ring(&mut hole, false);
+                polygons[index].push(hole);
+            }
+            None => {
+                return Err(AssemblyError::InnerWithoutOuter {
+                    way_ids: inner.way_ids,
+                });
+            }
+        }
+    }
+
+    Ok(Geometry::new(Value::MultiPolygon(polygons)))
+}
+
+/// Stitch ways into closed rings by joining them at shared endpoint nodes
+fn build_rings(members: &[&MemberWay]) -> Result<Vec<Ring>, AssemblyError> {
+    let segments: Vec<&MemberWay> = members
+        .iter()
+        .copied()
+        .filter(|m| m.node_ids.len() >= 2 && m.node_ids.len() == m.coordinates.len())
+        .collect();
+
+    // Index segments by both endpoint node IDs for quick lookup
+    let mut endpoints: HashMap<i64, Vec<usize>> = HashMap::new();
+    for (index, segment) in segments.iter().enumerate() {
+        endpoints
+            .entry(segment.node_ids[0])
+            .or_default()
+            .push(index);
+        endpoints
+            .entry(*segment.node_ids.last().unwrap())
+            .or_default()
+            .push(index);
+    }
+
+    let mut used = vec![false; segments.len()];
+    let mut rings = Vec::new();
+
+    for start in 0..segments.len() {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+
+        let mut way_ids = vec![segments[start].way_id];
+        let mut node_ids = segments[start].node_ids.to_vec();
+        let mut coordinates = segments[start].coordinates.to_vec();
+
+        while node_ids.first() != node_ids.last() {
+            let end = *node_ids.last().unwrap();
+            let next = endpoints
+                .get(&end)
+                .and_then(|candidates| candidates.iter().copied().find(|&i| !used[i]));
+
+            let Some(next) = next else {
+                return Err(AssemblyError::UnclosedRing { way_ids });
+            };
+            used[next] = true;
+            way_ids.push(segments[next].way_id);
+
+            let segment = segments[next];
+            if segment.node_ids[0] == end {
+                node_ids.extend_from_slice(&segment.node_ids[1..]);
+                coordinates.extend_from_slice(&segment.coordinates[1..]);
+            } else {
+                node_ids.extend(segment.node_ids.iter().rev().skip(1));
+                coordinates.extend(segment.coordinates.iter().rev().skip(1));
+            }
+        }
+
+        // A closed ring needs at least three distinct nodes
+        if node_ids.len() < 4 {
+            continue;
+        }
+
+        rings.push(Ring {
+            way_ids,
+            positions: to_positions(&coordinates),
+        });
+    }
+
+    Ok(rings)
+}
+
+/// Check whether `inner` lies inside `outer`.
+///
+/// Inner rings frequently share vertices with their outer ring, so a single
+/// vertex test is ambiguous. The ring is considered contained when the
+/// majority of its vertices are inside the outer ring.
+fn ring_contains_ring(outer: &[Position], inner: &[Position]) -> bool {
+    let vertices = &inner[..inner.len() - 1];
+    let inside = vertices
+        .iter()
+        .filter(|p| point_in_ring(p[0], p[1], outer))
+        .count();
+    inside * 2 > vertices.len()
+}
+
+/// Even-odd point-in-polygon test for a closed ring of `[lon, lat]` positions
+pub fn point_in_ring(x: f64, y: f64, ring: &[Position]) -> bool {
+    let mut inside = false;
+    for pair in ring.windows(2) {
+        let (x1, y1) = (pair[0][0], pair[0][1]);
+        let (x2, y2) = (pair[1][0], pair[1][1]);
+        if (y1 > y) != (y2 > y) && x < (x2 - x1) * (y - y1) / (y2 - y1) + x1 {
+            inside = !inside;
+        }
+    }
+    inside
+}
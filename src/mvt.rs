@@ -0,0 +1,491 @@
+// Mapbox Vector Tile encoding: Web Mercator projection, tile clipping and protobuf output
+use geojson::{Geometry, Value};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+/// Tile coordinate extent (MVT default)
+pub const EXTENT: u32 = 4096;
+
+/// Clip buffer around each tile, in tile units
+const BUFFER: f64 = 64.0;
+
+/// Latitude limit of the Web Mercator projection
+const MAX_LATITUDE: f64 = 85.051_128_78;
+
+/// MVT geometry types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeomType {
+    Point = 1,
+    LineString = 2,
+    Polygon = 3,
+}
+
+impl GeomType {
+    pub fn from_u32(value: u32) -> Option<GeomType> {
+        match value {
+            1 => Some(GeomType::Point),
+            2 => Some(GeomType::LineString),
+            3 => Some(GeomType::Polygon),
+            _ => None,
+        }
+    }
+}
+
+/// Project lon/lat to Web Mercator world coordinates in `[0, 1]`, y pointing south
+pub fn project(lon: f64, lat: f64) -> (f64, f64) {
+    let lat = lat.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+    let x = (lon + 180.0) / 360.0;
+    let y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0;
+    (x.clamp(0.0, 1.0), y.clamp(0.0, 1.0))
+}
+
+/// Inclusive `(x_min, y_min, x_max, y_max)` tile range covering a
+/// `[west, south, east, north]` box at a zoom level, including the clip buffer
+pub fn tile_range(bbox: [f64; 4], zoom: u8) -> (u32, u32, u32, u32) {
+    let tiles = (1u64 << zoom) as f64;
+    let buffer = BUFFER / EXTENT as f64;
+    let (west, north) = project(bbox[0], bbox[3]);
+    let (east, south) = project(bbox[2], bbox[1]);
+    let max_tile = (1u64 << zoom) as f64 - 1.0;
+    let tile =
+        |world: f64, offset: f64| (world * tiles + offset).floor().clamp(0.0, max_tile) as u32;
+
+    (
+        tile(west, -buffer),
+        tile(north, -buffer),
+        tile(east, buffer),
+        tile(south, buffer),
+    )
+}
+
+/// A geometry projected to world coordinates, in MVT layout:
+/// points are one part with all points, lines one part per linestring, and
+/// polygons one part per polygon holding its exterior ring followed by holes
+#[derive(Debug, Clone)]
+pub struct ProjectedGeometry {
+    pub geom_type: GeomType,
+    parts: Vec<Vec<Vec<(f64, f64)>>>,
+}
+
+fn project_line(positions: &[Vec<f64>]) -> Vec<(f64, f64)> {
+    positions.iter().map(|p| project(p[0], p[1])).collect()
+}
+
+/// Project a GeoJSON geometry; geometry collections are not supported
+pub fn project_geometry(geometry: &Geometry) -> Option<ProjectedGeometry> {
+    let (geom_type, parts) = match &geometry.value {
+        Value::Point(point) => (
+            GeomType::Point,
+            vec![vec![project_line(std::slice::from_ref(point))]],
+        ),
+        Value::MultiPoint(points) => (GeomType::Point, vec![vec![project_line(points)]]),
+        Value::LineString(line) => (GeomType::LineString, vec![vec![project_line(line)]]),
+        Value::MultiLineString(lines) => (
+            GeomType::LineString,
+            lines.iter().map(|line| vec![project_line(line)]).collect(),
+        ),
+        Value::Polygon(rings) => (
+            GeomType::Polygon,
+            vec![rings.iter().map(|ring| project_line(ring)).collect()],
+        ),
+        Value::MultiPolygon(polygons) => (
+            GeomType::Polygon,
+            polygons
+                .iter()
+                .map(|rings| rings.iter().map(|ring| project_line(ring)).collect())
+                .collect(),
+        ),
+        Value::GeometryCollection(_) => return None,
+    };
+    Some(ProjectedGeometry { geom_type, parts })
+}
+
+impl ProjectedGeometry {
+    /// Clip to tile `z/x/y` and encode as MVT geometry commands, or `None`
+    /// when nothing of the geometry remains in the tile
+    pub fn tile_commands(&self, zoom: u8, x: u32, y: u32) -> Option<Vec<u32>> {
+        let scale = (1u64 << zoom) as f64 * EXTENT as f64;
+        let origin = (x as f64 * EXTENT as f64, y as f64 * EXTENT as f64);
+        let to_tile = |line: &[(f64, f64)]| -> Vec<(f64, f64)> {
+            line.iter()
+                .map(|&(px, py)| (px * scale - origin.0, py * scale - origin.1))
+                .collect()
+        };
+        let (min, max) = (-BUFFER, EXTENT as f64 + BUFFER);
+
+        let mut encoder = CommandEncoder::default();
+        match self.geom_type {
+            GeomType::Point => {
+                let points: Vec<(i32, i32)> = to_tile(&self.parts[0][0])
+                    .into_iter()
+                    .filter(|&(px, py)| px >= min && px <= max && py >= min && py <= max)
+                    .map(quantize)
+                    .collect();
+                encoder.points(&points);
+            }
+            GeomType::LineString => {
+                for part in &self.parts {
+                    for clipped in clip_line(&to_tile(&part[0]), min, max) {
+                        let line = dedupe(clipped.into_iter().map(quantize));
+                        if line.len() >= 2 {
+                            encoder.line(&line);
+                        }
+                    }
+                }
+            }
+            GeomType::Polygon => {
+                for rings in &self.parts {
+                    for (index, ring) in rings.iter().enumerate() {
+                        let clipped = clip_ring(&to_tile(ring), min, max);
+                        let mut ring = dedupe(clipped.into_iter().map(quantize));
+                        if ring.len() > 1 && ring.first() == ring.last() {
+                            ring.pop();
+                        }
+
+                        let area = ring_area(&ring);
+                        if ring.len() < 3 || area == 0 {
+                            if index == 0 {
+                                break; // Exterior vanished, so do its holes
+                            }
+                            continue;
+                        }
+
+                        // Exterior rings have positive area in tile coordinates, holes negative
+                        if (index == 0) != (area > 0) {
+                            ring.reverse();
+                        }
+                        encoder.ring(&ring);
+                    }
+                }
+            }
+        }
+
+        (!encoder.commands.is_empty()).then_some(encoder.commands)
+    }
+}
+
+fn quantize((x, y): (f64, f64)) -> (i32, i32) {
+    (x.round() as i32, y.round() as i32)
+}
+
+fn dedupe(points: impl Iterator<Item = (i32, i32)>) -> Vec<(i32, i32)> {
+    let mut result: Vec<(i32, i32)> = Vec::new();
+    for point in points {
+        if result.last() != Some(&point) {
+            result.push(point);
+        }
+    }
+    result
+}
+
+/// Twice the signed area (surveyor's formula); positive is clockwise with y down
+fn ring_area(ring: &[(i32, i32)]) -> i64 {
+    let mut sum = 0i64;
+    for (i, &(x1, y1)) in ring.iter().enumerate() {
+        let (x2, y2) = ring[(i + 1) % ring.len()];
+        sum += x1 as i64 * y2 as i64 - x2 as i64 * y1 as i64;
+    }
+    sum
+}
+
+/// Clip a segment to the square `[min, max]` (Liang-Barsky)
+fn clip_segment(
+    a: (f64, f64),
+    b: (f64, f64),
+    min: f64,
+    max: f64,
+) -> Option<((f64, f64), (f64, f64))> {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let (mut t0, mut t1) = (0.0f64, 1.0f64);
+
+    for (p, q) in [
+        (-dx, a.0 - min),
+        (dx, max - a.0),
+        (-dy, a.1 - min),
+        (dy, max - a.1),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else {
+            let t = q / p;
+            if p < 0.0 {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
+        }
+    }
+
+    if t0 > t1 {
+        return None;
+    }
+    let at = |t: f64| {
+        if t == 0.0 {
+            a
+        } else if t == 1.0 {
+            b
+        } else {
+            (a.0 + t * dx, a.1 + t * dy)
+        }
+    };
+    Some((at(t0), at(t1)))
+}
+
+/// Clip a linestring to the square `[min, max]`, splitting it where it leaves
+fn clip_line(line: &[(f64, f64)], min: f64, max: f64) -> Vec<Vec<(f64, f64)>> {
+    let mut parts = Vec::new();
+    let mut current: Vec<(f64, f64)> = Vec::new();
+
+    for segment in line.windows(2) {
+        match clip_segment(segment[0], segment[1], min, max) {
+            Some((start, end)) => {
+                if current.last() != Some(&start) {
+                    if current.len() >= 2 {
+                        parts.push(std::mem::take(&mut current));
+                    }
+                    current.clear();
+                    current.push(start);
+                }
+                current.push(end);
+            }
+            None => {
+                if current.len() >= 2 {
+                    parts.push(std::mem::take(&mut current));
+                }
+                current.clear();
+            }
+        }
+    }
+
+    if current.len() >= 2 {
+        parts.push(current);
+    }
+    parts
+}
+
+/// Clip a ring to the square `[min, max]` (Sutherland-Hodgman)
+fn clip_ring(ring: &[(f64, f64)], min: f64, max: f64) -> Vec<(f64, f64)> {
+    let mut output: Vec<(f64, f64)> = ring.to_vec();
+
+    // Each edge: inside test and intersection with the clip line
+    let edges: [(usize, f64, bool); 4] = [
+        (0, min, true),
+        (0, max, false),
+        (1, min, true),
+        (1, max, false),
+    ];
+    for (axis, bound, keep_above) in edges {
+        if output.is_empty() {
+            break;
+        }
+        let input = std::mem::take(&mut output);
+        let coordinate = |p: (f64, f64)| if axis == 0 { p.0 } else { p.1 };
+        let inside = |p: (f64, f64)| {
+            if keep_above {
+                coordinate(p) >= bound
+            } else {
+                coordinate(p) <= bound
+            }
+        };
+        let intersect = |a: (f64, f64), b: (f64, f64)| {
+            let t = (bound - coordinate(a)) / (coordinate(b) - coordinate(a));
+            if axis == 0 {
+                (bound, a.1 + t * (b.1 - a.1))
+            } else {
+                (a.0 + t * (b.0 - a.0), bound)
+            }
+        };
+
+        let mut previous = input[input.len() - 1];
+        for &current in &input {
+            match (inside(current), inside(previous)) {
+                (true, true) => output.push(current),
+                (true, false) => {
+                    output.push(intersect(previous, current));
+                    output.push(current);
+                }
+                (false, true) => output.push(intersect(previous, current)),
+                (false, false) => {}
+            }
+            previous = current;
+        }
+    }
+    output
+}
+
+/// Builds an MVT geometry command stream with delta-encoded parameters
+#[derive(Default)]
+struct CommandEncoder {
+    commands: Vec<u32>,
+    cursor: (i32, i32),
+}
+
+const MOVE_TO: u32 = 1;
+const LINE_TO: u32 = 2;
+const CLOSE_PATH: u32 = 7;
+
+impl CommandEncoder {
+    fn command(&mut self, id: u32, count: usize) {
+        self.commands.push((id & 0x7) | ((count as u32) << 3));
+    }
+
+    fn point(&mut self, (x, y): (i32, i32)) {
+        self.commands.push(zigzag(x - self.cursor.0));
+        self.commands.push(zigzag(y - self.cursor.1));
+        self.cursor = (x, y);
+    }
+
+    fn points(&mut self, points: &[(i32, i32)]) {
+        if points.is_empty() {
+            return;
+        }
+        self.command(MOVE_TO, points.len());
+        for &point in points {
+            self.point(point);
+        }
+    }
+
+    fn line(&mut self, line: &[(i32, i32)]) {
+        self.command(MOVE_TO, 1);
+        self.point(line[0]);
+        self.command(LINE_TO, line.len() - 1);
+        for &point in &line[1..] {
+            self.point(point);
+        }
+    }
+
+    fn ring(&mut self, ring: &[(i32, i32)]) {
+        self.line(ring);
+        self.command(CLOSE_PATH, 1);
+    }
+}
+
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+// Protocol buffer wire format helpers
+const VARINT: u64 = 0;
+const LENGTH_DELIMITED: u64 = 2;
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn write_key(buffer: &mut Vec<u8>, field: u64, wire_type: u64) {
+    write_varint(buffer, (field << 3) | wire_type);
+}
+
+fn write_bytes(buffer: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    write_key(buffer, field, LENGTH_DELIMITED);
+    write_varint(buffer, bytes.len() as u64);
+    buffer.extend_from_slice(bytes);
+}
+
+fn write_packed(buffer: &mut Vec<u8>, field: u64, values: &[u32]) {
+    let mut packed = Vec::with_capacity(values.len() * 2);
+    for &value in values {
+        write_varint(&mut packed, value as u64);
+    }
+    write_bytes(buffer, field, &packed);
+}
+
+/// One MVT layer of a tile, with its key and value dictionaries
+pub struct LayerBuilder {
+    name: String,
+    features: Vec<Vec<u8>>,
+    keys: Vec<String>,
+    key_index: HashMap<String, u32>,
+    values: Vec<String>,
+    value_index: HashMap<String, u32>,
+}
+
+impl LayerBuilder {
+    pub fn new(name: &str) -> Self {
+        LayerBuilder {
+            name: name.to_string(),
+            features: Vec::new(),
+            keys: Vec::new(),
+            key_index: HashMap::new(),
+            values: Vec::new(),
+            value_index: HashMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty()
+    }
+
+    fn intern(strings: &mut Vec<String>, index: &mut HashMap<String, u32>, value: &str) -> u32 {
+        if let Some(&position) = index.get(value) {
+            return position;
+        }
+        let position = strings.len() as u32;
+        strings.push(value.to_string());
+        index.insert(value.to_string(), position);
+        position
+    }
+
+    pub fn add_feature<'a>(
+        &mut self,
+        id: Option<u64>,
+        geom_type: GeomType,
+        geometry: &[u32],
+        tags: impl Iterator<Item = (&'a str, &'a str)>,
+    ) {
+        let mut tag_indices = Vec::new();
+        for (key, value) in tags {
+            tag_indices.push(Self::intern(&mut self.keys, &mut self.key_index, key));
+            tag_indices.push(Self::intern(&mut self.values, &mut self.value_index, value));
+        }
+
+        let mut feature = Vec::new();
+        if let Some(id) = id {
+            write_key(&mut feature, 1, VARINT);
+            write_varint(&mut feature, id);
+        }
+        if !tag_indices.is_empty() {
+            write_packed(&mut feature, 2, &tag_indices);
+        }
+        write_key(&mut feature, 3, VARINT);
+        write_varint(&mut feature, geom_type as u64);
+        write_packed(&mut feature, 4, geometry);
+        self.features.push(feature);
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut layer = Vec::new();
+        write_key(&mut layer, 15, VARINT);
+        write_varint(&mut layer, 2); // MVT version 2
+        write_bytes(&mut layer, 1, self.name.as_bytes());
+        for feature in &self.features {
+            write_bytes(&mut layer, 2, feature);
+        }
+        for key in &self.keys {
+            write_bytes(&mut layer, 3, key.as_bytes());
+        }
+        for value in &self.values {
+            let mut encoded = Vec::new();
+            write_bytes(&mut encoded, 1, value.as_bytes()); // string_value
+            write_bytes(&mut layer, 4, &encoded);
+        }
+        write_key(&mut layer, 5, VARINT);
+        write_varint(&mut layer, EXTENT as u64);
+        layer
+    }
+}
+
+/// Encode the non-empty layers as an (uncompressed) MVT tile
+pub fn encode_tile(layers: &[LayerBuilder]) -> Vec<u8> {
+    let mut tile = Vec::new();
+    for layer in layers.iter().filter(|layer| !layer.is_empty()) {
+        write_bytes(&mut tile, 3, &layer.encode());
+    }
+    tile
+}
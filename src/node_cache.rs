@@ -0,0 +1,239 @@
+// Reusing the node coordinate store across runs with --node-cache
+use crate::coordinate_storage::CoordinateStorage;
+use crate::node_store::{DenseNodeArray, LmdbNodeStore, NODE_FORMAT_VERSION, NodeStore};
+use anyhow::{Context, Result};
+use osmpbf::{BlobDecode, BlobReader};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Bytes hashed from the start of the input: the header and the first blobs
+const HEAD_BYTES: u64 = 16 * 1024 * 1024;
+
+/// What identifies an input file cheaply, without reading all of it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputFingerprint {
+    pub file_size: u64,
+    pub replication_timestamp: Option<i64>, // osmosis_replication_timestamp of the header
+    pub head_crc32: u32,                    // CRC-32 of the first HEAD_BYTES
+    pub node_store: String,                 // --node-store the cache was built with
+    pub format_version: u32,                // NODE_FORMAT_VERSION of the store
+}
+
+impl InputFingerprint {
+    pub fn of(input_path: &str, node_store: NodeStore) -> Result<Self> {
+        let file_size = fs::metadata(input_path)
+            .with_context(|| format!("Failed to read {}", input_path))?
+            .len();
+
+        let mut reader = BlobReader::from_path(input_path).context("Failed to open PBF file")?;
+        let replication_timestamp = match reader.next() {
+            Some(blob) => match blob.context("Failed to read blob")?.decode()? {
+                BlobDecode::OsmHeader(header) => header.osmosis_replication_timestamp(),
+                _ => None,
+            },
+            None => None,
+        };
+
+        let mut head = Vec::new();
+        File::open(input_path)?
+            .take(HEAD_BYTES)
+            .read_to_end(&mut head)?;
+
+        Ok(InputFingerprint {
+            file_size,
+            replication_timestamp,
+            head_crc32: crc32fast::hash(&head),
+            node_store: node_store.to_string(),
+            format_version: NODE_FORMAT_VERSION,
+        })
+    }
+}
+
+/// A node store file kept at a fixed path between runs. Next to it, a
+/// `.fingerprint` file records the input it holds all the nodes of; it is
+/// written once loading completes, so an interrupted load is never reused.
+///
+/// Runs sharing a cache coordinate through a `.lock` file: a run reusing the
+/// cache holds a shared lock, a run rebuilding it an exclusive lock until it
+/// is done with the cache.
+pub struct NodeCache {
+    path: PathBuf,
+    node_store: NodeStore,
+    fingerprint: InputFingerprint,
+    complete: bool,
+    lock: File, // Released when the cache is dropped
+}
+
+impl NodeCache {
+    /// Check the cache at `path` against the input. A cache built from
+    /// another input or store, or never completed, is deleted.
+    pub fn open(path: &Path, input_path: &str, node_store: NodeStore) -> Result<Self> {
+        if node_store.in_memory() {
+            anyhow::bail!("--node-cache needs an on-disk node store: --node-store lmdb or dense");
+        }
+
+        let fingerprint = InputFingerprint::of(input_path, node_store)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let lock_path = suffixed(path, ".lock");
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("Failed to open {}", lock_path.display()))?;
+        let mut cache = NodeCache {
+            path: path.to_path_buf(),
+            node_store,
+            fingerprint,
+            complete: false,
+            lock,
+        };
+
+        // Reuse under a shared lock; rebuild under an exclusive one. Another
+        // run may complete the cache while this one waits for the exclusive
+        // lock, then it is reused after all.
+        loop {
+            cache.wait_for_lock(true)?;
+            if cache.matches() {
+                cache.complete = true;
+                return Ok(cache);
+            }
+            cache.lock.unlock()?;
+            cache.wait_for_lock(false)?;
+            if !cache.matches() {
+                cache.remove()?;
+                return Ok(cache);
+            }
+            cache.lock.unlock()?;
+        }
+    }
+
+    /// Whether the cache already holds every node of the input
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Coordinate storage on the cache file, left in place after the run
+    pub fn storage(&self) -> Result<CoordinateStorage> {
+        if !self.complete {
+            return CoordinateStorage::with_store(self.node_store, Some(&self.path), true);
+        }
+        Ok(match self.node_store {
+            NodeStore::Dense => {
+                CoordinateStorage::from_store(Box::new(DenseNodeArray::open(&self.path)?))
+            }
+            _ => CoordinateStorage::from_store(Box::new(LmdbNodeStore::open(&self.path)?)),
+        })
+    }
+
+    /// Record that every node of the input is stored in `storage`. The store
+    /// is synced first and the fingerprint is written to a temp file and
+    /// renamed, so a crash never leaves a fingerprint over a partial store.
+    pub fn mark_complete(&mut self, storage: &CoordinateStorage) -> Result<()> {
+        if self.complete {
+            return Ok(());
+        }
+        storage.sync()?;
+
+        let fingerprint_path = self.fingerprint_path();
+        let temp_path = suffixed(&fingerprint_path, ".tmp");
+        let json = serde_json::to_string_pretty(&self.fingerprint)?;
+        let write = || -> Result<()> {
+            let mut file = File::create(&temp_path)?;
+            file.write_all(json.as_bytes())?;
+            file.sync_all()?;
+            fs::rename(&temp_path, &fingerprint_path)?;
+            if let Some(parent) = fingerprint_path.parent() {
+                File::open(parent)?.sync_all()?;
+            }
+            Ok(())
+        };
+        write().with_context(|| {
+            format!(
+                "Failed to write node cache fingerprint {}",
+                fingerprint_path.display()
+            )
+        })?;
+        self.complete = true;
+        Ok(())
+    }
+
+    /// Take the shared or the exclusive lock, saying so when another run holds it
+    fn wait_for_lock(&self, shared: bool) -> Result<()> {
+        let attempt = if shared {
+            self.lock.try_lock_shared()
+        } else {
+            self.lock.try_lock()
+        };
+        match attempt {
+            Ok(()) => Ok(()),
+            Err(TryLockError::WouldBlock) => {
+                eprintln!(
+                    "Waiting for another run using node cache {}...",
+                    self.path.display()
+                );
+                if shared {
+                    self.lock.lock_shared()?;
+                } else {
+                    self.lock.lock()?;
+                }
+                Ok(())
+            }
+            Err(TryLockError::Error(e)) => {
+                Err(e).with_context(|| format!("Failed to lock node cache {}", self.path.display()))
+            }
+        }
+    }
+
+    /// Whether the cache holds every node of the input
+    fn matches(&self) -> bool {
+        self.path.exists() && self.stored_fingerprint().as_ref() == Some(&self.fingerprint)
+    }
+
+    fn stored_fingerprint(&self) -> Option<InputFingerprint> {
+        let json = fs::read_to_string(self.fingerprint_path()).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+
+    /// Delete the fingerprint first, then the store and the LMDB lock file
+    fn remove(&self) -> Result<()> {
+        for path in [
+            self.fingerprint_path(),
+            self.path.clone(),
+            lock_path(&self.path),
+        ] {
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(e).with_context(|| {
+                        format!("Failed to remove stale node cache {}", path.display())
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn fingerprint_path(&self) -> PathBuf {
+        suffixed(&self.path, ".fingerprint")
+    }
+}
+
+/// LMDB's lock file for a `NO_SUB_DIR` environment
+fn lock_path(path: &Path) -> PathBuf {
+    suffixed(path, "-lock")
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
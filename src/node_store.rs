@@ -0,0 +1,504 @@
+// Node location stores behind CoordinateStorage, selected with --node-store
+use crate::db_file::open_lmdb_env;
+use anyhow::{Context, Result};
+use lmdb::{Cursor, Database, DatabaseFlags, Environment, Transaction, WriteFlags};
+use memmap2::MmapMut;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Version of the on-disk node formats, checked when a store is reopened.
+/// Version 1 stored LMDB locations as two `f64`; version 2 uses fixed point.
+pub const NODE_FORMAT_VERSION: u32 = 2;
+
+/// LMDB "meta" database key of the format version
+const FORMAT_VERSION_KEY: &[u8] = b"format_version";
+
+/// Nodes moved per transaction when migrating a version 1 LMDB store
+const MIGRATION_CHUNK: usize = 1 << 20;
+
+/// Fixed-point units per degree: 1e-7 degrees, the OSM coordinate precision
+const FIXED_SCALE: f64 = 1e7;
+
+/// Bytes per node in the dense array: latitude and longitude as 32-bit fixed point
+const SLOT_SIZE: usize = 8;
+
+/// Initial dense file size, 1M node slots. The file is sparse, so untouched
+/// ranges take no disk space.
+const INITIAL_DENSE_LEN: u64 = (1 << 20) * SLOT_SIZE as u64;
+
+/// Storage for node `(lat, lon)` locations, filled in bulk during the
+/// coordinate collection phase and read concurrently while building geometry.
+/// Implement it to back `CoordinateStorage` with your own store.
+pub trait NodeLocationStore: Send + Sync {
+    /// Store node `(id, lat, lon)` locations. Calls may come from several
+    /// threads and store a node again, in which case the last location wins.
+    fn put_nodes(&self, nodes: &[(i64, f64, f64)]) -> Result<()>;
+
+    /// Locations of `node_ids`, `None` for nodes never stored
+    fn get_nodes(&self, node_ids: &[i64]) -> Result<Vec<Option<(f64, f64)>>>;
+
+    /// Flush pending writes to durable storage
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called once all nodes are stored, before the lookups of the geometry phase
+    fn finalize(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Built-in stores, by their `--node-store` names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NodeStore {
+    /// LMDB B-tree keyed by node ID
+    #[default]
+    Lmdb,
+    /// Memory-mapped sparse file indexed directly by node ID
+    Dense,
+    /// In-memory array of (ID, location) pairs, sorted once loaded
+    SparseMem,
+    /// In-memory `HashMap` keeping the exact `f64` locations
+    HashMem,
+}
+
+impl NodeStore {
+    /// Names accepted by `--node-store`
+    pub const NAMES: [&'static str; 4] = ["lmdb", "dense", "sparse-mem", "hash-mem"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NodeStore::Lmdb => "lmdb",
+            NodeStore::Dense => "dense",
+            NodeStore::SparseMem => "sparse-mem",
+            NodeStore::HashMem => "hash-mem",
+        }
+    }
+
+    /// Whether the store lives in memory, with nothing written to disk
+    pub fn in_memory(&self) -> bool {
+        matches!(self, NodeStore::SparseMem | NodeStore::HashMem)
+    }
+}
+
+impl fmt::Display for NodeStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for NodeStore {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "lmdb" => Ok(NodeStore::Lmdb),
+            "dense" => Ok(NodeStore::Dense),
+            "sparse-mem" => Ok(NodeStore::SparseMem),
+            "hash-mem" => Ok(NodeStore::HashMem),
+            _ => anyhow::bail!(
+                "Unknown node store '{}', expected one of: {}",
+                s,
+                NodeStore::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+/// Degrees to 1e-7 degree fixed point
+pub fn to_fixed(degrees: f64) -> i32 {
+    (degrees * FIXED_SCALE).round() as i32
+}
+
+/// 1e-7 degree fixed point to degrees
+pub fn from_fixed(fixed: i32) -> f64 {
+    fixed as f64 / FIXED_SCALE
+}
+
+/// Dense slot encoding with the sign bit flipped, so the all-zero bytes of
+/// never written slots decode to `i32::MIN`, outside any valid coordinate
+fn encode_slot(fixed: i32) -> [u8; 4] {
+    ((fixed as u32) ^ 0x8000_0000).to_le_bytes()
+}
+
+fn decode_slot(bytes: [u8; 4]) -> i32 {
+    (u32::from_le_bytes(bytes) ^ 0x8000_0000) as i32
+}
+
+/// Node locations in an LMDB B-tree keyed by big-endian node ID, with the
+/// location as two big-endian 32-bit fixed point values (8 bytes per node).
+///
+/// Batches in ascending key order are appended (`MDB_APPEND`), filling pages
+/// sequentially instead of splitting them, as long as each batch continues
+/// after the keys already stored. Once a batch doesn't, as for input not
+/// sorted by ID, all later batches use regular inserts.
+pub struct LmdbNodeStore {
+    env: Environment,
+    db: Database,
+    append: AtomicBool,
+}
+
+impl LmdbNodeStore {
+    /// Open or create the store at `path`, migrating a store in an older node
+    /// format. Stores in a newer format are rejected.
+    pub fn open(path: &Path) -> Result<Self> {
+        // 500GB max map size for planet files; "nodes" and "meta" databases
+        let env = open_lmdb_env(path, 500 * 1024 * 1024 * 1024, 2)?;
+
+        let db = env.create_db(Some("nodes"), DatabaseFlags::empty())?;
+        let meta = env.create_db(Some("meta"), DatabaseFlags::empty())?;
+        let store = LmdbNodeStore {
+            env,
+            db,
+            append: AtomicBool::new(true),
+        };
+
+        match store.format_version(meta)? {
+            Some(NODE_FORMAT_VERSION) => {}
+            Some(version) => anyhow::bail!(
+                "{} holds nodes in format version {}, but this build reads version {}; delete it to rebuild",
+                path.display(),
+                version,
+                NODE_FORMAT_VERSION
+            ),
+            None => {
+                let migrated = store.migrate_f64_nodes()?;
+                if migrated > 0 {
+                    eprintln!(
+                        "Migrated {} nodes in {} to node format version {}",
+                        migrated,
+                        path.display(),
+                        NODE_FORMAT_VERSION
+                    );
+                }
+                let mut txn = store.env.begin_rw_txn()?;
+                txn.put(
+                    meta,
+                    &FORMAT_VERSION_KEY,
+                    &NODE_FORMAT_VERSION.to_be_bytes(),
+                    WriteFlags::empty(),
+                )?;
+                txn.commit()?;
+            }
+        }
+        Ok(store)
+    }
+
+    fn format_version(&self, meta: Database) -> Result<Option<u32>> {
+        let txn = self.env.begin_ro_txn()?;
+        match txn.get(meta, &FORMAT_VERSION_KEY) {
+            Ok(value) => {
+                let bytes: [u8; 4] = value
+                    .try_into()
+                    .context("Invalid node format version in LMDB store")?;
+                Ok(Some(u32::from_be_bytes(bytes)))
+            }
+            Err(lmdb::Error::NotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Move nodes of format version 1, two `f64` in the unnamed database, to
+    /// the "nodes" database in fixed point, a chunk per transaction
+    fn migrate_f64_nodes(&self) -> Result<u64> {
+        let main = self.env.open_db(None)?;
+        let mut migrated = 0;
+        loop {
+            let chunk: Vec<(Vec<u8>, f64, f64)> = {
+                let txn = self.env.begin_ro_txn()?;
+                let mut cursor = txn.open_ro_cursor(main)?;
+                cursor
+                    .iter()
+                    .filter(|(key, value)| key.len() == 8 && value.len() == 16)
+                    .take(MIGRATION_CHUNK)
+                    .map(|(key, value)| {
+                        let lat = f64::from_be_bytes(value[0..8].try_into().unwrap());
+                        let lon = f64::from_be_bytes(value[8..16].try_into().unwrap());
+                        (key.to_vec(), lat, lon)
+                    })
+                    .collect()
+            };
+            if chunk.is_empty() {
+                return Ok(migrated);
+            }
+
+            let mut txn = self.env.begin_rw_txn()?;
+            for (key, lat, lon) in &chunk {
+                txn.put(
+                    self.db,
+                    key,
+                    &encode_location(*lat, *lon),
+                    WriteFlags::empty(),
+                )?;
+                txn.del(main, key, None)?;
+            }
+            txn.commit()?;
+            migrated += chunk.len() as u64;
+        }
+    }
+
+    /// Store `nodes` in one write transaction
+    fn write(&self, nodes: &[(i64, f64, f64)], flags: WriteFlags) -> lmdb::Result<()> {
+        let mut txn = self.env.begin_rw_txn()?;
+        for &(node_id, lat, lon) in nodes {
+            let key = node_id.to_be_bytes();
+            txn.put(self.db, &key, &encode_location(lat, lon), flags)?;
+        }
+        txn.commit()
+    }
+}
+
+/// Whether node IDs strictly ascend in the byte order of their big-endian keys
+fn in_key_order(nodes: &[(i64, f64, f64)]) -> bool {
+    nodes
+        .windows(2)
+        .all(|pair| (pair[0].0 as u64) < (pair[1].0 as u64))
+}
+
+/// LMDB value of a location: latitude and longitude as big-endian fixed point
+fn encode_location(lat: f64, lon: f64) -> [u8; 8] {
+    let mut value = [0; 8];
+    value[0..4].copy_from_slice(&to_fixed(lat).to_be_bytes());
+    value[4..8].copy_from_slice(&to_fixed(lon).to_be_bytes());
+    value
+}
+
+fn decode_location(value: &[u8]) -> Option<(f64, f64)> {
+    let value: [u8; 8] = value.try_into().ok()?;
+    let lat = i32::from_be_bytes(value[0..4].try_into().unwrap());
+    let lon = i32::from_be_bytes(value[4..8].try_into().unwrap());
+    Some((from_fixed(lat), from_fixed(lon)))
+}
+
+impl NodeLocationStore for LmdbNodeStore {
+    /// One write transaction per call
+    fn put_nodes(&self, nodes: &[(i64, f64, f64)]) -> Result<()> {
+        if self.append.load(Ordering::Relaxed) && in_key_order(nodes) {
+            match self.write(nodes, WriteFlags::APPEND) {
+                // A key not past the last one stored; the transaction is aborted
+                Err(lmdb::Error::KeyExist) => self.append.store(false, Ordering::Relaxed),
+                result => return Ok(result?),
+            }
+        }
+        Ok(self.write(nodes, WriteFlags::empty())?)
+    }
+
+    /// One read transaction per call
+    fn get_nodes(&self, node_ids: &[i64]) -> Result<Vec<Option<(f64, f64)>>> {
+        let txn = self.env.begin_ro_txn()?;
+        let mut result = Vec::with_capacity(node_ids.len());
+
+        for &node_id in node_ids {
+            let key = node_id.to_be_bytes();
+            match txn.get(self.db, &key) {
+                Ok(value) => result.push(decode_location(value)), // None if invalid
+                Err(lmdb::Error::NotFound) => result.push(None),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn sync(&self) -> Result<()> {
+        self.env.sync(true)?;
+        Ok(())
+    }
+}
+
+/// Node locations in a memory-mapped file with one 8-byte slot per node ID,
+/// like osmium's `dense_mmap_array`. The file grows as larger IDs arrive and
+/// stays sparse, so disk use follows the stored ID range, not the file length.
+pub struct DenseNodeArray {
+    file: File,
+    map: RwLock<MmapMut>,
+}
+
+impl DenseNodeArray {
+    /// Create an empty array, truncating any file at `path`
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .with_context(|| format!("Failed to create dense node file {}", path.display()))?;
+        file.set_len(INITIAL_DENSE_LEN)?;
+        Self::map(file)
+    }
+
+    /// Open an array written by an earlier run, keeping its nodes
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Failed to open dense node file {}", path.display()))?;
+        Self::map(file)
+    }
+
+    fn map(file: File) -> Result<Self> {
+        // SAFETY: the file is private to this store and only accessed through the map
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        Ok(DenseNodeArray {
+            file,
+            map: RwLock::new(map),
+        })
+    }
+}
+
+impl NodeLocationStore for DenseNodeArray {
+    fn put_nodes(&self, nodes: &[(i64, f64, f64)]) -> Result<()> {
+        let Some(max_id) = nodes.iter().map(|&(id, _, _)| id).max() else {
+            return Ok(());
+        };
+        if let Some(&(id, _, _)) = nodes.iter().find(|&&(id, _, _)| id < 0) {
+            anyhow::bail!(
+                "Node {} has a negative ID, which the dense node store can't index; use --node-store lmdb",
+                id
+            );
+        }
+
+        let mut map = self.map.write().unwrap();
+        let needed = (max_id as u64 + 1) * SLOT_SIZE as u64;
+        if needed > map.len() as u64 {
+            let len = needed.max(map.len() as u64 * 2);
+            map.flush_async()?;
+            self.file.set_len(len)?;
+            // SAFETY: as in `create`; the old map is replaced while holding the write lock
+            *map = unsafe { MmapMut::map_mut(&self.file)? };
+        }
+
+        for &(id, lat, lon) in nodes {
+            let offset = id as usize * SLOT_SIZE;
+            map[offset..offset + 4].copy_from_slice(&encode_slot(to_fixed(lat)));
+            map[offset + 4..offset + 8].copy_from_slice(&encode_slot(to_fixed(lon)));
+        }
+        Ok(())
+    }
+
+    fn get_nodes(&self, node_ids: &[i64]) -> Result<Vec<Option<(f64, f64)>>> {
+        let map = self.map.read().unwrap();
+        Ok(node_ids
+            .iter()
+            .map(|&id| {
+                let offset = usize::try_from(id).ok()?.checked_mul(SLOT_SIZE)?;
+                let slot = map.get(offset..offset + SLOT_SIZE)?;
+                let lat = decode_slot(slot[0..4].try_into().unwrap());
+                let lon = decode_slot(slot[4..8].try_into().unwrap());
+                (lat != i32::MIN).then(|| (from_fixed(lat), from_fixed(lon)))
+            })
+            .collect())
+    }
+
+    /// Write the mapped slots and the file length to disk, waiting for both
+    fn sync(&self) -> Result<()> {
+        self.map.read().unwrap().flush()?;
+        self.file.sync_all()?;
+        Ok(())
+    }
+}
+
+/// Node locations as an in-memory array of `(id, lat, lon)` entries (16 bytes
+/// per node), like osmium's `sparse_mem_array`. Entries are appended while
+/// loading and sorted by ID before the first lookup.
+#[derive(Default)]
+pub struct SparseNodeArray {
+    entries: RwLock<SparseEntries>,
+}
+
+#[derive(Default)]
+struct SparseEntries {
+    nodes: Vec<(i64, i32, i32)>,
+    sorted: bool,
+}
+
+impl SparseNodeArray {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sort by ID so lookups can binary search; a node stored twice keeps its
+    /// last location
+    fn sort(&self) {
+        let mut entries = self.entries.write().unwrap();
+        if entries.sorted {
+            return;
+        }
+        entries.nodes.sort_by_key(|&(id, _, _)| id);
+        entries.nodes.reverse();
+        entries.nodes.dedup_by_key(|&mut (id, _, _)| id);
+        entries.nodes.reverse();
+        entries.nodes.shrink_to_fit();
+        entries.sorted = true;
+    }
+}
+
+impl NodeLocationStore for SparseNodeArray {
+    fn put_nodes(&self, nodes: &[(i64, f64, f64)]) -> Result<()> {
+        let mut entries = self.entries.write().unwrap();
+        entries.nodes.extend(
+            nodes
+                .iter()
+                .map(|&(id, lat, lon)| (id, to_fixed(lat), to_fixed(lon))),
+        );
+        entries.sorted = false;
+        Ok(())
+    }
+
+    /// Sorts first if nodes were stored since `finalize`
+    fn get_nodes(&self, node_ids: &[i64]) -> Result<Vec<Option<(f64, f64)>>> {
+        if !self.entries.read().unwrap().sorted {
+            self.sort();
+        }
+        let entries = self.entries.read().unwrap();
+        Ok(node_ids
+            .iter()
+            .map(|&id| {
+                let index = entries
+                    .nodes
+                    .binary_search_by_key(&id, |&(node_id, _, _)| node_id)
+                    .ok()?;
+                let (_, lat, lon) = entries.nodes[index];
+                Some((from_fixed(lat), from_fixed(lon)))
+            })
+            .collect())
+    }
+
+    fn finalize(&self) -> Result<()> {
+        self.sort();
+        Ok(())
+    }
+}
+
+/// Node locations in an in-memory `HashMap`, keeping the exact `f64` values.
+/// Handy for tests and small inputs; about 50 bytes per node.
+#[derive(Default)]
+pub struct HashMapNodeStore {
+    nodes: RwLock<HashMap<i64, (f64, f64)>>,
+}
+
+impl HashMapNodeStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl NodeLocationStore for HashMapNodeStore {
+    fn put_nodes(&self, nodes: &[(i64, f64, f64)]) -> Result<()> {
+        let mut map = self.nodes.write().unwrap();
+        map.extend(nodes.iter().map(|&(id, lat, lon)| (id, (lat, lon))));
+        Ok(())
+    }
+
+    fn get_nodes(&self, node_ids: &[i64]) -> Result<Vec<Option<(f64, f64)>>> {
+        let map = self.nodes.read().unwrap();
+        Ok(node_ids.iter().map(|id| map.get(id).copied()).collect())
+    }
+}
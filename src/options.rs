@@ -0,0 +1,182 @@
+// Conversion options shared by the sequential and parallel converters
+use crate::csv_output::{Column, DEFAULT_COLUMNS};
+use crate::feature::Feature;
+use crate::filter::{ElementTypes, TagFilter};
+use crate::go_compat::Compat;
+use crate::id_filter::IdSet;
+use crate::layers::Layers;
+use crate::node_store::NodeStore;
+use crate::osm::OsmElement;
+use crate::output::OutputFormat;
+use crate::spatial_filter::SpatialFilter;
+use crate::tag_projection::TagProjection;
+use std::fmt;
+use std::str::FromStr;
+
+/// Whether way and relation geometry is computed (`--geometry`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GeometryLevel {
+    /// Full geometry for files up to 1GB, basic above
+    #[default]
+    Auto,
+    /// Streaming without a node store: ways and relations get no geometry
+    Basic,
+    /// Node and way stores on disk for complete way and relation geometry
+    Full,
+}
+
+impl GeometryLevel {
+    /// Names accepted by `--geometry`
+    pub const NAMES: [&'static str; 3] = ["auto", "basic", "full"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GeometryLevel::Auto => "auto",
+            GeometryLevel::Basic => "basic",
+            GeometryLevel::Full => "full",
+        }
+    }
+}
+
+impl fmt::Display for GeometryLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for GeometryLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "auto" => Ok(GeometryLevel::Auto),
+            "basic" => Ok(GeometryLevel::Basic),
+            "full" => Ok(GeometryLevel::Full),
+            _ => anyhow::bail!(
+                "Unknown geometry level '{}', expected one of: {}",
+                s,
+                GeometryLevel::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+/// Settings for a PBF conversion run
+#[derive(Debug, Clone)]
+pub struct ConvertOptions {
+    pub tag_filter: Option<TagFilter>,
+    pub types: ElementTypes,                 // --types
+    pub ids: Option<IdSet>,                  // --ids, --ids-file
+    pub dependencies: Option<IdSet>,         // --with-dependencies, from collect_dependencies
+    pub spatial_filters: Vec<SpatialFilter>, // --bbox, --clip-polygon; all must pass
+    pub tag_projection: TagProjection,       // --keep-tags, --drop-tags, --rename-tags
+    pub pretty_print: bool,
+    pub geometry_level: GeometryLevel, // --geometry
+    pub temp_db_path: Option<String>,
+    pub keep_temp_db: bool,
+    pub node_store: NodeStore,      // --node-store
+    pub node_cache: Option<String>, // --node-cache
+    pub format: OutputFormat,
+    pub columns: Vec<Column>, // CSV/TSV columns
+    pub layers: Layers,       // --layers: GeoPackage tables and MBTiles layers
+    pub min_zoom: u8,         // MBTiles zoom range
+    pub max_zoom: u8,
+    pub compat: Compat, // --compat: layout of jsonl records
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        ConvertOptions {
+            tag_filter: None,
+            types: ElementTypes::ALL,
+            ids: None,
+            dependencies: None,
+            spatial_filters: Vec::new(),
+            tag_projection: TagProjection::default(),
+            pretty_print: false,
+            geometry_level: GeometryLevel::default(),
+            temp_db_path: None,
+            keep_temp_db: false,
+            node_store: NodeStore::default(),
+            node_cache: None,
+            format: OutputFormat::default(),
+            columns: DEFAULT_COLUMNS.iter().map(|c| Column::parse(c)).collect(),
+            layers: Layers::default(),
+            min_zoom: 0,
+            max_zoom: 14,
+            compat: Compat::default(),
+        }
+    }
+}
+
+impl ConvertOptions {
+    /// Element types to convert: `--types` narrowed by the filter's type
+    /// qualifiers and the types of listed IDs, plus the types of dependencies
+    pub fn element_types(&self) -> ElementTypes {
+        let types = match &self.tag_filter {
+            Some(filter) => self.types.intersection(filter.element_types()),
+            None => self.types,
+        };
+        let types = match &self.ids {
+            Some(ids) => types.intersection(ids.element_types()),
+            None => types,
+        };
+        match &self.dependencies {
+            Some(dependencies) => types.union(dependencies.element_types()),
+            None => types,
+        }
+    }
+
+    /// Whether an element is converted. Dependencies always are. Otherwise
+    /// untagged elements are skipped unless listed in `ids`; with `ids`,
+    /// unlisted elements are always skipped.
+    pub fn selects(&self, element: &OsmElement) -> bool {
+        if self
+            .dependencies
+            .as_ref()
+            .is_some_and(|dependencies| dependencies.contains(element.element_type(), element.id()))
+        {
+            return true;
+        }
+
+        let listed = self
+            .ids
+            .as_ref()
+            .map(|ids| ids.contains(element.element_type(), element.id()));
+        match listed {
+            Some(false) => return false,
+            None if element.tags().is_empty() => return false,
+            _ => {}
+        }
+        self.tag_filter
+            .as_ref()
+            .is_none_or(|filter| filter.matches(element.element_type(), element.tags()))
+    }
+
+    /// Apply the spatial filters to a converted feature, then record the
+    /// filter group it matched, keep only that group's `keep_tags` and apply
+    /// the tag projection
+    pub fn finish_feature(&self, mut feature: Feature) -> Option<Feature> {
+        if !self
+            .spatial_filters
+            .iter()
+            .all(|filter| filter.matches(&feature))
+        {
+            return None;
+        }
+
+        if let Some(filter) = &self.tag_filter {
+            feature.group = filter.group_of(feature.element_type, &feature.tags);
+            let keep_tags = feature
+                .group
+                .and_then(|group| filter.groups[group].keep_tags.as_ref());
+            if let Some(keep_tags) = keep_tags {
+                feature
+                    .tags
+                    .retain(|key, _| keep_tags.iter().any(|pattern| pattern.matches(key)));
+            }
+        }
+        self.tag_projection.apply(&mut feature.tags);
+        Some(feature)
+    }
+}
@@ -1,3 +1,6 @@
+use crate::feature::ElementType;
+use crate::filter::{TagCondition, TagFilter};
+use osmpbf::Element;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -30,6 +33,16 @@ pub enum MemberType {
     Relation,
 }
 
+impl From<osmpbf::RelMemberType> for MemberType {
+    fn from(member_type: osmpbf::RelMemberType) -> Self {
+        match member_type {
+            osmpbf::RelMemberType::Node => MemberType::Node,
+            osmpbf::RelMemberType::Way => MemberType::Way,
+            osmpbf::RelMemberType::Relation => MemberType::Relation,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OsmRelation {
     pub id: i64,
@@ -45,7 +58,47 @@ pub enum OsmElement {
 }
 
 impl OsmElement {
-    #[allow(dead_code)]
+    /// Copy a decoded PBF element, with its tags and refs or members
+    pub fn from_pbf(element: Element) -> Self {
+        fn owned_tags<'a>(
+            tags: impl Iterator<Item = (&'a str, &'a str)>,
+        ) -> HashMap<String, String> {
+            tags.map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        }
+
+        match element {
+            Element::Node(node) => OsmElement::Node(OsmNode {
+                id: node.id(),
+                lat: node.lat(),
+                lon: node.lon(),
+                tags: owned_tags(node.tags()),
+            }),
+            Element::DenseNode(dense_node) => OsmElement::Node(OsmNode {
+                id: dense_node.id(),
+                lat: dense_node.lat(),
+                lon: dense_node.lon(),
+                tags: owned_tags(dense_node.tags()),
+            }),
+            Element::Way(way) => OsmElement::Way(OsmWay {
+                id: way.id(),
+                node_refs: way.refs().collect(),
+                tags: owned_tags(way.tags()),
+            }),
+            Element::Relation(relation) => OsmElement::Relation(OsmRelation {
+                id: relation.id(),
+                members: relation
+                    .members()
+                    .map(|member| OsmRelationMember {
+                        member_type: member.member_type.clone().into(),
+                        member_id: member.member_id,
+                        role: member.role().unwrap_or("").to_string(),
+                    })
+                    .collect(),
+                tags: owned_tags(relation.tags()),
+            }),
+        }
+    }
+
     pub fn id(&self) -> i64 {
         match self {
             OsmElement::Node(node) => node.id,
@@ -54,6 +107,14 @@ impl OsmElement {
         }
     }
 
+    pub fn element_type(&self) -> ElementType {
+        match self {
+            OsmElement::Node(_) => ElementType::Node,
+            OsmElement::Way(_) => ElementType::Way,
+            OsmElement::Relation(_) => ElementType::Relation,
+        }
+    }
+
     pub fn tags(&self) -> &HashMap<String, String> {
         match self {
             OsmElement::Node(node) => &node.tags,
@@ -62,6 +123,7 @@ impl OsmElement {
         }
     }
 
+    #[allow(dead_code)]
     pub fn has_tag(&self, key: &str) -> bool {
         self.tags().contains_key(key)
     }
@@ -71,66 +133,29 @@ impl OsmElement {
         self.tags().get(key)
     }
 
+    #[allow(dead_code)]
     pub fn matches_filter(&self, filter_tags: &[Vec<String>]) -> bool {
-        if filter_tags.is_empty() {
-            return true;
-        }
-
-        // OR logic between groups: any group that matches makes the element match
-        filter_tags.iter().any(|and_group| {
-            // AND logic within group: all tags in the group must match
-            and_group
-                .iter()
-                .all(|tag_pattern| self.matches_tag_pattern(tag_pattern))
-        })
+        tags_match_filter(self.tags(), filter_tags)
     }
 
-    /// Check if element matches a tag pattern (supports wildcards with *)
+    /// Check if element matches a tag condition (see `TagCondition`)
+    #[allow(dead_code)]
     pub fn matches_tag_pattern(&self, pattern: &str) -> bool {
-        if pattern == "*" {
-            // Special case: '*' matches any element that has at least one tag
-            return !self.tags().is_empty();
-        }
-
-        if let Some(prefix) = pattern.strip_suffix('*') {
-            // Prefix wildcard: "addr*" matches "addr:street", "addr:housenumber", etc.
-            return self.tags().keys().any(|key| key.starts_with(prefix));
-        }
-
-        if let Some(suffix) = pattern.strip_prefix('*') {
-            // Suffix wildcard: "*:en" matches "name:en", "addr:street:en", etc.
-            return self.tags().keys().any(|key| key.ends_with(suffix));
-        }
+        tags_match_pattern(self.tags(), pattern)
+    }
+}
 
-        if pattern.contains('*') {
-            // Middle wildcard: "addr:*:en" matches "addr:street:en", etc.
-            let parts: Vec<&str> = pattern.split('*').collect();
-            return self.tags().keys().any(|key| {
-                let mut key_pos = 0;
-                for (i, part) in parts.iter().enumerate() {
-                    if part.is_empty() {
-                        continue;
-                    }
-                    if let Some(found_pos) = key[key_pos..].find(part) {
-                        key_pos += found_pos + part.len();
-                        // For the last part, it must be at the end (unless it's empty)
-                        if i == parts.len() - 1
-                            && key_pos != key.len()
-                            && !parts.last().unwrap().is_empty()
-                        {
-                            return false;
-                        }
-                    } else {
-                        return false;
-                    }
-                }
-                true
-            });
-        }
+/// Check tags against OR groups of AND-ed tag conditions. The groups are
+/// compiled on every call; converters compile a `TagFilter` once instead.
+#[allow(dead_code)]
+pub fn tags_match_filter(tags: &HashMap<String, String>, filter_tags: &[Vec<String>]) -> bool {
+    // Groups carry no type qualifiers, so the element type doesn't matter
+    TagFilter::from_groups(filter_tags).is_ok_and(|filter| filter.matches(ElementType::Node, tags))
+}
 
-        // Exact match: no wildcards
-        self.has_tag(pattern)
-    }
+/// Check if tags match a single tag condition, e.g. `addr*` or `highway=primary|secondary`
+pub fn tags_match_pattern(tags: &HashMap<String, String>, pattern: &str) -> bool {
+    TagCondition::parse(pattern).is_ok_and(|condition| condition.matches(tags))
 }
 
 #[allow(dead_code)]
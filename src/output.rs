@@ -0,0 +1,258 @@
+// Output formats and the sinks that encode features into them
+use crate::csv_output::DelimitedSink;
+use crate::feature::Feature;
+use crate::flatgeobuf_output::FlatGeobufSink;
+use crate::go_compat::{Compat, to_go_json};
+use crate::gpkg_output::GpkgSink;
+use crate::layers::LayerScheme;
+use crate::mbtiles_output::MbtilesSink;
+use crate::options::ConvertOptions;
+use crate::parquet_output::ParquetSink;
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::str::FromStr;
+
+/// Supported output formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// pbf2json-style JSON Lines (one record per line)
+    #[default]
+    Jsonl,
+    /// A single streamed GeoJSON FeatureCollection
+    GeoJson,
+    /// RFC 8142 GeoJSON Text Sequence (record separator before each Feature)
+    GeoJsonSeq,
+    /// Comma-separated values with the `--columns` fields
+    Csv,
+    /// Tab-separated values with the `--columns` fields
+    Tsv,
+    /// GeoParquet with WKB geometry
+    Parquet,
+    /// FlatGeobuf with a packed Hilbert R-tree index
+    Fgb,
+    /// GeoPackage with one feature table per layer
+    Gpkg,
+    /// MBTiles file of gzipped Mapbox Vector Tiles
+    Mbtiles,
+}
+
+impl OutputFormat {
+    /// Names accepted by `--format`
+    pub const NAMES: [&'static str; 9] = [
+        "jsonl",
+        "geojson",
+        "geojsonseq",
+        "csv",
+        "tsv",
+        "parquet",
+        "fgb",
+        "gpkg",
+        "mbtiles",
+    ];
+
+    /// Formats that only make sense with way and relation geometry
+    pub fn requires_geometry(&self) -> bool {
+        matches!(self, OutputFormat::Fgb | OutputFormat::Mbtiles)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::GeoJson => "geojson",
+            OutputFormat::GeoJsonSeq => "geojsonseq",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Tsv => "tsv",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Fgb => "fgb",
+            OutputFormat::Gpkg => "gpkg",
+            OutputFormat::Mbtiles => "mbtiles",
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            "geojson" => Ok(OutputFormat::GeoJson),
+            "geojsonseq" => Ok(OutputFormat::GeoJsonSeq),
+            "csv" => Ok(OutputFormat::Csv),
+            "tsv" => Ok(OutputFormat::Tsv),
+            "parquet" => Ok(OutputFormat::Parquet),
+            "fgb" => Ok(OutputFormat::Fgb),
+            "gpkg" => Ok(OutputFormat::Gpkg),
+            "mbtiles" => Ok(OutputFormat::Mbtiles),
+            _ => anyhow::bail!(
+                "Unknown output format '{}', expected one of: {}",
+                s,
+                OutputFormat::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+/// Destination for converted features.
+///
+/// The converters' output threads hand every batch to `write_batch` in
+/// arrival order and call `finish` once after the last batch.
+pub trait FeatureSink {
+    fn write_batch(&mut self, features: &[Feature]) -> Result<()>;
+    fn finish(&mut self) -> Result<()>;
+}
+
+/// Create the sink for the configured format, writing to `output_path` or stdout
+pub fn create_sink(
+    output_path: Option<&str>,
+    options: &ConvertOptions,
+) -> Result<Box<dyn FeatureSink>> {
+    // SQLite needs a real file rather than a stream
+    match options.format {
+        OutputFormat::Gpkg => {
+            let path = output_path.context("gpkg output needs an output file (-o)")?;
+            return Ok(Box::new(GpkgSink::create(
+                path,
+                LayerScheme::from_options(options),
+            )?));
+        }
+        OutputFormat::Mbtiles => {
+            let path = output_path.context("mbtiles output needs an output file (-o)")?;
+            // Tile layers always follow the filter groups when there are any
+            let scheme = match &options.tag_filter {
+                Some(filter) => LayerScheme::by_filter(filter),
+                None => LayerScheme::ByType,
+            };
+            return Ok(Box::new(MbtilesSink::create(
+                path,
+                scheme,
+                options.min_zoom,
+                options.max_zoom,
+                options.temp_db_path.as_deref(),
+            )?));
+        }
+        _ => {}
+    }
+
+    let writer = open_output(output_path)?;
+    let columns = || options.columns.clone();
+
+    Ok(match options.format {
+        OutputFormat::Csv => Box::new(DelimitedSink::new(writer, columns(), b',')?),
+        OutputFormat::Tsv => Box::new(DelimitedSink::new(writer, columns(), b'\t')?),
+        OutputFormat::Parquet => Box::new(ParquetSink::new(writer)?),
+        OutputFormat::Fgb => Box::new(FlatGeobufSink::new(writer)?),
+        format => Box::new(TextSink::new(
+            writer,
+            format,
+            options.pretty_print,
+            options.compat == Compat::Go,
+        )?),
+    })
+}
+
+/// Open the output file, or stdout if no path is given
+pub fn open_output(output_path: Option<&str>) -> Result<Box<dyn Write + Send>> {
+    Ok(match output_path {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("Failed to create output file: {}", path))?;
+            Box::new(BufWriter::new(file))
+        }
+        None => Box::new(std::io::stdout()),
+    })
+}
+
+const RECORD_SEPARATOR: u8 = 0x1e;
+
+/// Sink for the JSON-based text formats: jsonl, geojson and geojsonseq
+struct TextSink {
+    writer: Box<dyn Write>,
+    format: OutputFormat,
+    pretty_print: bool,
+    go_compat: bool, // jsonl records in the Go pbf2json layout
+    written: usize,
+}
+
+impl TextSink {
+    fn new(
+        mut writer: Box<dyn Write>,
+        format: OutputFormat,
+        pretty_print: bool,
+        go_compat: bool,
+    ) -> Result<Self> {
+        if format == OutputFormat::GeoJson {
+            writer.write_all(b"{\"type\":\"FeatureCollection\",\"features\":[\n")?;
+        }
+
+        Ok(TextSink {
+            writer,
+            format,
+            pretty_print,
+            go_compat,
+            written: 0,
+        })
+    }
+}
+
+fn encode_text(
+    feature: &Feature,
+    format: OutputFormat,
+    pretty_print: bool,
+    go_compat: bool,
+) -> Option<String> {
+    if go_compat && format == OutputFormat::Jsonl {
+        return Some(to_go_json(feature));
+    }
+
+    let result = match (format, pretty_print) {
+        (OutputFormat::Jsonl, false) => serde_json::to_string(&feature.to_jsonl_value()),
+        (OutputFormat::Jsonl, true) => serde_json::to_string_pretty(&feature.to_jsonl_value()),
+        (_, false) => serde_json::to_string(&feature.to_geojson_feature()),
+        (_, true) => serde_json::to_string_pretty(&feature.to_geojson_feature()),
+    };
+    result.ok()
+}
+
+impl FeatureSink for TextSink {
+    fn write_batch(&mut self, features: &[Feature]) -> Result<()> {
+        // Encode in parallel, write in order
+        let (format, pretty_print, go_compat) = (self.format, self.pretty_print, self.go_compat);
+        let encoded: Vec<String> = features
+            .par_iter()
+            .filter_map(|feature| encode_text(feature, format, pretty_print, go_compat))
+            .collect();
+
+        for record in encoded {
+            match self.format {
+                OutputFormat::GeoJson if self.written > 0 => self.writer.write_all(b",\n")?,
+                OutputFormat::GeoJsonSeq => self.writer.write_all(&[RECORD_SEPARATOR])?,
+                _ => {}
+            }
+            self.writer.write_all(record.as_bytes())?;
+            if self.format != OutputFormat::GeoJson {
+                self.writer.write_all(b"\n")?;
+            }
+            self.written += 1;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.format == OutputFormat::GeoJson {
+            self.writer.write_all(b"\n]}\n")?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+// GeoParquet output writer
+use crate::feature::Feature;
+use crate::geometry::{geometry_type_name, to_wkb};
+use crate::output::FeatureSink;
+use anyhow::{Context, Result};
+use arrow_array::builder::{
+    BinaryBuilder, Float64Builder, Int64Builder, MapBuilder, MapFieldNames, StringBuilder,
+    StructBuilder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Fields, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::metadata::KeyValue;
+use parquet::file::properties::WriterProperties;
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::sync::Arc;
+
+/// Converter batches per Parquet row group
+pub const BATCHES_PER_ROW_GROUP: usize = 10;
+
+/// Upper bound on rows per row group, for converters that send small batches
+const MAX_ROWS_PER_ROW_GROUP: usize = 131_072;
+
+fn centroid_fields() -> Fields {
+    Fields::from(vec![
+        Field::new("lat", DataType::Float64, false),
+        Field::new("lon", DataType::Float64, false),
+    ])
+}
+
+/// GeoParquet 1.1 bbox covering column layout
+fn bbox_fields() -> Fields {
+    Fields::from(vec![
+        Field::new("xmin", DataType::Float64, false),
+        Field::new("ymin", DataType::Float64, false),
+        Field::new("xmax", DataType::Float64, false),
+        Field::new("ymax", DataType::Float64, false),
+    ])
+}
+
+fn tags_type() -> DataType {
+    // Must agree with map_field_names()
+    let entries = Field::new(
+        "entries",
+        DataType::Struct(Fields::from(vec![
+            Field::new("key", DataType::Utf8, false),
+            Field::new("value", DataType::Utf8, true),
+        ])),
+        false,
+    );
+    DataType::Map(Arc::new(entries), false)
+}
+
+fn map_field_names() -> MapFieldNames {
+    MapFieldNames {
+        entry: "entries".to_string(),
+        key: "key".to_string(),
+        value: "value".to_string(),
+    }
+}
+
+/// Arrow schema of the Parquet output
+pub fn parquet_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("type", DataType::Utf8, false),
+        Field::new("tags", tags_type(), false),
+        Field::new("centroid", DataType::Struct(centroid_fields()), true),
+        Field::new("bbox", DataType::Struct(bbox_fields()), true),
+        Field::new("geometry", DataType::Binary, true),
+    ]))
+}
+
+/// Parquet sink writing one row group per `BATCHES_PER_ROW_GROUP` batches,
+/// with GeoParquet `geo` metadata added to the footer on finish
+pub struct ParquetSink {
+    writer: Option<ArrowWriter<Box<dyn Write + Send>>>,
+    schema: SchemaRef,
+    batches_in_group: usize,
+    rows_in_group: usize,
+    geometry_types: BTreeSet<&'static str>,
+    extent: Option<[f64; 4]>, // xmin, ymin, xmax, ymax of all geometries
+}
+
+impl ParquetSink {
+    pub fn new(writer: Box<dyn Write + Send>) -> Result<Self> {
+        let schema = parquet_schema();
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let writer = ArrowWriter::try_new(writer, schema.clone(), Some(properties))
+            .context("Failed to create Parquet writer")?;
+
+        Ok(ParquetSink {
+            writer: Some(writer),
+            schema,
+            batches_in_group: 0,
+            rows_in_group: 0,
+            geometry_types: BTreeSet::new(),
+            extent: None,
+        })
+    }
+
+    fn record_batch(&mut self, features: &[Feature]) -> Result<RecordBatch> {
+        let mut ids = Int64Builder::with_capacity(features.len());
+        let mut types = StringBuilder::new();
+        let mut tags = MapBuilder::new(
+            Some(map_field_names()),
+            StringBuilder::new(),
+            StringBuilder::new(),
+        );
+        let mut centroids = StructBuilder::from_fields(centroid_fields(), features.len());
+        let mut bboxes = StructBuilder::from_fields(bbox_fields(), features.len());
+        let mut geometries = BinaryBuilder::new();
+
+        for feature in features {
+            ids.append_value(feature.id);
+            types.append_value(feature.element_type.as_str());
+
+            // Sorted keys keep the output deterministic
+            let mut sorted_tags: Vec<_> = feature.tags.iter().collect();
+            sorted_tags.sort();
+            for (key, value) in sorted_tags {
+                tags.keys().append_value(key);
+                tags.values().append_value(value);
+            }
+            tags.append(true)?;
+
+            let point = feature.point();
+            append_values(&mut centroids, point.map(|(lat, lon)| vec![lat, lon]));
+
+            // Nodes get a degenerate bbox so every geometry is covered
+            let bbox = feature.bbox();
+            append_values(&mut bboxes, bbox.map(|b| b.to_vec()));
+
+            match feature.output_geometry() {
+                Some(geometry) => {
+                    self.geometry_types
+                        .insert(geometry_type_name(&geometry.value));
+                    if let Some(bbox) = bbox {
+                        self.extend_extent(bbox);
+                    }
+                    geometries.append_value(to_wkb(&geometry));
+                }
+                None => geometries.append_null(),
+            }
+        }
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(ids.finish()),
+            Arc::new(types.finish()),
+            Arc::new(tags.finish()),
+            Arc::new(centroids.finish()),
+            Arc::new(bboxes.finish()),
+            Arc::new(geometries.finish()),
+        ];
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+
+    fn extend_extent(&mut self, bbox: [f64; 4]) {
+        let extent = self.extent.get_or_insert(bbox);
+        extent[0] = extent[0].min(bbox[0]);
+        extent[1] = extent[1].min(bbox[1]);
+        extent[2] = extent[2].max(bbox[2]);
+        extent[3] = extent[3].max(bbox[3]);
+    }
+
+    /// GeoParquet 1.1 file metadata for the `geometry` column
+    fn geo_metadata(&self) -> String {
+        let mut column = json!({
+            "encoding": "WKB",
+            "geometry_types": self.geometry_types,
+            "covering": {
+                "bbox": {
+                    "xmin": ["bbox", "xmin"],
+                    "ymin": ["bbox", "ymin"],
+                    "xmax": ["bbox", "xmax"],
+                    "ymax": ["bbox", "ymax"]
+                }
+            }
+        });
+        if let Some(extent) = self.extent {
+            column["bbox"] = json!(extent);
+        }
+
+        json!({
+            "version": "1.1.0",
+            "primary_column": "geometry",
+            "columns": { "geometry": column }
+        })
+        .to_string()
+    }
+}
+
+/// Append a row to a struct of non-null Float64 fields, or a null row
+fn append_values(builder: &mut StructBuilder, values: Option<Vec<f64>>) {
+    let field_count = builder.num_fields();
+    for index in 0..field_count {
+        let field = builder.field_builder::<Float64Builder>(index).unwrap();
+        match &values {
+            Some(values) => field.append_value(values[index]),
+            None => field.append_value(0.0), // Masked by the struct null
+        }
+    }
+    builder.append(values.is_some());
+}
+
+impl FeatureSink for ParquetSink {
+    fn write_batch(&mut self, features: &[Feature]) -> Result<()> {
+        if features.is_empty() {
+            return Ok(());
+        }
+
+        let batch = self.record_batch(features)?;
+        let writer = self
+            .writer
+            .as_mut()
+            .context("Parquet writer already closed")?;
+        writer.write(&batch)?;
+
+        self.batches_in_group += 1;
+        self.rows_in_group += features.len();
+        if self.batches_in_group >= BATCHES_PER_ROW_GROUP
+            || self.rows_in_group >= MAX_ROWS_PER_ROW_GROUP
+        {
+            writer.flush()?; // Close the current row group
+            self.batches_in_group = 0;
+            self.rows_in_group = 0;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let metadata = self.geo_metadata();
+        if let Some(mut writer) = self.writer.take() {
+            writer.append_key_value_metadata(KeyValue::new("geo".to_string(), metadata));
+            let mut output = writer.into_inner()?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+// Spatial filtering of converted features with --bbox and --clip-polygon
+use crate::boundary::PreparedPolygon;
+use crate::feature::Feature;
+use anyhow::Result;
+use geojson::Value;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// `--bbox minlon,minlat,maxlon,maxlat` in WGS84 degrees
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+impl BoundingBox {
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        (self.min_lat..=self.max_lat).contains(&lat) && (self.min_lon..=self.max_lon).contains(&lon)
+    }
+
+    /// Whether the `[xmin, ymin, xmax, ymax]` extent overlaps the box
+    pub fn intersects(&self, extent: [f64; 4]) -> bool {
+        extent[0] <= self.max_lon
+            && extent[2] >= self.min_lon
+            && extent[1] <= self.max_lat
+            && extent[3] >= self.min_lat
+    }
+
+    /// Whether the `[xmin, ymin, xmax, ymax]` extent lies inside the box
+    pub fn encloses(&self, extent: [f64; 4]) -> bool {
+        self.contains(extent[1], extent[0]) && self.contains(extent[3], extent[2])
+    }
+}
+
+impl FromStr for BoundingBox {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let values = s
+            .split(',')
+            .map(|value| value.trim().parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|e| anyhow::anyhow!("invalid coordinate in '{}': {}", s, e))?;
+        let [min_lon, min_lat, max_lon, max_lat] = values[..] else {
+            anyhow::bail!("expected minlon,minlat,maxlon,maxlat, got '{}'", s);
+        };
+
+        if !(-180.0..=180.0).contains(&min_lon) || !(-180.0..=180.0).contains(&max_lon) {
+            anyhow::bail!("longitudes must be between -180 and 180");
+        }
+        if !(-90.0..=90.0).contains(&min_lat) || !(-90.0..=90.0).contains(&max_lat) {
+            anyhow::bail!("latitudes must be between -90 and 90");
+        }
+        if min_lon > max_lon || min_lat > max_lat {
+            anyhow::bail!("minimum exceeds maximum in '{}'", s);
+        }
+
+        Ok(BoundingBox {
+            min_lon,
+            min_lat,
+            max_lon,
+            max_lat,
+        })
+    }
+}
+
+/// How ways and relations are tested against a region (`--bbox-mode`, `--clip-mode`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpatialMode {
+    /// Any resolved coordinate lies inside
+    #[default]
+    Intersects,
+    /// Every resolved coordinate lies inside
+    Within,
+    /// The centroid lies inside
+    Centroid,
+}
+
+impl SpatialMode {
+    /// Names accepted by `--bbox-mode` and `--clip-mode`
+    pub const NAMES: [&'static str; 3] = ["intersects", "within", "centroid"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SpatialMode::Intersects => "intersects",
+            SpatialMode::Within => "within",
+            SpatialMode::Centroid => "centroid",
+        }
+    }
+}
+
+impl fmt::Display for SpatialMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SpatialMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "intersects" => Ok(SpatialMode::Intersects),
+            "within" => Ok(SpatialMode::Within),
+            "centroid" => Ok(SpatialMode::Centroid),
+            _ => anyhow::bail!(
+                "Unknown spatial mode '{}', expected one of: {}",
+                s,
+                SpatialMode::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+/// Area features are tested against
+#[derive(Debug, Clone)]
+pub enum Region {
+    Bbox(BoundingBox),
+    /// Shared by all workers; cloning the options doesn't copy the edges
+    Polygon(Arc<PreparedPolygon>),
+}
+
+impl Region {
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        match self {
+            Region::Bbox(bbox) => bbox.contains(lat, lon),
+            Region::Polygon(polygon) => polygon.contains(lat, lon),
+        }
+    }
+
+    /// Whether the `[xmin, ymin, xmax, ymax]` extent overlaps the region.
+    /// For polygons: a corner of the extent is inside the polygon, or a
+    /// polygon vertex is inside the extent.
+    fn intersects(&self, extent: [f64; 4]) -> bool {
+        match self {
+            Region::Bbox(bbox) => bbox.intersects(extent),
+            Region::Polygon(polygon) => {
+                let extent_box = BoundingBox {
+                    min_lon: extent[0],
+                    min_lat: extent[1],
+                    max_lon: extent[2],
+                    max_lat: extent[3],
+                };
+                corners(extent)
+                    .iter()
+                    .any(|&(lat, lon)| polygon.contains(lat, lon))
+                    || (extent_box.intersects(polygon.extent())
+                        && polygon
+                            .vertices
+                            .iter()
+                            .any(|&(lon, lat)| extent_box.contains(lat, lon)))
+            }
+        }
+    }
+
+    /// Whether the `[xmin, ymin, xmax, ymax]` extent lies inside the region.
+    /// For polygons: all corners of the extent are inside.
+    fn encloses(&self, extent: [f64; 4]) -> bool {
+        match self {
+            Region::Bbox(bbox) => bbox.encloses(extent),
+            Region::Polygon(polygon) => corners(extent)
+                .iter()
+                .all(|&(lat, lon)| polygon.contains(lat, lon)),
+        }
+    }
+}
+
+/// `(lat, lon)` corners of a `[xmin, ymin, xmax, ymax]` extent
+fn corners(extent: [f64; 4]) -> [(f64, f64); 4] {
+    [
+        (extent[1], extent[0]),
+        (extent[1], extent[2]),
+        (extent[3], extent[0]),
+        (extent[3], extent[2]),
+    ]
+}
+
+/// Keeps features inside a region.
+///
+/// Nodes pass when their location is inside. Ways and relations are tested
+/// with the coordinates of their geometry (or denormalized way nodes), and
+/// with their computed bounds when they have no geometry, such as
+/// non-multipolygon relations. Features without any resolved position fail.
+#[derive(Debug, Clone)]
+pub struct SpatialFilter {
+    pub region: Region,
+    pub mode: SpatialMode,
+}
+
+impl SpatialFilter {
+    pub fn matches(&self, feature: &Feature) -> bool {
+        let region = &self.region;
+        if let Some((lat, lon)) = feature.location {
+            return region.contains(lat, lon);
+        }
+
+        let inside = |lon: f64, lat: f64| region.contains(lat, lon);
+        match self.mode {
+            SpatialMode::Centroid => feature
+                .point()
+                .is_some_and(|(lat, lon)| region.contains(lat, lon)),
+            SpatialMode::Within => {
+                if let Some(geometry) = &feature.geometry {
+                    !any_position(&geometry.value, &|lon, lat| !inside(lon, lat))
+                } else if !feature.way_nodes.is_empty() {
+                    feature.way_nodes.iter().all(|&(lat, lon)| inside(lon, lat))
+                } else {
+                    feature.bbox().is_some_and(|extent| region.encloses(extent))
+                }
+            }
+            SpatialMode::Intersects => {
+                if let Some(geometry) = &feature.geometry {
+                    any_position(&geometry.value, &inside)
+                } else if !feature.way_nodes.is_empty() {
+                    feature.way_nodes.iter().any(|&(lat, lon)| inside(lon, lat))
+                } else {
+                    feature
+                        .bbox()
+                        .is_some_and(|extent| region.intersects(extent))
+                }
+            }
+        }
+    }
+}
+
+/// Whether any `[lon, lat]` position of the geometry satisfies `f`
+fn any_position(value: &Value, f: &impl Fn(f64, f64) -> bool) -> bool {
+    let test = |position: &Vec<f64>| f(position[0], position[1]);
+    match value {
+        Value::Point(position) => test(position),
+        Value::MultiPoint(positions) | Value::LineString(positions) => positions.iter().any(test),
+        Value::MultiLineString(lines) | Value::Polygon(lines) => lines.iter().flatten().any(test),
+        Value::MultiPolygon(polygons) => polygons.iter().flatten().flatten().any(test),
+        Value::GeometryCollection(geometries) => geometries
+            .iter()
+            .any(|geometry| any_position(&geometry.value, f)),
+    }
+}
@@ -0,0 +1,63 @@
+// Tags written for each feature: --keep-tags, --drop-tags and --rename-tags
+use crate::filter::Pattern;
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// Projection applied to the tags of every converted feature, after filtering.
+/// Keys are kept, then dropped, then renamed.
+#[derive(Debug, Clone, Default)]
+pub struct TagProjection {
+    pub keep: Option<Vec<Pattern>>, // None keeps every key
+    pub drop: Vec<Pattern>,
+    pub rename: Vec<(String, String)>, // (from, to)
+}
+
+impl TagProjection {
+    /// Build from comma-separated key patterns (`name,addr*`) and `from=to` renames
+    pub fn parse(keep: Option<&str>, drop: Option<&str>, rename: Option<&str>) -> Result<Self> {
+        Ok(TagProjection {
+            keep: keep.map(parse_key_patterns),
+            drop: drop.map(parse_key_patterns).unwrap_or_default(),
+            rename: rename.map(parse_renames).transpose()?.unwrap_or_default(),
+        })
+    }
+
+    pub fn apply(&self, tags: &mut HashMap<String, String>) {
+        if let Some(keep) = &self.keep {
+            tags.retain(|key, _| keep.iter().any(|pattern| pattern.matches(key)));
+        }
+        if !self.drop.is_empty() {
+            tags.retain(|key, _| !self.drop.iter().any(|pattern| pattern.matches(key)));
+        }
+        // A rename onto a key the element already has keeps both tags as they are
+        for (from, to) in &self.rename {
+            if !tags.contains_key(to)
+                && let Some(value) = tags.remove(from)
+            {
+                tags.insert(to.clone(), value);
+            }
+        }
+    }
+}
+
+fn parse_key_patterns(list: &str) -> Vec<Pattern> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(Pattern::parse)
+        .collect()
+}
+
+/// `addr:street=street,addr:city=city`
+fn parse_renames(list: &str) -> Result<Vec<(String, String)>> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|rename| !rename.is_empty())
+        .map(|rename| match rename.split_once('=') {
+            Some((from, to)) if !from.trim().is_empty() && !to.trim().is_empty() => {
+                Ok((from.trim().to_string(), to.trim().to_string()))
+            }
+            _ => anyhow::bail!("expected from=to, got '{}'", rename),
+        })
+        .collect()
+}
@@ -0,0 +1,293 @@
+// Relation member way node lists on disk, for relation geometry
+use crate::blob_index::BlobIndex;
+use crate::coordinate_storage::CoordinateStorage;
+use crate::db_file::{DbFile, open_lmdb_env};
+use crate::feature::ElementType;
+use crate::filter::ElementTypes;
+use crate::options::ConvertOptions;
+use crate::osm::{MemberType, OsmRelation};
+use anyhow::Result;
+use lmdb::{Database, Environment, Transaction, WriteFlags};
+use std::path::{Path, PathBuf};
+
+/// 200GB max map size for planet files
+const WAY_MAP_SIZE: usize = 200 * 1024 * 1024 * 1024;
+
+/// Disk-based way node list storage using LMDB for planet-scale relation geometry.
+///
+/// Only ways referenced by relations are stored. Node refs are delta encoded as
+/// zigzag varints, since consecutive nodes of a way usually have close IDs.
+pub struct WayStorage {
+    env: Environment,
+    db: Database,
+    _db_file: DbFile, // Dropped after the environment, removing its temp directory
+}
+
+/// A relation member way with its node refs resolved to coordinates
+#[derive(Debug, Clone)]
+pub struct ResolvedWay {
+    pub way_id: i64,
+    pub role: String,
+    pub node_ids: Vec<i64>, // Resolved node IDs, parallel to `coordinates`
+    pub coordinates: Vec<(f64, f64)>,
+}
+
+impl WayStorage {
+    /// Create way storage at specified path, or temp dir if None
+    #[allow(dead_code)]
+    pub fn new(db_path: Option<&Path>) -> Result<Self> {
+        Self::new_with_cleanup(db_path, false)
+    }
+
+    /// Create way storage with specified cleanup behavior. Ways left at
+    /// `db_path` by an earlier run, possibly of another input, are removed.
+    pub fn new_with_cleanup(db_path: Option<&Path>, keep_temp_db: bool) -> Result<Self> {
+        let db_file = DbFile::new(db_path, "ways", keep_temp_db, "way")?;
+        let env = open_lmdb_env(db_file.path(), WAY_MAP_SIZE, 0)?;
+        let db = env.open_db(None)?;
+        let mut txn = env.begin_rw_txn()?;
+        txn.clear_db(db)?;
+        txn.commit()?;
+
+        Ok(WayStorage {
+            env,
+            db,
+            _db_file: db_file,
+        })
+    }
+
+    /// Create way storage in default temp location
+    #[allow(dead_code)]
+    pub fn new_temp() -> Result<Self> {
+        Self::new(None)
+    }
+
+    /// Store node refs for multiple ways in a single transaction
+    pub fn store_ways(&self, ways: &[(i64, Vec<i64>)]) -> Result<()> {
+        let mut txn = self.env.begin_rw_txn()?;
+        for (way_id, node_refs) in ways {
+            let key = way_id.to_be_bytes();
+            let value = encode_node_refs(node_refs);
+            txn.put(self.db, &key, &value, WriteFlags::empty())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Retrieve node refs for a way ID
+    #[allow(dead_code)]
+    pub fn get_way(&self, way_id: i64) -> Result<Option<Vec<i64>>> {
+        Ok(self.get_ways(&[way_id])?.pop().flatten())
+    }
+
+    /// Retrieve node refs for multiple way IDs efficiently in a single transaction
+    pub fn get_ways(&self, way_ids: &[i64]) -> Result<Vec<Option<Vec<i64>>>> {
+        let txn = self.env.begin_ro_txn()?;
+        let mut result = Vec::with_capacity(way_ids.len());
+
+        for &way_id in way_ids {
+            let key = way_id.to_be_bytes();
+            match txn.get(self.db, &key) {
+                Ok(value) => result.push(decode_node_refs(value)),
+                Err(lmdb::Error::NotFound) => result.push(None),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Sync all pending writes to disk
+    pub fn sync(&self) -> Result<()> {
+        self.env.sync(true)?;
+        Ok(())
+    }
+}
+
+impl Drop for WayStorage {
+    fn drop(&mut self) {
+        // Ensure all data is synced before cleanup
+        let _ = self.sync();
+    }
+}
+
+/// Path of the way database that sits next to a user-supplied coordinate database
+pub fn way_db_path(coordinate_db_path: &Path) -> PathBuf {
+    let mut path = coordinate_db_path.as_os_str().to_owned();
+    path.push(".ways");
+    PathBuf::from(path)
+}
+
+/// Way storage for a conversion, next to the `--temp-db` coordinate database
+/// when given, otherwise in a new temp directory
+pub(crate) fn create_way_storage(options: &ConvertOptions) -> Result<WayStorage> {
+    let db_path = options
+        .temp_db_path
+        .as_deref()
+        .map(|path| way_db_path(Path::new(path)));
+    WayStorage::new_with_cleanup(db_path.as_deref(), options.keep_temp_db)
+}
+
+/// Store node refs of the ways in `way_ids` (sorted), one transaction per blob
+pub fn collect_relation_ways(
+    way_storage: &WayStorage,
+    input_path: &str,
+    way_ids: &[i64],
+    blob_index: &mut BlobIndex,
+) -> Result<u64> {
+    if way_ids.is_empty() {
+        return Ok(0);
+    }
+
+    let mut way_count = 0u64;
+    let wanted = ElementTypes::only(ElementType::Way);
+    blob_index.for_each_block(input_path, wanted, |data| {
+        let batch_ways: Vec<(i64, Vec<i64>)> = data
+            .groups()
+            .flat_map(|group| group.ways())
+            .filter(|way| way_ids.binary_search(&way.id()).is_ok())
+            .map(|way| (way.id(), way.refs().collect()))
+            .collect();
+
+        if !batch_ways.is_empty() {
+            way_storage.store_ways(&batch_ways)?;
+            way_count += batch_ways.len() as u64;
+        }
+        Ok(())
+    })?;
+
+    way_storage.sync()?;
+    Ok(way_count)
+}
+
+/// Collect the IDs of way members of a relation, for building the relation way set
+pub fn relation_way_ids(relation: &OsmRelation) -> impl Iterator<Item = i64> + '_ {
+    relation
+        .members
+        .iter()
+        .filter(|member| member.member_type == MemberType::Way)
+        .map(|member| member.member_id)
+}
+
+/// Resolve a relation's way members to coordinates using the way and node stores.
+///
+/// Member ways that are missing from the way store, or whose nodes cannot be
+/// resolved at all, are left out. Unresolved nodes are dropped from each way.
+pub fn resolve_relation_ways(
+    relation: &OsmRelation,
+    ways: &WayStorage,
+    coordinates: &CoordinateStorage,
+) -> Result<Vec<ResolvedWay>> {
+    let members: Vec<_> = relation
+        .members
+        .iter()
+        .filter(|member| member.member_type == MemberType::Way)
+        .collect();
+    let way_ids: Vec<i64> = members.iter().map(|member| member.member_id).collect();
+    let node_lists = ways.get_ways(&way_ids)?;
+
+    let mut resolved = Vec::with_capacity(members.len());
+    for (member, node_refs) in members.into_iter().zip(node_lists) {
+        let Some(node_refs) = node_refs else {
+            continue;
+        };
+
+        let coords = coordinates.get_nodes(&node_refs)?;
+        let (node_ids, coordinates): (Vec<i64>, Vec<(f64, f64)>) = node_refs
+            .iter()
+            .zip(coords)
+            .filter_map(|(&node_id, coord)| coord.map(|c| (node_id, c)))
+            .unzip();
+
+        if !coordinates.is_empty() {
+            resolved.push(ResolvedWay {
+                way_id: member.member_id,
+                role: member.role.clone(),
+                node_ids,
+                coordinates,
+            });
+        }
+    }
+
+    Ok(resolved)
+}
+
+fn encode_node_refs(node_refs: &[i64]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(node_refs.len() * 3);
+    let mut previous = 0i64;
+    for &node_id in node_refs {
+        let delta = node_id.wrapping_sub(previous);
+        let mut zigzag = ((delta << 1) ^ (delta >> 63)) as u64;
+        while zigzag >= 0x80 {
+            buf.push((zigzag as u8) | 0x80);
+            zigzag >>= 7;
+        }
+        buf.push(zigzag as u8);
+        previous = node_id;
+    }
+    buf
+}
+
+fn decode_node_refs(mut bytes: &[u8]) -> Option<Vec<i64>> {
+    let mut node_refs = Vec::new();
+    let mut previous = 0i64;
+    while !bytes.is_empty() {
+        let mut zigzag = 0u64;
+        let mut shift = 0;
+        loop {
+            let (&byte, rest) = bytes.split_first()?; // Truncated varint = invalid data
+            bytes = rest;
+            zigzag |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+            if shift >= 64 {
+                return None;
+            }
+        }
+        let delta = ((zigzag >> 1) as i64) ^ -((zigzag & 1) as i64);
+        previous = previous.wrapping_add(delta);
+        node_refs.push(previous);
+    }
+    Some(node_refs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_way_storage_roundtrip() -> Result<()> {
+        let storage = WayStorage::new_temp()?;
+
+        storage.store_ways(&[
+            (10, vec![1, 2, 3, 1]),
+            (20, vec![9_000_000_000, 8_999_999_999, 42, -5]),
+        ])?;
+
+        assert_eq!(storage.get_way(10)?, Some(vec![1, 2, 3, 1]));
+        assert_eq!(
+            storage.get_way(20)?,
+            Some(vec![9_000_000_000, 8_999_999_999, 42, -5])
+        );
+        assert_eq!(storage.get_way(30)?, None);
+
+        let ways = storage.get_ways(&[20, 30, 10])?;
+        assert_eq!(ways.len(), 3);
+        assert!(ways[1].is_none());
+        assert_eq!(ways[2], Some(vec![1, 2, 3, 1]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_node_ref_encoding_is_compact() {
+        let node_refs: Vec<i64> = (1_000_000_000..1_000_000_100).collect();
+        let encoded = encode_node_refs(&node_refs);
+
+        // First ref takes 5 bytes, each following delta of 1 takes a single byte
+        assert_eq!(encoded.len(), 5 + 99);
+        assert_eq!(decode_node_refs(&encoded), Some(node_refs));
+    }
+}
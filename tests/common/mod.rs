@@ -0,0 +1,352 @@
+// Minimal OSM PBF writer for building test fixtures
+#![allow(dead_code)]
+
+use pbf2json::{ConvertOptions, convert_pbf_parallel_with_options, convert_pbf_with_options};
+use std::io::Write;
+use tempfile::NamedTempFile;
+
+/// Member type codes from osmformat.proto
+pub const NODE: u64 = 0;
+pub const WAY: u64 = 1;
+pub const RELATION: u64 = 2;
+
+struct TestNode {
+    id: i64,
+    lat: f64,
+    lon: f64,
+    tags: Vec<(String, String)>,
+}
+
+struct TestWay {
+    id: i64,
+    refs: Vec<i64>,
+    tags: Vec<(String, String)>,
+}
+
+struct TestRelation {
+    id: i64,
+    members: Vec<(u64, i64, String)>,
+    tags: Vec<(String, String)>,
+}
+
+/// Builds a small PBF file with one uncompressed blob per element type
+#[derive(Default)]
+pub struct PbfBuilder {
+    nodes: Vec<TestNode>,
+    ways: Vec<TestWay>,
+    relations: Vec<TestRelation>,
+    sorted: bool,
+}
+
+fn owned_tags(tags: &[(&str, &str)]) -> Vec<(String, String)> {
+    tags.iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+impl PbfBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declare `Sort.Type_then_ID` in the header
+    pub fn sorted(mut self) -> Self {
+        self.sorted = true;
+        self
+    }
+
+    pub fn node(mut self, id: i64, lat: f64, lon: f64, tags: &[(&str, &str)]) -> Self {
+        self.nodes.push(TestNode {
+            id,
+            lat,
+            lon,
+            tags: owned_tags(tags),
+        });
+        self
+    }
+
+    pub fn way(mut self, id: i64, refs: &[i64], tags: &[(&str, &str)]) -> Self {
+        self.ways.push(TestWay {
+            id,
+            refs: refs.to_vec(),
+            tags: owned_tags(tags),
+        });
+        self
+    }
+
+    /// Members are `(type, id, role)` using the `NODE`/`WAY`/`RELATION` codes
+    pub fn relation(
+        mut self,
+        id: i64,
+        members: &[(u64, i64, &str)],
+        tags: &[(&str, &str)],
+    ) -> Self {
+        self.relations.push(TestRelation {
+            id,
+            members: members
+                .iter()
+                .map(|(t, member_id, role)| (*t, *member_id, role.to_string()))
+                .collect(),
+            tags: owned_tags(tags),
+        });
+        self
+    }
+
+    pub fn write(&self) -> NamedTempFile {
+        let mut file = NamedTempFile::new().expect("Failed to create temp PBF");
+        file.write_all(&self.to_bytes()).unwrap();
+        file.flush().unwrap();
+        file
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        let mut header = Vec::new();
+        field_bytes(&mut header, 4, b"OsmSchema-V0.6");
+        field_bytes(&mut header, 4, b"DenseNodes");
+        if self.sorted {
+            field_bytes(&mut header, 5, b"Sort.Type_then_ID");
+        }
+        write_blob(&mut out, "OSMHeader", &header);
+
+        if !self.nodes.is_empty() {
+            write_blob(&mut out, "OSMData", &self.node_block());
+        }
+        if !self.ways.is_empty() {
+            write_blob(&mut out, "OSMData", &self.way_block());
+        }
+        if !self.relations.is_empty() {
+            write_blob(&mut out, "OSMData", &self.relation_block());
+        }
+        out
+    }
+
+    fn node_block(&self) -> Vec<u8> {
+        let mut strings = StringTable::default();
+        let (mut ids, mut lats, mut lons, mut keys_vals) = (vec![], vec![], vec![], vec![]);
+        let (mut last_id, mut last_lat, mut last_lon) = (0i64, 0i64, 0i64);
+
+        for node in &self.nodes {
+            let lat = (node.lat * 1e7).round() as i64;
+            let lon = (node.lon * 1e7).round() as i64;
+            ids.push(zigzag(node.id - last_id));
+            lats.push(zigzag(lat - last_lat));
+            lons.push(zigzag(lon - last_lon));
+            (last_id, last_lat, last_lon) = (node.id, lat, lon);
+
+            for (k, v) in &node.tags {
+                keys_vals.push(strings.index(k));
+                keys_vals.push(strings.index(v));
+            }
+            keys_vals.push(0);
+        }
+
+        let mut dense = Vec::new();
+        field_packed(&mut dense, 1, &ids);
+        field_packed(&mut dense, 8, &lats);
+        field_packed(&mut dense, 9, &lons);
+        field_packed(&mut dense, 10, &keys_vals);
+
+        let mut group = Vec::new();
+        field_bytes(&mut group, 2, &dense);
+        primitive_block(&strings, &group)
+    }
+
+    fn way_block(&self) -> Vec<u8> {
+        let mut strings = StringTable::default();
+        let mut group = Vec::new();
+
+        for way in &self.ways {
+            let mut message = Vec::new();
+            field_varint(&mut message, 1, way.id as u64);
+            write_tags(&mut message, &mut strings, &way.tags);
+            field_packed(&mut message, 8, &delta_zigzag(&way.refs));
+            field_bytes(&mut group, 3, &message);
+        }
+        primitive_block(&strings, &group)
+    }
+
+    fn relation_block(&self) -> Vec<u8> {
+        let mut strings = StringTable::default();
+        let mut group = Vec::new();
+
+        for relation in &self.relations {
+            let mut message = Vec::new();
+            field_varint(&mut message, 1, relation.id as u64);
+            write_tags(&mut message, &mut strings, &relation.tags);
+            let roles: Vec<u64> = relation
+                .members
+                .iter()
+                .map(|(_, _, role)| strings.index(role))
+                .collect();
+            let ids: Vec<i64> = relation.members.iter().map(|(_, id, _)| *id).collect();
+            let types: Vec<u64> = relation.members.iter().map(|(t, _, _)| *t).collect();
+            field_packed(&mut message, 8, &roles);
+            field_packed(&mut message, 9, &delta_zigzag(&ids));
+            field_packed(&mut message, 10, &types);
+            field_bytes(&mut group, 4, &message);
+        }
+        primitive_block(&strings, &group)
+    }
+}
+
+/// Unit square of nodes 1-4 closed by building way 10
+pub fn square_building() -> PbfBuilder {
+    PbfBuilder::new()
+        .node(1, 0.0, 0.0, &[])
+        .node(2, 0.0, 1.0, &[])
+        .node(3, 1.0, 1.0, &[])
+        .node(4, 1.0, 0.0, &[])
+        .way(10, &[1, 2, 3, 4, 1], &[("building", "yes")])
+}
+
+/// The square building, multipolygon relation 20 with way 10 as its outer
+/// ring, and shop node 9 at `lat`, `lon`
+pub fn bakery_and_grass(lat: f64, lon: f64) -> NamedTempFile {
+    square_building()
+        .node(9, lat, lon, &[("shop", "bakery"), ("name", "Crumbs")])
+        .relation(
+            20,
+            &[(WAY, 10, "outer")],
+            &[("type", "multipolygon"), ("landuse", "grass")],
+        )
+        .write()
+}
+
+/// Convert `input` into a new temp file with the parallel or the sequential converter
+pub fn convert_to_file(
+    input: &NamedTempFile,
+    options: &ConvertOptions,
+    parallel: bool,
+) -> NamedTempFile {
+    let output = NamedTempFile::new().unwrap();
+    let output_path = output.path().to_str().unwrap().to_string();
+    let input_path = input.path().to_str().unwrap();
+    if parallel {
+        convert_pbf_parallel_with_options(input_path, Some(&output_path), options).unwrap();
+    } else {
+        convert_pbf_with_options(input_path, Some(&output_path), options).unwrap();
+    }
+    output
+}
+
+/// `type/id` of every jsonl record `convert_to_file` writes, sorted
+pub fn converted_ids(
+    input: &NamedTempFile,
+    options: &ConvertOptions,
+    parallel: bool,
+) -> Vec<String> {
+    let output = convert_to_file(input, options, parallel);
+    let mut ids: Vec<String> = std::fs::read_to_string(output.path())
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let record: serde_json::Value = serde_json::from_str(line).unwrap();
+            format!("{}/{}", record["type"].as_str().unwrap(), record["id"])
+        })
+        .collect();
+    ids.sort();
+    ids
+}
+
+#[derive(Default)]
+struct StringTable {
+    strings: Vec<String>,
+}
+
+impl StringTable {
+    /// Index of `s`, adding it if needed. Index 0 is reserved for the empty string.
+    fn index(&mut self, s: &str) -> u64 {
+        if self.strings.is_empty() {
+            self.strings.push(String::new());
+        }
+        match self.strings.iter().position(|existing| existing == s) {
+            Some(index) => index as u64,
+            None => {
+                self.strings.push(s.to_string());
+                (self.strings.len() - 1) as u64
+            }
+        }
+    }
+}
+
+fn write_tags(message: &mut Vec<u8>, strings: &mut StringTable, tags: &[(String, String)]) {
+    let keys: Vec<u64> = tags.iter().map(|(k, _)| strings.index(k)).collect();
+    let vals: Vec<u64> = tags.iter().map(|(_, v)| strings.index(v)).collect();
+    field_packed(message, 2, &keys);
+    field_packed(message, 3, &vals);
+}
+
+fn primitive_block(strings: &StringTable, group: &[u8]) -> Vec<u8> {
+    let mut table = Vec::new();
+    if strings.strings.is_empty() {
+        field_bytes(&mut table, 1, b"");
+    }
+    for s in &strings.strings {
+        field_bytes(&mut table, 1, s.as_bytes());
+    }
+
+    let mut block = Vec::new();
+    field_bytes(&mut block, 1, &table);
+    field_bytes(&mut block, 2, group);
+    block
+}
+
+/// Append an uncompressed blob holding `data` as is
+pub fn write_blob(out: &mut Vec<u8>, blob_type: &str, data: &[u8]) {
+    let mut blob = Vec::new();
+    field_bytes(&mut blob, 1, data); // raw
+    field_varint(&mut blob, 2, data.len() as u64); // raw_size
+
+    let mut header = Vec::new();
+    field_bytes(&mut header, 1, blob_type.as_bytes());
+    field_varint(&mut header, 3, blob.len() as u64);
+
+    out.extend_from_slice(&(header.len() as u32).to_be_bytes());
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&blob);
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn delta_zigzag(values: &[i64]) -> Vec<u64> {
+    let mut last = 0;
+    values
+        .iter()
+        .map(|&value| {
+            let delta = zigzag(value - last);
+            last = value;
+            delta
+        })
+        .collect()
+}
+
+fn varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn field_varint(out: &mut Vec<u8>, field: u32, value: u64) {
+    varint(out, u64::from(field << 3));
+    varint(out, value);
+}
+
+fn field_bytes(out: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    varint(out, u64::from(field << 3 | 2));
+    varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn field_packed(out: &mut Vec<u8>, field: u32, values: &[u64]) {
+    let mut packed = Vec::new();
+    for &value in values {
+        varint(&mut packed, value);
+    }
+    field_bytes(out, field, &packed);
+}
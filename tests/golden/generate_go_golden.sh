@@ -0,0 +1,16 @@
+#!/bin/sh
+# Write go_compat_go.jsonl by running pelias/pbf2json on go_compat.osm.pbf.
+# Set PBF2JSON_GO to the Go binary, e.g. one of the prebuilt binaries in the
+# build/ directory of github.com/pelias/pbf2json; it shares this crate's
+# binary name, so the default is pbf2json-go on PATH.
+set -eu
+
+dir=$(cd "$(dirname "$0")" && pwd)
+go_tool=${PBF2JSON_GO:-pbf2json-go}
+leveldb=$(mktemp -d)
+trap 'rm -rf "$leveldb"' EXIT
+
+# The tags match go_options() in tests/test_go_compat.rs
+"$go_tool" -tags="building,highway~residential,amenity,landuse" \
+    -leveldb="$leveldb" "$dir/go_compat.osm.pbf" > "$dir/go_compat_go.jsonl"
+echo "Wrote $dir/go_compat_go.jsonl"
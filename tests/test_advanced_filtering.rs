@@ -314,3 +314,197 @@ fn test_matches_tag_pattern_edge_cases() {
     assert!(element.matches_tag_pattern("very*"));
     assert!(element.matches_tag_pattern("*:here"));
 }
+
+fn tags_of(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_key_value_conditions() {
+    let primary = tags_of(&[("highway", "primary"), ("name", "Baker Street")]);
+    let cafe = tags_of(&[("amenity", "cafe")]);
+    let untagged = HashMap::new();
+
+    let condition = TagCondition::parse("highway=primary").unwrap();
+    assert!(condition.matches(&primary));
+    assert!(!condition.matches(&cafe));
+
+    // Alternatives
+    let condition = TagCondition::parse("amenity=restaurant|cafe").unwrap();
+    assert!(condition.matches(&cafe));
+    assert!(!condition.matches(&primary));
+
+    // Negation also passes elements without the key
+    let condition = TagCondition::parse("highway!=primary").unwrap();
+    assert!(!condition.matches(&primary));
+    assert!(condition.matches(&cafe));
+    assert!(condition.matches(&untagged));
+
+    // Value and key wildcards
+    assert!(
+        TagCondition::parse("name=*Street")
+            .unwrap()
+            .matches(&primary)
+    );
+    assert!(!TagCondition::parse("name=*Road").unwrap().matches(&primary));
+    assert!(TagCondition::parse("*=caf*").unwrap().matches(&cafe));
+
+    // Go pbf2json syntax
+    assert!(
+        TagCondition::parse("highway~primary")
+            .unwrap()
+            .matches(&primary)
+    );
+}
+
+#[test]
+fn test_tag_filter_groups() {
+    let filter = TagFilter::from_groups(&[
+        vec!["highway".to_string(), "highway!=primary".to_string()],
+        vec!["amenity=restaurant|cafe".to_string()],
+    ])
+    .unwrap();
+
+    assert!(filter.matches(ElementType::Node, &tags_of(&[("highway", "residential")])));
+    assert!(!filter.matches(ElementType::Node, &tags_of(&[("highway", "primary")])));
+    assert!(filter.matches(ElementType::Node, &tags_of(&[("amenity", "cafe")])));
+    assert!(!filter.matches(ElementType::Node, &tags_of(&[("amenity", "bench")])));
+    assert!(TagFilter::default().matches(ElementType::Node, &HashMap::new()));
+}
+
+#[test]
+fn test_wildcard_with_repeated_segments() {
+    let tags = tags_of(&[("a:x:en:en", "1")]);
+    assert!(tags_match_pattern(&tags, "a:*:en"));
+    assert!(!tags_match_pattern(&tags, "x:*:en"));
+    assert!(tags_match_pattern(&tags, "a:*:en:*"));
+    assert!(!tags_match_pattern(&tags_of(&[("ab", "1")]), "a*b*b"));
+}
+
+#[test]
+fn test_filter_expressions() {
+    let building = tags_of(&[("building", "yes")]);
+    let not_building = tags_of(&[("building", "no")]);
+    let named_shop = tags_of(&[("shop", "bakery"), ("name", "Crumbs")]);
+    let shop = tags_of(&[("shop", "bakery")]);
+
+    let filter = TagFilter::parse("building+!building=no").unwrap();
+    assert!(filter.matches(ElementType::Node, &building));
+    assert!(!filter.matches(ElementType::Node, &not_building));
+
+    // + binds tighter than , and parentheses override both
+    let filter = TagFilter::parse("shop,amenity+name").unwrap();
+    assert!(filter.matches(ElementType::Node, &shop));
+    let filter = TagFilter::parse("(shop,amenity)+name").unwrap();
+    assert!(!filter.matches(ElementType::Node, &shop));
+    assert!(filter.matches(ElementType::Node, &named_shop));
+
+    // ! applies to the parenthesized group
+    let filter = TagFilter::parse("!(shop,building)").unwrap();
+    assert!(!filter.matches(ElementType::Node, &shop));
+    assert!(filter.matches(ElementType::Node, &tags_of(&[("highway", "primary")])));
+
+    // Top-level alternatives keep their text for layer names
+    let filter = TagFilter::parse(" shop , (amenity,tourism)+name ").unwrap();
+    let sources: Vec<&str> = filter.groups.iter().map(|g| g.source.as_str()).collect();
+    assert_eq!(sources, ["shop", "(amenity,tourism)+name"]);
+}
+
+#[test]
+fn test_filter_parse_errors_name_the_column() {
+    let error = |input: &str| TagFilter::parse(input).unwrap_err().to_string();
+
+    assert!(error("highway+").starts_with("expected a tag condition at column 9"));
+    assert!(error("highway,,building").starts_with("expected a tag condition at column 9"));
+    assert!(error("shop+(amenity,name").starts_with("unclosed '(' at column 6"));
+    assert!(error("shop)").starts_with("unexpected ')' at column 5"));
+    assert!(error("shop+=yes").starts_with("missing tag key at column 6"));
+    assert!(error("").starts_with("expected a tag condition at column 1"));
+
+    // The message repeats the input with a caret under the column
+    assert_eq!(
+        error("shop)"),
+        "unexpected ')' at column 5\n  shop)\n      ^"
+    );
+}
+
+#[test]
+fn test_regex_conditions() {
+    let german = tags_of(&[("name:de", "Hauptstraße")]);
+    let russian = tags_of(&[("name:ru", "Главная улица")]);
+    let saint = tags_of(&[("name", "St. Mary's")]);
+    let street = tags_of(&[("name", "Station Road")]);
+
+    // Key regex; unanchored like grep unless anchored explicitly
+    let condition = TagCondition::parse("~name:(en|de|fr)").unwrap();
+    assert!(condition.matches(&german));
+    assert!(!condition.matches(&russian));
+    assert!(
+        TagCondition::parse("~/^name:.+$/")
+            .unwrap()
+            .matches(&russian)
+    );
+
+    // Value regex and its negation
+    let condition = TagCondition::parse(r"name~/^St\.? /").unwrap();
+    assert!(condition.matches(&saint));
+    assert!(!condition.matches(&street));
+    assert!(
+        TagCondition::parse(r"name!~/^St\.? /")
+            .unwrap()
+            .matches(&street)
+    );
+
+    // Operators inside a regex literal don't split the expression
+    let filter = TagFilter::parse("name~/^(St|Station)\\b.+/+!shop,amenity").unwrap();
+    assert_eq!(filter.groups.len(), 2);
+    assert!(filter.matches(ElementType::Node, &saint));
+    assert!(filter.matches(ElementType::Node, &street));
+    assert!(!filter.matches(
+        ElementType::Node,
+        &tags_of(&[("name", "Crumbs"), ("shop", "bakery")])
+    ));
+}
+
+#[test]
+fn test_regex_errors_name_the_column() {
+    let error = |input: &str| TagFilter::parse(input).unwrap_err().to_string();
+
+    assert!(error("shop,name~/(St/").starts_with("invalid regex /(St/"));
+    assert!(error("shop,name~/(St/").contains("at column 6"));
+    assert!(error("shop+name~/St").starts_with("unterminated regex at column 11"));
+}
+
+#[test]
+fn test_type_qualifiers() {
+    let bench = tags_of(&[("amenity", "bench")]);
+    let building = tags_of(&[("building", "yes")]);
+
+    let filter = TagFilter::parse("n:amenity,w:building,r:type=multipolygon").unwrap();
+    assert!(filter.matches(ElementType::Node, &bench));
+    assert!(!filter.matches(ElementType::Way, &bench));
+    assert!(filter.matches(ElementType::Way, &building));
+    assert!(!filter.matches(ElementType::Node, &building));
+    assert_eq!(filter.element_types(), ElementTypes::ALL);
+
+    // Qualifiers combine and apply to groups; unqualified alternatives match any type
+    let filter = TagFilter::parse("nw:(amenity,building)").unwrap();
+    assert!(filter.matches(ElementType::Way, &bench));
+    assert!(!filter.matches(ElementType::Relation, &building));
+    assert_eq!(
+        filter.element_types(),
+        ElementTypes::parse("node,way").unwrap()
+    );
+    let filter = TagFilter::parse("w:building,amenity").unwrap();
+    assert_eq!(filter.element_types(), ElementTypes::ALL);
+
+    // Keys that merely start with a type letter are not qualifiers
+    let filter = TagFilter::parse("name:en").unwrap();
+    assert!(filter.matches(ElementType::Relation, &tags_of(&[("name:en", "Rome")])));
+
+    assert!(ElementTypes::parse("node,area").is_err());
+    assert_eq!(ElementTypes::parse("ways").unwrap().to_string(), "way");
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use common::{PbfBuilder, convert_to_file};
+use pbf2json::*;
+use std::fs;
+use tempfile::NamedTempFile;
+
+fn fixture() -> NamedTempFile {
+    PbfBuilder::new()
+        .node(1, 0.0, 0.0, &[])
+        .node(2, 0.0, 2.0, &[])
+        .node(3, 2.0, 2.0, &[])
+        .node(4, 2.0, 0.0, &[])
+        .node(
+            5,
+            51.5,
+            -0.125,
+            &[
+                ("name", "Quote \"Corner\", Ltd"),
+                ("description", "line one\nline two"),
+            ],
+        )
+        .way(
+            10,
+            &[1, 2, 3, 4, 1],
+            &[("building", "yes"), ("name", "Hall")],
+        )
+        .write()
+}
+
+fn convert(input: &NamedTempFile, format: OutputFormat, columns: &str, geometry: &str) -> String {
+    let options = ConvertOptions {
+        geometry_level: geometry.parse().unwrap(),
+        format,
+        columns: parse_columns(columns),
+        ..ConvertOptions::default()
+    };
+    fs::read_to_string(convert_to_file(input, &options, true).path()).unwrap()
+}
+
+#[test]
+fn test_quote_field() {
+    assert_eq!(quote_field("plain", b','), "plain");
+    assert_eq!(quote_field("a,b", b','), "\"a,b\"");
+    assert_eq!(quote_field("say \"hi\"", b','), "\"say \"\"hi\"\"\"");
+    assert_eq!(quote_field("two\nlines", b','), "\"two\nlines\"");
+    assert_eq!(quote_field("cr\r", b','), "\"cr\r\"");
+    assert_eq!(quote_field("a,b", b'\t'), "a,b");
+    assert_eq!(quote_field("a\tb", b'\t'), "\"a\tb\"");
+}
+
+#[test]
+fn test_parse_columns() {
+    assert_eq!(
+        parse_columns("id, lat,addr:street,,"),
+        vec![
+            Column::Id,
+            Column::Lat,
+            Column::Tag("addr:street".to_string())
+        ]
+    );
+}
+
+#[test]
+fn test_csv_quotes_multiline_values_and_fills_way_centroid() {
+    let input = fixture();
+    let output = convert(
+        &input,
+        OutputFormat::Csv,
+        "id,type,lat,lon,name,description",
+        "full",
+    );
+
+    assert!(output.starts_with("id,type,lat,lon,name,description\n"));
+    assert!(output.contains("\n10,way,0.8000000,0.8000000,Hall,\n"));
+
+    // The multi-line description stays inside one quoted field
+    assert!(output.contains(
+        "\n5,node,51.5000000,-0.1250000,\"Quote \"\"Corner\"\", Ltd\",\"line one\nline two\"\n"
+    ));
+}
+
+#[test]
+fn test_tsv_leaves_way_coordinates_empty_without_geometry() {
+    let input = fixture();
+    let output = convert(&input, OutputFormat::Tsv, "id,lat,lon,building", "basic");
+
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines[0], "id\tlat\tlon\tbuilding");
+    assert!(lines.contains(&"10\t\t\tyes"));
+    assert!(lines.contains(&"5\t51.5000000\t-0.1250000\t"));
+}
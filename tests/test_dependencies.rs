@@ -0,0 +1,79 @@
+mod common;
+
+use common::{NODE, PbfBuilder, RELATION, WAY, converted_ids};
+use pbf2json::*;
+use tempfile::NamedTempFile;
+
+/// A road of untagged vertices, an untagged way used by a route relation,
+/// and a cafe that is a stop of the route
+fn fixture() -> NamedTempFile {
+    PbfBuilder::new()
+        .node(1, 0.0, 0.0, &[])
+        .node(2, 0.0, 1.0, &[])
+        .node(3, 0.0, 2.0, &[("crossing", "zebra")])
+        .node(4, 1.0, 1.0, &[("amenity", "cafe")])
+        .node(5, 1.0, 2.0, &[])
+        .way(10, &[1, 2, 3], &[("highway", "primary")])
+        .way(11, &[3, 5], &[])
+        .relation(
+            20,
+            &[(WAY, 11, ""), (NODE, 4, "stop"), (RELATION, 21, "")],
+            &[("type", "route")],
+        )
+        .relation(21, &[(WAY, 10, "")], &[])
+        .write()
+}
+
+fn options_for(filter: &str) -> ConvertOptions {
+    ConvertOptions {
+        tag_filter: Some(TagFilter::parse(filter).unwrap()),
+        geometry_level: GeometryLevel::Full,
+        ..ConvertOptions::default()
+    }
+}
+
+#[test]
+fn test_collect_dependencies() {
+    let input = fixture();
+    let input_path = input.path().to_str().unwrap();
+
+    let dependencies = collect_dependencies(input_path, &options_for("highway")).unwrap();
+    assert_eq!(dependencies, IdSet::parse("n1,n2,n3").unwrap());
+
+    // Member ways bring their nodes; sub-relations are not followed
+    let dependencies = collect_dependencies(input_path, &options_for("type=route")).unwrap();
+    assert_eq!(dependencies, IdSet::parse("w11,n4,n3,n5").unwrap());
+
+    // Node-only filters have no dependencies
+    let dependencies = collect_dependencies(input_path, &options_for("amenity")).unwrap();
+    assert!(dependencies.is_empty());
+}
+
+#[test]
+fn test_convert_with_dependencies() {
+    let input = fixture();
+    let input_path = input.path().to_str().unwrap();
+
+    for parallel in [false, true] {
+        let mut options = options_for("highway");
+        assert_eq!(converted_ids(&input, &options, parallel), ["way/10"]);
+
+        options.dependencies = Some(collect_dependencies(input_path, &options).unwrap());
+        assert_eq!(
+            converted_ids(&input, &options, parallel),
+            ["node/1", "node/2", "node/3", "way/10"],
+            "parallel {}",
+            parallel
+        );
+
+        // Dependencies are converted whatever their type qualifiers
+        let mut options = options_for("r:type=route");
+        options.dependencies = Some(collect_dependencies(input_path, &options).unwrap());
+        assert_eq!(
+            converted_ids(&input, &options, parallel),
+            ["node/3", "node/4", "node/5", "relation/20", "way/11"],
+            "parallel {}",
+            parallel
+        );
+    }
+}
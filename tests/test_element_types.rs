@@ -0,0 +1,113 @@
+mod common;
+
+use common::{PbfBuilder, WAY, converted_ids, write_blob};
+use pbf2json::*;
+use std::io::Write;
+use tempfile::NamedTempFile;
+
+/// Amenities and buildings as nodes, ways and a multipolygon relation
+fn fixture() -> PbfBuilder {
+    PbfBuilder::new()
+        .node(1, 0.0, 0.0, &[("amenity", "bench")])
+        .node(2, 0.0, 1.0, &[("building", "yes")])
+        .node(3, 1.0, 1.0, &[])
+        .node(4, 1.0, 0.0, &[])
+        .way(10, &[1, 2, 3, 4, 1], &[("building", "yes")])
+        .way(11, &[1, 2, 3, 1], &[("amenity", "parking")])
+        .relation(
+            20,
+            &[(WAY, 10, "outer")],
+            &[("type", "multipolygon"), ("building", "yes")],
+        )
+        .sorted()
+}
+
+#[test]
+fn test_type_qualifiers_in_both_converters() {
+    let input = fixture().write();
+
+    for geometry in ["basic", "full"] {
+        for parallel in [false, true] {
+            let options = ConvertOptions {
+                geometry_level: geometry.parse().unwrap(),
+                tag_filter: Some(
+                    TagFilter::parse("n:amenity,w:building,r:type=multipolygon").unwrap(),
+                ),
+                ..ConvertOptions::default()
+            };
+            assert_eq!(
+                converted_ids(&input, &options, parallel),
+                ["node/1", "relation/20", "way/10"]
+            );
+
+            // --types narrows the filter further
+            let options = ConvertOptions {
+                types: ElementTypes::parse("way,relation").unwrap(),
+                ..options
+            };
+            assert_eq!(
+                converted_ids(&input, &options, parallel),
+                ["relation/20", "way/10"]
+            );
+        }
+    }
+}
+
+#[test]
+fn test_excluded_blobs_are_not_decoded() {
+    // Blobs past the nodes of a type sorted file are never decoded when only
+    // nodes are wanted, so a corrupt trailing blob goes unnoticed
+    let mut bytes = fixture().to_bytes();
+    write_blob(&mut bytes, "OSMData", b"not a primitive block");
+    let mut input = NamedTempFile::new().unwrap();
+    input.write_all(&bytes).unwrap();
+    let input_path = input.path().to_str().unwrap();
+
+    for geometry in ["basic", "full"] {
+        let options = ConvertOptions {
+            geometry_level: geometry.parse().unwrap(),
+            types: ElementTypes::parse("node").unwrap(),
+            ..ConvertOptions::default()
+        };
+        for parallel in [true, false] {
+            assert_eq!(
+                converted_ids(&input, &options, parallel),
+                ["node/1", "node/2"]
+            );
+        }
+    }
+
+    let output = NamedTempFile::new().unwrap();
+    let output_path = output.path().to_str().unwrap().to_string();
+    let options = ConvertOptions {
+        geometry_level: GeometryLevel::Basic,
+        ..ConvertOptions::default()
+    };
+    assert!(convert_pbf_parallel_with_options(input_path, Some(&output_path), &options).is_err());
+    assert!(convert_pbf_with_options(input_path, Some(&output_path), &options).is_err());
+}
+
+/// The warning for an empty selection names the option that emptied it
+#[test]
+fn test_empty_selection_warning() {
+    let input = fixture().write();
+    for (args, warning) in [
+        (
+            &["--types", "way", "--tags", "n:amenity"][..],
+            "--types excludes every element type the --tags filter can match",
+        ),
+        (
+            &["--types", "way", "--ids", "n1"],
+            "--ids lists no element of the types that --types and --tags can match",
+        ),
+    ] {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_pbf2json"))
+            .arg(input.path())
+            .args(args)
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(warning), "{:?}: {}", args, stderr);
+        assert_eq!(stderr.matches("Warning: --").count(), 1, "{}", stderr);
+    }
+}
@@ -0,0 +1,230 @@
+mod common;
+
+use common::PbfBuilder;
+use pbf2json::*;
+use std::collections::HashMap;
+use std::fs;
+use tempfile::NamedTempFile;
+
+const RULES_TOML: &str = r#"
+[[rules]]
+name = "Main roads"
+types = "way"
+tags = "highway=motorway|trunk|primary"
+keep_tags = ["highway", "name", "ref"]
+
+[[rules]]
+name = "addresses"
+tags = ["addr:housenumber", "addr:street"]
+keep_tags = ["addr:*"]
+layer = "addr"
+
+[[rules]]
+name = "cafes"
+types = ["node"]
+tags = "amenity=cafe,cuisine=coffee_shop"
+"#;
+
+fn tags_of(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_rules_compile_to_filter_groups() {
+    let filter = parse_filter_file(RULES_TOML, "toml").unwrap();
+    let primary = tags_of(&[("highway", "primary"), ("name", "Via Appia")]);
+    let address = tags_of(&[("addr:housenumber", "7"), ("addr:street", "Via Roma")]);
+    let cafe = tags_of(&[("amenity", "cafe")]);
+
+    assert_eq!(filter.group_of(ElementType::Way, &primary), Some(0));
+    assert_eq!(filter.group_of(ElementType::Node, &primary), None);
+    assert_eq!(filter.group_of(ElementType::Relation, &address), Some(1));
+    assert_eq!(filter.group_of(ElementType::Node, &cafe), Some(2));
+    assert_eq!(filter.group_of(ElementType::Way, &cafe), None);
+    assert!(!filter.matches(ElementType::Node, &tags_of(&[("addr:housenumber", "7")])));
+    assert_eq!(filter.element_types(), ElementTypes::ALL);
+
+    // Layers are named after the rule unless it sets one
+    assert_eq!(
+        LayerScheme::by_filter(&filter).names(),
+        ["main_roads", "addr", "cafes"]
+    );
+
+    // The same rules as YAML and JSON
+    let yaml = "
+rules:
+  - name: cafes
+    types: node
+    tags: amenity=cafe
+    keep_tags: [name]
+";
+    let json = r#"{"rules": [{"name": "cafes", "types": "node", "tags": "amenity=cafe", "keep_tags": ["name"]}]}"#;
+    for filter in [
+        parse_filter_file(yaml, "yml").unwrap(),
+        parse_filter_file(json, "json").unwrap(),
+    ] {
+        assert_eq!(filter.groups.len(), 1);
+        assert_eq!(filter.group_of(ElementType::Node, &cafe), Some(0));
+        assert_eq!(filter.element_types(), ElementTypes::parse("node").unwrap());
+    }
+}
+
+#[test]
+fn test_rule_errors() {
+    let error = |content: &str| format!("{:#}", parse_filter_file(content, "toml").unwrap_err());
+
+    assert!(
+        error("[[rules]]\nname = \"roads\"\ntags = \"highway+\"\n").contains("in rule 'roads'")
+    );
+    assert!(error("[[rules]]\nname = \"roads\"\ntypes = \"area\"\n").contains("in rule 'roads'"));
+    assert!(
+        error("[[rules]]\nname = \"a\"\n[[rules]]\nname = \"a\"\n")
+            .contains("duplicate rule name 'a'")
+    );
+    assert!(error("[[rules]]\nname = \"a\"\nkeep = [\"name\"]\n").contains("unknown field"));
+    assert!(error("rules = []\n").contains("no rules defined"));
+    assert!(parse_filter_file("", "ini").is_err());
+}
+
+#[test]
+fn test_keep_tags_in_both_converters() {
+    let input = PbfBuilder::new()
+        .node(
+            1,
+            0.0,
+            0.0,
+            &[
+                ("amenity", "cafe"),
+                ("name", "Sant'Eustachio"),
+                ("source", "survey"),
+            ],
+        )
+        .node(2, 0.0, 1.0, &[])
+        .node(
+            3,
+            1.0,
+            1.0,
+            &[
+                ("addr:housenumber", "7"),
+                ("addr:street", "Via Roma"),
+                ("building", "yes"),
+            ],
+        )
+        .way(
+            10,
+            &[1, 2],
+            &[
+                ("highway", "primary"),
+                ("name", "Via Appia"),
+                ("surface", "asphalt"),
+            ],
+        )
+        .write();
+    let filter = parse_filter_file(RULES_TOML, "toml").unwrap();
+
+    for geometry in ["basic", "full"] {
+        for parallel in [false, true] {
+            let output = NamedTempFile::new().unwrap();
+            let output_path = output.path().to_str().unwrap().to_string();
+            let options = ConvertOptions {
+                geometry_level: geometry.parse().unwrap(),
+                tag_filter: Some(filter.clone()),
+                ..ConvertOptions::default()
+            };
+            let input_path = input.path().to_str().unwrap();
+            if parallel {
+                convert_pbf_parallel_with_options(input_path, Some(&output_path), &options)
+                    .unwrap();
+            } else {
+                convert_pbf_with_options(input_path, Some(&output_path), &options).unwrap();
+            }
+
+            let mut records: Vec<(String, serde_json::Value)> = fs::read_to_string(&output_path)
+                .unwrap()
+                .lines()
+                .map(|line| {
+                    let record: serde_json::Value = serde_json::from_str(line).unwrap();
+                    let id = format!("{}/{}", record["type"].as_str().unwrap(), record["id"]);
+                    (id, record["tags"].clone())
+                })
+                .collect();
+            records.sort_by(|a, b| a.0.cmp(&b.0));
+
+            let expected = [
+                // No keep_tags in the cafes rule
+                (
+                    "node/1",
+                    serde_json::json!({"amenity": "cafe", "name": "Sant'Eustachio", "source": "survey"}),
+                ),
+                (
+                    "node/3",
+                    serde_json::json!({"addr:housenumber": "7", "addr:street": "Via Roma"}),
+                ),
+                (
+                    "way/10",
+                    serde_json::json!({"highway": "primary", "name": "Via Appia"}),
+                ),
+            ];
+            let expected: Vec<(String, serde_json::Value)> = expected
+                .into_iter()
+                .map(|(id, tags)| (id.to_string(), tags))
+                .collect();
+            assert_eq!(
+                records, expected,
+                "geometry {} parallel {}",
+                geometry, parallel
+            );
+        }
+    }
+}
+
+/// Rule layer names are refused where they would be silently ignored
+#[test]
+fn test_rule_layer_needs_layered_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let rules_path = dir.path().join("rules.toml");
+    fs::write(&rules_path, RULES_TOML).unwrap();
+    let input = PbfBuilder::new()
+        .node(1, 41.9, 12.5, &[("amenity", "cafe")])
+        .write();
+
+    let run = |args: &[&str]| {
+        let output_path = dir.path().join("out");
+        let _ = fs::remove_file(&output_path);
+        std::process::Command::new(env!("CARGO_BIN_EXE_pbf2json"))
+            .arg(input.path())
+            .arg("--filter-file")
+            .arg(&rules_path)
+            .arg("-o")
+            .arg(&output_path)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    for args in [&["-f", "jsonl"][..], &["-f", "csv"], &["-f", "gpkg"]] {
+        let output = run(args);
+        assert!(!output.status.success(), "{:?}", args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("filter rule 'addresses' sets a layer"),
+            "{}",
+            stderr
+        );
+    }
+    for args in [
+        &["-f", "gpkg", "--layers", "filter"][..],
+        &["-f", "mbtiles"],
+    ] {
+        let output = run(args);
+        assert!(
+            output.status.success(),
+            "{:?}: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
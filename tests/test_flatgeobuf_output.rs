@@ -0,0 +1,81 @@
+mod common;
+
+use common::{bakery_and_grass, convert_to_file};
+use flatgeobuf::{FallibleStreamingIterator, FeatureProperties, FgbReader, GeometryType};
+use pbf2json::*;
+use std::fs::File;
+use std::io::BufReader;
+use tempfile::NamedTempFile;
+
+fn convert(input: &NamedTempFile, parallel: bool) -> NamedTempFile {
+    let options = ConvertOptions {
+        geometry_level: GeometryLevel::Full,
+        format: OutputFormat::Fgb,
+        ..ConvertOptions::default()
+    };
+    convert_to_file(input, &options, parallel)
+}
+
+fn open(output: &NamedTempFile) -> FgbReader<BufReader<File>> {
+    FgbReader::open(BufReader::new(File::open(output.path()).unwrap())).unwrap()
+}
+
+#[test]
+fn test_fgb_header_has_index_and_columns() {
+    let input = bakery_and_grass(10.0, 20.0); // Shop far from the building
+    let output = convert(&input, true);
+    let reader = open(&output);
+    let header = reader.header();
+
+    assert_eq!(header.features_count(), 3);
+    assert!(header.index_node_size() > 0);
+    assert_eq!(header.geometry_type(), GeometryType::Unknown);
+    assert_eq!(header.crs().unwrap().code(), 4326);
+    assert_eq!(
+        header.envelope().unwrap().iter().collect::<Vec<f64>>(),
+        vec![0.0, 0.0, 20.0, 10.0]
+    );
+
+    let columns: Vec<&str> = header.columns().unwrap().iter().map(|c| c.name()).collect();
+    assert_eq!(columns, ["id", "type", "tags"]);
+}
+
+#[test]
+fn test_fgb_bbox_query_uses_index() {
+    let input = bakery_and_grass(10.0, 20.0);
+
+    for parallel in [false, true] {
+        let output = convert(&input, parallel);
+        let mut features = open(&output).select_bbox(-0.5, -0.5, 1.5, 1.5).unwrap();
+
+        let mut found = Vec::new();
+        while let Some(feature) = features.next().unwrap() {
+            let id: i64 = feature.property("id").unwrap();
+            let element_type: String = feature.property("type").unwrap();
+            let geometry_type = feature.geometry().unwrap().type_();
+            found.push((element_type, id, geometry_type));
+        }
+        found.sort_by_key(|(_, id, _)| *id);
+
+        assert_eq!(
+            found,
+            vec![
+                ("way".to_string(), 10, GeometryType::Polygon),
+                ("relation".to_string(), 20, GeometryType::MultiPolygon),
+            ]
+        );
+    }
+}
+
+#[test]
+fn test_fgb_tags_are_json() {
+    let input = bakery_and_grass(10.0, 20.0);
+    let output = convert(&input, true);
+    let mut features = open(&output).select_bbox(19.0, 9.0, 21.0, 11.0).unwrap();
+
+    let feature = features.next().unwrap().expect("shop node");
+    let tags: String = feature.property("tags").unwrap();
+    assert_eq!(tags, r#"{"name":"Crumbs","shop":"bakery"}"#);
+    assert_eq!(feature.geometry().unwrap().type_(), GeometryType::Point);
+    assert!(features.next().unwrap().is_none());
+}
@@ -0,0 +1,79 @@
+use pbf2json::*;
+use std::collections::HashMap;
+
+fn way_with_tags(node_refs: Vec<i64>, key: &str, value: &str) -> OsmWay {
+    let mut tags = HashMap::new();
+    tags.insert(key.to_string(), value.to_string());
+    OsmWay {
+        id: 1,
+        node_refs,
+        tags,
+    }
+}
+
+#[test]
+fn test_open_way_becomes_linestring() {
+    let way = way_with_tags(vec![1, 2, 3], "highway", "residential");
+    let coordinates = vec![(41.0, 12.0), (41.1, 12.1), (41.2, 12.2)];
+
+    let geometry = way_geometry(&way, &coordinates).expect("Should build geometry");
+
+    match geometry.value {
+        geojson::Value::LineString(line) => {
+            assert_eq!(line.len(), 3);
+            // GeoJSON positions are [lon, lat]
+            assert_eq!(line[0], vec![12.0, 41.0]);
+            assert_eq!(line[2], vec![12.2, 41.2]);
+        }
+        other => panic!("Expected LineString, got {}", other.type_name()),
+    }
+}
+
+#[test]
+fn test_closed_area_becomes_polygon() {
+    let way = way_with_tags(vec![1, 2, 3, 4, 1], "building", "yes");
+    // Clockwise square, should be reoriented counter-clockwise
+    let coordinates = vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)];
+
+    let geometry = way_geometry(&way, &coordinates).expect("Should build geometry");
+
+    match geometry.value {
+        geojson::Value::Polygon(rings) => {
+            assert_eq!(rings.len(), 1);
+            assert_eq!(rings[0].len(), 5);
+            assert_eq!(rings[0].first(), rings[0].last());
+            assert!(
+                ring_signed_area(&rings[0]) > 0.0,
+                "Exterior ring should be CCW"
+            );
+        }
+        other => panic!("Expected Polygon, got {}", other.type_name()),
+    }
+}
+
+#[test]
+fn test_closed_non_area_stays_linestring() {
+    // A closed highway loop (e.g. roundabout) is not an area
+    let way = way_with_tags(vec![1, 2, 3, 1], "highway", "primary");
+    let coordinates = vec![(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (0.0, 0.0)];
+
+    let geometry = way_geometry(&way, &coordinates).expect("Should build geometry");
+    assert_eq!(geometry.value.type_name(), "LineString");
+}
+
+#[test]
+fn test_area_with_missing_nodes_falls_back_to_linestring() {
+    let way = way_with_tags(vec![1, 2, 3, 4, 1], "landuse", "grass");
+    // Last node could not be resolved, so the ring no longer closes
+    let coordinates = vec![(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (1.0, 0.0)];
+
+    let geometry = way_geometry(&way, &coordinates).expect("Should build geometry");
+    assert_eq!(geometry.value.type_name(), "LineString");
+}
+
+#[test]
+fn test_degenerate_way_has_no_geometry() {
+    let way = way_with_tags(vec![1], "highway", "residential");
+    assert!(way_geometry(&way, &[(1.0, 1.0)]).is_none());
+    assert!(way_geometry(&way, &[]).is_none());
+}
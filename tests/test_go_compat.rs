@@ -0,0 +1,128 @@
+mod common;
+
+use common::{PbfBuilder, WAY, convert_to_file};
+use pbf2json::*;
+use std::path::Path;
+use tempfile::NamedTempFile;
+
+/// Buildings with and without an entrance, a residential street, a multipolygon
+/// and elements the Go tool skips because their nodes cannot be resolved
+fn fixture() -> PbfBuilder {
+    PbfBuilder::new()
+        .node(1, 0.0, 0.0, &[])
+        .node(2, 0.0, 2.0, &[])
+        .node(3, 2.0, 2.0, &[])
+        .node(4, 2.0, 0.0, &[])
+        .node(5, 1.0, 3.0, &[])
+        .node(6, 0.0, 1.0, &[("entrance", "yes")])
+        .node(
+            7,
+            51.5,
+            -0.125,
+            &[
+                ("amenity", "restaurant"),
+                ("name", "Fish & Chips <Ltd>"),
+                (" cuisine ", " fish "),
+            ],
+        )
+        .node(
+            8,
+            -0.0000005,
+            13.3888599,
+            &[("amenity", "bench"), ("note", "two\nlines \"quoted\"")],
+        )
+        .way(10, &[1, 2, 3, 4, 1], &[("building", "yes")])
+        .way(11, &[4, 5], &[("highway", "residential")])
+        .way(12, &[1, 2], &[("highway", "footway")])
+        .way(13, &[1, 6, 2, 3, 1], &[("building", "house")])
+        .way(14, &[1, 99, 2], &[("building", "shed")])
+        .relation(
+            20,
+            &[(WAY, 10, "outer"), (WAY, 11, "")],
+            &[("type", "multipolygon"), ("landuse", "grass")],
+        )
+        .relation(21, &[(WAY, 14, "outer")], &[("landuse", "farmyard")])
+}
+
+fn golden(name: &str) -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name)
+}
+
+fn go_options() -> ConvertOptions {
+    ConvertOptions {
+        tag_filter: Some(TagFilter::parse("building,highway~residential,amenity,landuse").unwrap()),
+        geometry_level: GeometryLevel::Full,
+        compat: Compat::Go,
+        ..ConvertOptions::default()
+    }
+}
+
+fn convert(input: &NamedTempFile) -> String {
+    std::fs::read_to_string(convert_to_file(input, &go_options(), true).path()).unwrap()
+}
+
+/// The snapshot was written by this implementation, not by the Go binary: it
+/// guards the record layout against regressions until go_compat_go.jsonl exists
+#[test]
+fn test_go_compat_matches_snapshot() {
+    let input = fixture().write();
+    let expected = include_str!("golden/go_compat_snapshot.jsonl");
+
+    assert_eq!(convert(&input), expected);
+}
+
+/// Only the parallel converter keeps records in file order
+#[test]
+fn test_go_compat_needs_parallel_converter() {
+    let input = fixture().write();
+    let output = NamedTempFile::new().unwrap();
+    let output_path = output.path().to_str().unwrap().to_string();
+    let result = convert_pbf_with_options(
+        input.path().to_str().unwrap(),
+        Some(&output_path),
+        &go_options(),
+    );
+    assert!(result.is_err());
+}
+
+/// The Go tool runs on the committed copy of the fixture
+#[test]
+fn test_go_fixture_is_committed() {
+    let committed = std::fs::read(golden("go_compat.osm.pbf")).unwrap();
+    assert_eq!(committed, fixture().to_bytes());
+}
+
+/// Output of pelias/pbf2json itself on the fixture, written by
+/// tests/golden/generate_go_golden.sh
+#[test]
+#[ignore = "needs tests/golden/go_compat_go.jsonl from the Go tool, see tests/golden/generate_go_golden.sh"]
+fn test_go_compat_matches_go_binary() {
+    let expected = std::fs::read_to_string(golden("go_compat_go.jsonl"))
+        .expect("run tests/golden/generate_go_golden.sh with pelias/pbf2json first");
+    let input = fixture().write();
+    assert_eq!(convert(&input), expected);
+}
+
+#[test]
+fn test_go_json_encoding() {
+    let mut out = String::new();
+    write_go_string(&mut out, "a<b>&c\u{1}\u{2028}é");
+    assert_eq!(out, r#""a\u003cb\u003e\u0026c\u0001\u2028é""#);
+
+    assert_eq!(go_float(13.3888599), "13.3888599");
+    assert_eq!(go_float(-2.0), "-2");
+    assert_eq!(go_float(0.0000005), "5e-7");
+    assert_eq!(go_float(1e21), "1e+21");
+}
+
+#[test]
+fn test_go_centroid() {
+    // Closed rings use the area centroid, open ways the length weighted one
+    let square = [(0.0, 0.0), (0.0, 3.0), (1.0, 3.0), (1.0, 0.0), (0.0, 0.0)];
+    assert_eq!(go_centroid(&square), (0.5, 1.5));
+    let line = [(0.0, 0.0), (0.0, 2.0), (0.0, 3.0)];
+    assert_eq!(go_centroid(&line), (0.0, 1.5));
+    assert_eq!(go_centroid(&[(1.0, 2.0)]), (1.0, 2.0));
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use common::{bakery_and_grass, convert_to_file};
+use pbf2json::*;
+use rusqlite::Connection;
+use tempfile::NamedTempFile;
+
+fn convert(input: &NamedTempFile, options: &ConvertOptions, parallel: bool) -> NamedTempFile {
+    let options = ConvertOptions {
+        geometry_level: GeometryLevel::Full,
+        format: OutputFormat::Gpkg,
+        ..options.clone()
+    };
+    convert_to_file(input, &options, parallel)
+}
+
+fn count(connection: &Connection, sql: &str) -> i64 {
+    connection.query_row(sql, [], |row| row.get(0)).unwrap()
+}
+
+#[test]
+fn test_gpkg_geometry_blob_header() {
+    let point = geojson::Geometry::new(geojson::Value::Point(vec![2.0, 1.0]));
+    let blob = gpkg_geometry_blob(&point, Some([2.0, 1.0, 2.0, 1.0]));
+
+    assert_eq!(&blob[..4], b"GP\x00\x03");
+    assert_eq!(i32::from_le_bytes(blob[4..8].try_into().unwrap()), 4326);
+    // Envelope is minx, maxx, miny, maxy
+    assert_eq!(f64::from_le_bytes(blob[16..24].try_into().unwrap()), 2.0);
+    assert_eq!(f64::from_le_bytes(blob[24..32].try_into().unwrap()), 1.0);
+    assert_eq!(&blob[40..], to_wkb(&point).as_slice());
+}
+
+#[test]
+fn test_gpkg_tables_per_element_type() {
+    let input = bakery_and_grass(0.5, 0.25);
+
+    for parallel in [false, true] {
+        let output = convert(&input, &ConvertOptions::default(), parallel);
+        let connection = Connection::open(output.path()).unwrap();
+
+        assert_eq!(count(&connection, "PRAGMA application_id"), 0x4750_4B47);
+        assert_eq!(
+            count(
+                &connection,
+                "SELECT count(*) FROM gpkg_contents WHERE data_type = 'features'"
+            ),
+            3
+        );
+        for table in ["nodes", "ways", "relations"] {
+            assert_eq!(
+                count(&connection, &format!("SELECT count(*) FROM {}", table)),
+                1
+            );
+            assert_eq!(
+                count(
+                    &connection,
+                    &format!("SELECT count(*) FROM rtree_{}_geom", table)
+                ),
+                1
+            );
+        }
+
+        let (osm_id, tags, geom): (i64, String, Vec<u8>) = connection
+            .query_row("SELECT osm_id, tags, geom FROM nodes", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(osm_id, 9);
+        assert_eq!(tags, r#"{"name":"Crumbs","shop":"bakery"}"#);
+        assert_eq!(&geom[..2], b"GP");
+
+        // R-tree bbox query finds the building by its extent
+        assert_eq!(
+            count(
+                &connection,
+                "SELECT count(*) FROM rtree_ways_geom WHERE minx <= 0.5 AND maxx >= 0.5"
+            ),
+            1
+        );
+        let max_y: f64 = connection
+            .query_row(
+                "SELECT max_y FROM gpkg_contents WHERE table_name = 'relations'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(max_y, 1.0);
+
+        // Spec triggers are created once loading is done
+        assert_eq!(
+            count(
+                &connection,
+                "SELECT count(*) FROM sqlite_master WHERE type = 'trigger'"
+            ),
+            18
+        );
+    }
+}
+
+#[test]
+fn test_gpkg_tables_per_filter_group() {
+    let input = bakery_and_grass(0.5, 0.25);
+    let options = ConvertOptions {
+        tag_filter: Some(TagFilter::parse("shop,building,landuse+type").unwrap()),
+        layers: Layers::Filter,
+        ..ConvertOptions::default()
+    };
+    let output = convert(&input, &options, true);
+    let connection = Connection::open(output.path()).unwrap();
+
+    let tables: Vec<String> = connection
+        .prepare("SELECT table_name FROM gpkg_contents ORDER BY table_name")
+        .unwrap()
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(tables, ["building", "landuse_type", "shop"]);
+
+    assert_eq!(count(&connection, "SELECT osm_id FROM shop"), 9);
+    assert_eq!(count(&connection, "SELECT osm_id FROM building"), 10);
+    assert_eq!(count(&connection, "SELECT osm_id FROM landuse_type"), 20);
+}
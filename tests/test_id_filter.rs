@@ -0,0 +1,118 @@
+mod common;
+
+use common::{NODE, PbfBuilder, RELATION, WAY, convert_to_file};
+use pbf2json::*;
+use std::fs;
+use std::io::Write;
+use tempfile::NamedTempFile;
+
+/// A route relation whose sub-relation comes later in the file, over two
+/// roads of untagged nodes, plus an unrelated cafe
+fn fixture() -> NamedTempFile {
+    PbfBuilder::new()
+        .node(1, 0.0, 0.0, &[])
+        .node(2, 0.0, 1.0, &[])
+        .node(3, 0.0, 2.0, &[("highway", "stop")])
+        .node(4, 1.0, 1.0, &[("amenity", "cafe")])
+        .way(10, &[1, 2], &[("highway", "primary")])
+        .way(11, &[2, 3], &[])
+        .relation(
+            20,
+            &[(RELATION, 21, ""), (NODE, 4, "stop")],
+            &[("type", "route")],
+        )
+        .relation(21, &[(WAY, 10, ""), (WAY, 11, "")], &[])
+        .write()
+}
+
+fn converted_ids(input: &NamedTempFile, ids: IdSet, geometry: &str, parallel: bool) -> Vec<String> {
+    let options = ConvertOptions {
+        ids: Some(ids),
+        geometry_level: geometry.parse().unwrap(),
+        ..ConvertOptions::default()
+    };
+    common::converted_ids(input, &options, parallel)
+}
+
+#[test]
+fn test_parse_ids() {
+    let ids = IdSet::parse("n123, w456 r789,node/5,way/6 relation/7,n123").unwrap();
+    assert_eq!(ids.len(), 6);
+    assert!(ids.contains(ElementType::Node, 123));
+    assert!(ids.contains(ElementType::Node, 5));
+    assert!(ids.contains(ElementType::Way, 456));
+    assert!(ids.contains(ElementType::Relation, 7));
+    assert!(!ids.contains(ElementType::Way, 123));
+    assert_eq!(ids.element_types(), ElementTypes::ALL);
+    assert_eq!(
+        IdSet::parse("n1,n2").unwrap().element_types(),
+        ElementTypes::only(ElementType::Node)
+    );
+
+    assert!(IdSet::parse("123").is_err());
+    assert!(IdSet::parse("x123").is_err());
+    assert!(IdSet::parse("n12a").is_err());
+    assert!(IdSet::parse("changeset/1").is_err());
+    assert!(IdSet::parse("").unwrap().is_empty());
+
+    let mut file = NamedTempFile::new().unwrap();
+    writeln!(file, "# QA report 42\nn1 n2\n\nw10  # broken road\nr20,r20").unwrap();
+    let from_file = IdSet::from_file(file.path()).unwrap();
+    assert_eq!(from_file, IdSet::parse("n1,n2,w10,r20").unwrap());
+
+    let mut file = NamedTempFile::new().unwrap();
+    writeln!(file, "n1\nbogus").unwrap();
+    let error = IdSet::from_file(file.path()).unwrap_err();
+    assert!(format!("{:#}", error).contains("line 2"));
+}
+
+#[test]
+fn test_add_referenced() {
+    let input = fixture();
+    let input_path = input.path().to_str().unwrap();
+
+    let mut ids = IdSet::parse("r20").unwrap();
+    ids.add_referenced(input_path).unwrap();
+    assert_eq!(ids, IdSet::parse("r20,r21,w10,w11,n1,n2,n3,n4").unwrap());
+
+    let mut ids = IdSet::parse("w11").unwrap();
+    ids.add_referenced(input_path).unwrap();
+    assert_eq!(ids, IdSet::parse("w11,n2,n3").unwrap());
+}
+
+#[test]
+fn test_convert_listed_elements() {
+    let input = fixture();
+
+    for parallel in [false, true] {
+        // Listed elements are converted even when untagged
+        assert_eq!(
+            converted_ids(
+                &input,
+                IdSet::parse("n1,w11,r21").unwrap(),
+                "full",
+                parallel
+            ),
+            ["node/1", "relation/21", "way/11"],
+            "parallel {}",
+            parallel
+        );
+        assert_eq!(
+            converted_ids(&input, IdSet::parse("n4,w10").unwrap(), "basic", parallel),
+            ["node/4", "way/10"],
+            "parallel {}",
+            parallel
+        );
+    }
+
+    // Listed relations get their geometry from member ways
+    let options = ConvertOptions {
+        ids: Some(IdSet::parse("r21").unwrap()),
+        geometry_level: GeometryLevel::Full,
+        ..ConvertOptions::default()
+    };
+    let output = convert_to_file(&input, &options, true);
+    let record: serde_json::Value =
+        serde_json::from_str(fs::read_to_string(output.path()).unwrap().trim()).unwrap();
+    assert_eq!(record["bounds"]["e"], "2.0000000");
+}
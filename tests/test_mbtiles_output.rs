@@ -0,0 +1,167 @@
+mod common;
+
+use common::{bakery_and_grass, convert_to_file};
+use flate2::read::GzDecoder;
+use pbf2json::*;
+use rusqlite::Connection;
+use std::io::Read;
+
+fn read_varint(data: &[u8], position: &mut usize) -> u64 {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = data[*position];
+        *position += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte < 0x80 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+/// Length-delimited fields of a protobuf message with the given field number
+fn message_fields(data: &[u8], wanted: u64) -> Vec<&[u8]> {
+    let mut fields = Vec::new();
+    let mut position = 0;
+    while position < data.len() {
+        let key = read_varint(data, &mut position);
+        match key & 0x7 {
+            0 => {
+                read_varint(data, &mut position);
+            }
+            2 => {
+                let length = read_varint(data, &mut position) as usize;
+                if key >> 3 == wanted {
+                    fields.push(&data[position..position + length]);
+                }
+                position += length;
+            }
+            wire_type => panic!("unexpected wire type {}", wire_type),
+        }
+    }
+    fields
+}
+
+/// `(layer name, feature count)` of a gzipped tile
+fn tile_layers(tile_data: &[u8]) -> Vec<(String, usize)> {
+    let mut tile = Vec::new();
+    GzDecoder::new(tile_data).read_to_end(&mut tile).unwrap();
+    message_fields(&tile, 3)
+        .into_iter()
+        .map(|layer| {
+            let name = String::from_utf8(message_fields(layer, 1)[0].to_vec()).unwrap();
+            (name, message_fields(layer, 2).len())
+        })
+        .collect()
+}
+
+#[test]
+fn test_point_commands() {
+    let point = geojson::Geometry::new(geojson::Value::Point(vec![0.0, 0.0]));
+    let projected = project_geometry(&point).unwrap();
+
+    // MoveTo(1) to the tile center, zigzag encoded
+    assert_eq!(projected.tile_commands(0, 0, 0), Some(vec![9, 4096, 4096]));
+    assert_eq!(project(0.0, 0.0), (0.5, 0.5));
+    assert_eq!(tile_range([9.0, 59.0, 11.0, 61.0], 2), (2, 1, 2, 1));
+}
+
+#[test]
+fn test_polygon_is_clipped_into_every_tile() {
+    let square = geojson::Geometry::new(geojson::Value::Polygon(vec![vec![
+        vec![-10.0, -10.0],
+        vec![10.0, -10.0],
+        vec![10.0, 10.0],
+        vec![-10.0, 10.0],
+        vec![-10.0, -10.0],
+    ]]));
+    let projected = project_geometry(&square).unwrap();
+
+    for (x, y) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+        let commands = projected.tile_commands(1, x, y).expect("clipped ring");
+        assert_eq!(commands[0], 9); // MoveTo(1)
+        assert_eq!(*commands.last().unwrap(), 15); // ClosePath(1)
+    }
+    assert_eq!(projected.tile_commands(2, 0, 0), None);
+
+    // The exterior ring of a GeoJSON (counter-clockwise) polygon must have
+    // positive area in y-down tile coordinates
+    let commands = projected.tile_commands(0, 0, 0).unwrap();
+    let unzigzag = |v: u32| ((v >> 1) as i64) ^ -((v & 1) as i64);
+    let mut cursor = (0i64, 0i64);
+    let mut ring = Vec::new();
+    for pair in commands[1..3]
+        .chunks(2)
+        .chain(commands[4..commands.len() - 1].chunks(2))
+    {
+        cursor = (cursor.0 + unzigzag(pair[0]), cursor.1 + unzigzag(pair[1]));
+        ring.push(cursor);
+    }
+    let area: i64 = (0..ring.len())
+        .map(|i| {
+            let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+            a.0 * b.1 - b.0 * a.1
+        })
+        .sum();
+    assert!(area > 0);
+}
+
+#[test]
+fn test_mbtiles_layers_follow_filter_groups() {
+    // A building and a multipolygon near the origin, and a shop in Siberia
+    let input = bakery_and_grass(60.0, 100.0);
+    let options = ConvertOptions {
+        geometry_level: GeometryLevel::Full,
+        format: OutputFormat::Mbtiles,
+        tag_filter: Some(TagFilter::parse("shop,building").unwrap()),
+        min_zoom: 0,
+        max_zoom: 2,
+        ..ConvertOptions::default()
+    };
+    let output = convert_to_file(&input, &options, true);
+
+    let connection = Connection::open(output.path()).unwrap();
+    let metadata = |name: &str| -> String {
+        connection
+            .query_row(
+                "SELECT value FROM metadata WHERE name = ?1",
+                [name],
+                |row| row.get(0),
+            )
+            .unwrap()
+    };
+    assert_eq!(metadata("format"), "pbf");
+    assert_eq!(metadata("maxzoom"), "2");
+    let json: serde_json::Value = serde_json::from_str(&metadata("json")).unwrap();
+    let layer_ids: Vec<&str> = json["vector_layers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|layer| layer["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(layer_ids, ["shop", "building"]);
+
+    // The single zoom 0 tile holds both layers
+    let tile: Vec<u8> = connection
+        .query_row(
+            "SELECT tile_data FROM tiles WHERE zoom_level = 0",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(
+        tile_layers(&tile),
+        [("shop".to_string(), 1), ("building".to_string(), 1)]
+    );
+
+    // Rows are TMS numbered: the shop's XYZ tile 2/3/1 is stored as row 2
+    let tile: Vec<u8> = connection
+        .query_row(
+            "SELECT tile_data FROM tiles WHERE zoom_level = 2 AND tile_column = 3 AND tile_row = 2",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(tile_layers(&tile), [("shop".to_string(), 1)]);
+}
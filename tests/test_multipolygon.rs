@@ -0,0 +1,185 @@
+use pbf2json::*;
+use std::collections::HashMap;
+
+// Square corner nodes: 1=(0,0) 2=(0,10) 3=(10,10) 4=(10,0) as (lat, lon)
+const OUTER_NODES: [(i64, (f64, f64)); 4] = [
+    (1, (0.0, 0.0)),
+    (2, (0.0, 10.0)),
+    (3, (10.0, 10.0)),
+    (4, (10.0, 0.0)),
+];
+
+fn coords(ids: &[i64]) -> Vec<(f64, f64)> {
+    let lookup: HashMap<i64, (f64, f64)> = OUTER_NODES
+        .iter()
+        .copied()
+        .chain([
+            (11, (2.0, 2.0)),
+            (12, (2.0, 4.0)),
+            (13, (4.0, 4.0)),
+            (14, (4.0, 2.0)),
+            (21, (20.0, 20.0)),
+            (22, (20.0, 21.0)),
+            (23, (21.0, 21.0)),
+            (31, (50.0, 50.0)),
+            (32, (50.0, 51.0)),
+            (33, (51.0, 51.0)),
+        ])
+        .collect();
+    ids.iter().map(|id| lookup[id]).collect()
+}
+
+fn polygons(geometry: geojson::Geometry) -> Vec<Vec<Vec<Vec<f64>>>> {
+    match geometry.value {
+        geojson::Value::MultiPolygon(polygons) => polygons,
+        other => panic!("Expected MultiPolygon, got {}", other.type_name()),
+    }
+}
+
+#[test]
+fn test_outer_ring_stitched_from_split_ways_with_hole() {
+    // Outer ring split into two ways, the second one digitized in reverse
+    let west_ids = [1, 2, 3];
+    let east_ids = [1, 4, 3];
+    let inner_ids = [11, 12, 13, 14, 11];
+    let (west, east, inner) = (coords(&west_ids), coords(&east_ids), coords(&inner_ids));
+
+    let members = vec![
+        MemberWay {
+            way_id: 100,
+            role: "outer",
+            node_ids: &west_ids,
+            coordinates: &west,
+        },
+        MemberWay {
+            way_id: 101,
+            role: "outer",
+            node_ids: &east_ids,
+            coordinates: &east,
+        },
+        MemberWay {
+            way_id: 102,
+            role: "inner",
+            node_ids: &inner_ids,
+            coordinates: &inner,
+        },
+    ];
+
+    let polygons = polygons(assemble_multipolygon(&members).expect("Should assemble"));
+    assert_eq!(polygons.len(), 1);
+    assert_eq!(polygons[0].len(), 2, "Outer ring plus one hole");
+
+    let exterior = &polygons[0][0];
+    assert_eq!(exterior.len(), 5);
+    assert_eq!(exterior.first(), exterior.last());
+    assert!(ring_signed_area(exterior) > 0.0, "Exterior should be CCW");
+    assert!(ring_signed_area(&polygons[0][1]) < 0.0, "Hole should be CW");
+}
+
+#[test]
+fn test_disjoint_outer_rings_become_separate_polygons() {
+    let first_ids = [1, 2, 3, 4, 1];
+    let second_ids = [21, 22, 23, 21];
+    let (first, second) = (coords(&first_ids), coords(&second_ids));
+
+    let members = vec![
+        MemberWay {
+            way_id: 1,
+            role: "outer",
+            node_ids: &first_ids,
+            coordinates: &first,
+        },
+        MemberWay {
+            way_id: 2,
+            role: "", // Empty role is treated as outer
+            node_ids: &second_ids,
+            coordinates: &second,
+        },
+    ];
+
+    let polygons = polygons(assemble_multipolygon(&members).expect("Should assemble"));
+    assert_eq!(polygons.len(), 2);
+    assert!(polygons.iter().all(|polygon| polygon.len() == 1));
+}
+
+#[test]
+fn test_unclosed_ring_is_reported() {
+    let open_ids = [1, 2, 3];
+    let open = coords(&open_ids);
+
+    let members = vec![MemberWay {
+        way_id: 7,
+        role: "outer",
+        node_ids: &open_ids,
+        coordinates: &open,
+    }];
+
+    assert_eq!(
+        assemble_multipolygon(&members).unwrap_err(),
+        AssemblyError::UnclosedRing { way_ids: vec![7] }
+    );
+}
+
+#[test]
+fn test_inner_outside_every_outer_is_reported() {
+    let outer_ids = [1, 2, 3, 4, 1];
+    let stray_ids = [31, 32, 33, 31];
+    let (outer, stray) = (coords(&outer_ids), coords(&stray_ids));
+
+    let members = vec![
+        MemberWay {
+            way_id: 1,
+            role: "outer",
+            node_ids: &outer_ids,
+            coordinates: &outer,
+        },
+        MemberWay {
+            way_id: 9,
+            role: "inner",
+            node_ids: &stray_ids,
+            coordinates: &stray,
+        },
+    ];
+
+    assert_eq!(
+        assemble_multipolygon(&members).unwrap_err(),
+        AssemblyError::InnerWithoutOuter { way_ids: vec![9] }
+    );
+}
+
+#[test]
+fn test_only_inner_members_is_reported() {
+    let inner_ids = [11, 12, 13, 14, 11];
+    let inner = coords(&inner_ids);
+
+    let members = vec![MemberWay {
+        way_id: 3,
+        role: "inner",
+        node_ids: &inner_ids,
+        coordinates: &inner,
+    }];
+
+    assert_eq!(
+        assemble_multipolygon(&members).unwrap_err(),
+        AssemblyError::NoOuterRings
+    );
+}
+
+#[test]
+fn test_is_multipolygon_relation() {
+    let relation_with_type = |value: &str| {
+        let mut tags = HashMap::new();
+        tags.insert("type".to_string(), value.to_string());
+        OsmRelation {
+            id: 1,
+            members: vec![],
+            tags,
+        }
+    };
+
+    assert!(is_multipolygon_relation(&relation_with_type(
+        "multipolygon"
+    )));
+    assert!(is_multipolygon_relation(&relation_with_type("boundary")));
+    assert!(!is_multipolygon_relation(&relation_with_type("route")));
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use common::PbfBuilder;
+use pbf2json::*;
+use std::fs;
+use std::path::Path;
+use tempfile::{NamedTempFile, tempdir};
+
+fn road(end_lon: f64) -> PbfBuilder {
+    PbfBuilder::new()
+        .node(1, 0.0, 0.0, &[])
+        .node(2, 0.0, end_lon, &[])
+        .way(10, &[1, 2], &[("highway", "primary")])
+}
+
+fn centroid_lon(input_path: &str, options: &ConvertOptions, parallel: bool) -> String {
+    let output = NamedTempFile::new().unwrap();
+    let output_path = output.path().to_str().unwrap().to_string();
+    if parallel {
+        convert_pbf_parallel_with_options(input_path, Some(&output_path), options).unwrap();
+    } else {
+        convert_pbf_with_options(input_path, Some(&output_path), options).unwrap();
+    }
+    let record: serde_json::Value =
+        serde_json::from_str(fs::read_to_string(&output_path).unwrap().trim()).unwrap();
+    record["centroid"]["lon"].as_str().unwrap().to_string()
+}
+
+fn cache_options(cache_path: &Path, node_store: NodeStore) -> ConvertOptions {
+    ConvertOptions {
+        geometry_level: GeometryLevel::Full,
+        node_store,
+        node_cache: Some(cache_path.to_str().unwrap().to_string()),
+        ..ConvertOptions::default()
+    }
+}
+
+#[test]
+fn test_node_cache_is_reused() -> anyhow::Result<()> {
+    let input = road(1.0).write();
+    let input_path = input.path().to_str().unwrap();
+
+    for node_store in [NodeStore::Lmdb, NodeStore::Dense] {
+        for parallel in [false, true] {
+            let dir = tempdir()?;
+            let cache_path = dir.path().join("nodes.cache");
+            let options = cache_options(&cache_path, node_store);
+
+            assert!(!NodeCache::open(&cache_path, input_path, node_store)?.is_complete());
+            assert_eq!(centroid_lon(input_path, &options, parallel), "0.5000000");
+            assert!(cache_path.exists());
+
+            // Move node 2 in the cache: a run that reuses it doesn't reload nodes
+            let cache = NodeCache::open(&cache_path, input_path, node_store)?;
+            assert!(cache.is_complete(), "{} parallel {}", node_store, parallel);
+            cache.storage()?.store_node(2, 0.0, 3.0)?;
+            assert_eq!(centroid_lon(input_path, &options, parallel), "1.5000000");
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_node_cache_is_rebuilt_on_mismatch() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let cache_path = dir.path().join("nodes.cache");
+    let input = road(1.0).write();
+    let input_path = input.path().to_str().unwrap();
+
+    let options = cache_options(&cache_path, NodeStore::Lmdb);
+    assert_eq!(centroid_lon(input_path, &options, true), "0.5000000");
+
+    // Another node store can't read the cache
+    let dense = cache_options(&cache_path, NodeStore::Dense);
+    assert!(!NodeCache::open(&cache_path, input_path, NodeStore::Dense)?.is_complete());
+    assert_eq!(centroid_lon(input_path, &dense, true), "0.5000000");
+
+    // Nor can another input
+    let changed = road(2.0).write();
+    let changed_path = changed.path().to_str().unwrap();
+    assert!(!NodeCache::open(&cache_path, changed_path, NodeStore::Dense)?.is_complete());
+    assert_eq!(centroid_lon(changed_path, &dense, false), "1.0000000");
+    assert!(NodeCache::open(&cache_path, changed_path, NodeStore::Dense)?.is_complete());
+    Ok(())
+}
+
+#[test]
+fn test_node_cache_needs_disk_store() {
+    let dir = tempdir().unwrap();
+    let input = road(1.0).write();
+    let input_path = input.path().to_str().unwrap();
+    for store in [NodeStore::SparseMem, NodeStore::HashMem] {
+        let result = NodeCache::open(&dir.path().join("nodes.cache"), input_path, store);
+        assert!(result.is_err());
+    }
+}
+
+/// A run reusing the cache waits for the run building it
+#[test]
+fn test_node_cache_waits_for_builder() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let cache_path = dir.path().join("nodes.cache");
+    let input = road(1.0).write();
+    let input_path = input.path().to_str().unwrap().to_string();
+
+    let mut builder = NodeCache::open(&cache_path, &input_path, NodeStore::Lmdb)?;
+    assert!(!builder.is_complete());
+
+    let reader = {
+        let cache_path = cache_path.clone();
+        std::thread::spawn(move || {
+            NodeCache::open(&cache_path, &input_path, NodeStore::Lmdb)
+                .unwrap()
+                .is_complete()
+        })
+    };
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert!(!reader.is_finished());
+
+    let storage = builder.storage()?;
+    storage.store_node(1, 0.0, 0.0)?;
+    storage.finalize()?;
+    builder.mark_complete(&storage)?;
+    drop(storage);
+    drop(builder);
+    assert!(reader.join().unwrap());
+    Ok(())
+}
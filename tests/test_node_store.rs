@@ -0,0 +1,379 @@
+mod common;
+
+use common::{PbfBuilder, WAY};
+use pbf2json::*;
+use std::fs;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tempfile::{NamedTempFile, tempdir};
+
+/// Built-in stores rounding to 1e-7 degrees
+const STORES: [NodeStore; 3] = [NodeStore::Lmdb, NodeStore::Dense, NodeStore::SparseMem];
+
+/// Every built-in store, including the exact `hash-mem` one
+fn all_storages() -> anyhow::Result<Vec<(String, CoordinateStorage)>> {
+    let mut storages = Vec::new();
+    for store in STORES.into_iter().chain([NodeStore::HashMem]) {
+        storages.push((
+            store.to_string(),
+            CoordinateStorage::with_store(store, None, false)?,
+        ));
+    }
+    Ok(storages)
+}
+
+/// A library user's store: counts calls and keeps nodes in a `HashMap`
+#[derive(Default)]
+struct CountingStore {
+    nodes: HashMapNodeStore,
+    puts: Arc<AtomicUsize>,
+    finalized: Arc<AtomicUsize>,
+}
+
+impl NodeLocationStore for CountingStore {
+    fn put_nodes(&self, nodes: &[(i64, f64, f64)]) -> anyhow::Result<()> {
+        self.puts.fetch_add(nodes.len(), Ordering::SeqCst);
+        self.nodes.put_nodes(nodes)
+    }
+
+    fn get_nodes(&self, node_ids: &[i64]) -> anyhow::Result<Vec<Option<(f64, f64)>>> {
+        self.nodes.get_nodes(node_ids)
+    }
+
+    fn finalize(&self) -> anyhow::Result<()> {
+        self.finalized.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_store_and_get() -> anyhow::Result<()> {
+    for (store, storage) in all_storages()? {
+        storage.store_nodes(&[
+            (100, 37.7749, -122.4194),
+            (7, -33.8688, 151.2093),
+            (0, 0.0, 0.0),
+        ])?;
+        // Past the initial dense file size
+        storage.store_nodes(&[(5_000_000, 90.0, -180.0), (100, 37.775, -122.42)])?;
+        storage.finalize()?;
+
+        let coords = storage.get_nodes(&[100, 7, 0, 5_000_000, 8, 9_000_000_000, -1])?;
+        assert_eq!(
+            coords,
+            [
+                Some((37.775, -122.42)),
+                Some((-33.8688, 151.2093)),
+                Some((0.0, 0.0)),
+                Some((90.0, -180.0)),
+                None,
+                None,
+                None
+            ],
+            "{} store",
+            store
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_fixed_point_precision() -> anyhow::Result<()> {
+    for store in STORES {
+        let storage = CoordinateStorage::with_store(store, None, false)?;
+        storage.store_nodes(&[(1, 51.50735091, -0.12775829)])?;
+        storage.sync()?;
+        assert_eq!(storage.get_node(1)?, Some((51.5073509, -0.1277583)));
+    }
+    let storage = CoordinateStorage::with_store(NodeStore::HashMem, None, false)?;
+    storage.store_nodes(&[(1, 51.50735091, -0.12775829)])?;
+    assert_eq!(storage.get_node(1)?, Some((51.50735091, -0.12775829)));
+    assert_eq!(to_fixed(-179.9999999), -1_799_999_999);
+    assert_eq!(from_fixed(1_799_999_999), 179.9999999);
+    Ok(())
+}
+
+#[test]
+fn test_lmdb_format_migration() -> anyhow::Result<()> {
+    use lmdb::{DatabaseFlags, Environment, EnvironmentFlags, Transaction, WriteFlags};
+
+    let dir = tempdir()?;
+    let path = dir.path().join("nodes.lmdb");
+
+    // Format version 1: two f64 per node in the unnamed database
+    {
+        let env = Environment::new()
+            .set_flags(EnvironmentFlags::NO_SUB_DIR)
+            .open(&path)?;
+        let db = env.open_db(None)?;
+        let mut txn = env.begin_rw_txn()?;
+        for (id, lat, lon) in [
+            (1i64, 51.50735091f64, -0.12775829f64),
+            (2, -33.8688, 151.2093),
+        ] {
+            let value = [lat.to_be_bytes(), lon.to_be_bytes()].concat();
+            txn.put(db, &id.to_be_bytes(), &value, WriteFlags::empty())?;
+        }
+        txn.commit()?;
+    }
+
+    let store = LmdbNodeStore::open(&path)?;
+    assert_eq!(
+        store.get_nodes(&[1, 2, 3])?,
+        [
+            Some((51.5073509, -0.1277583)),
+            Some((-33.8688, 151.2093)),
+            None
+        ]
+    );
+    drop(store);
+
+    // Reopening a current store keeps its nodes
+    let store = LmdbNodeStore::open(&path)?;
+    assert_eq!(store.get_nodes(&[2])?, [Some((-33.8688, 151.2093))]);
+    drop(store);
+
+    // Stores from a newer build are rejected
+    {
+        let env = Environment::new()
+            .set_flags(EnvironmentFlags::NO_SUB_DIR)
+            .set_max_dbs(2)
+            .open(&path)?;
+        let meta = env.create_db(Some("meta"), DatabaseFlags::empty())?;
+        let mut txn = env.begin_rw_txn()?;
+        let version = NODE_FORMAT_VERSION + 1;
+        txn.put(
+            meta,
+            b"format_version",
+            &version.to_be_bytes(),
+            WriteFlags::empty(),
+        )?;
+        txn.commit()?;
+    }
+    assert!(LmdbNodeStore::open(&path).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_lmdb_append_fallback() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let store = LmdbNodeStore::open(&dir.path().join("nodes.lmdb"))?;
+
+    // Appended, then behind the stored keys, then unsorted
+    store.put_nodes(&[(1, 1.0, 1.0), (2, 2.0, 2.0), (5, 5.0, 5.0)])?;
+    store.put_nodes(&[(3, 3.0, 3.0), (4, 4.0, 4.0)])?;
+    store.put_nodes(&[(7, 7.0, 7.0), (6, 6.0, 6.0), (2, 2.5, 2.5)])?;
+    // Negative IDs sort after positive ones as big-endian keys
+    store.put_nodes(&[(8, 8.0, 8.0), (-1, -1.0, -1.0)])?;
+
+    assert_eq!(
+        store.get_nodes(&[1, 2, 3, 4, 5, 6, 7, 8, -1])?,
+        [
+            Some((1.0, 1.0)),
+            Some((2.5, 2.5)),
+            Some((3.0, 3.0)),
+            Some((4.0, 4.0)),
+            Some((5.0, 5.0)),
+            Some((6.0, 6.0)),
+            Some((7.0, 7.0)),
+            Some((8.0, 8.0)),
+            Some((-1.0, -1.0))
+        ]
+    );
+    Ok(())
+}
+
+/// A blob of ten nodes for each of `first_ids`, in that order, then a way
+/// from node 1 to node 640
+fn node_blobs(first_ids: &[i64]) -> NamedTempFile {
+    let mut file = NamedTempFile::new().unwrap();
+    for &first_id in first_ids {
+        let mut builder = PbfBuilder::new();
+        for id in first_id..first_id + 10 {
+            builder = builder.node(id, id as f64 / 1000.0, 0.0, &[]);
+        }
+        file.write_all(&builder.to_bytes()).unwrap();
+    }
+    let way = PbfBuilder::new().way(1000, &[1, 640], &[("highway", "primary")]);
+    file.write_all(&way.to_bytes()).unwrap();
+    file.flush().unwrap();
+    file
+}
+
+#[test]
+fn test_ordered_block_commits() -> anyhow::Result<()> {
+    let first_ids: Vec<i64> = (0..64).map(|blob| 1 + blob * 10).collect();
+    let input = node_blobs(&first_ids);
+
+    // Blobs are decoded in parallel but committed in file order
+    let mut committed = Vec::new();
+    BlobIndex::new().for_each_block_ordered(
+        input.path().to_str().unwrap(),
+        ElementTypes::only(ElementType::Node),
+        |block| {
+            Ok(block.elements().find_map(|element| match element {
+                osmpbf::Element::DenseNode(node) => Some(node.id()),
+                _ => None,
+            }))
+        },
+        |first_id| {
+            committed.push(first_id.unwrap());
+            Ok(())
+        },
+    )?;
+    assert_eq!(committed, first_ids);
+
+    // Errors and panics of `map` and `commit` end the pass with an error
+    let path = input.path().to_str().unwrap();
+    let nodes = ElementTypes::only(ElementType::Node);
+    let mut blocks = 0;
+    let result = BlobIndex::new().for_each_block_ordered(
+        path,
+        nodes,
+        |_| Ok(()),
+        |_| {
+            blocks += 1;
+            match blocks {
+                3 => anyhow::bail!("commit failed"),
+                _ => Ok(()),
+            }
+        },
+    );
+    assert_eq!(result.unwrap_err().to_string(), "commit failed");
+    assert_eq!(blocks, 3);
+    let result = BlobIndex::new().for_each_block_ordered(
+        path,
+        nodes,
+        |_| -> anyhow::Result<()> { anyhow::bail!("map failed") },
+        |_| Ok(()),
+    );
+    assert_eq!(result.unwrap_err().to_string(), "map failed");
+    let result = BlobIndex::new().for_each_block_ordered(
+        path,
+        nodes,
+        |_| -> anyhow::Result<()> { panic!("map panicked") },
+        |_| Ok(()),
+    );
+    assert!(result.is_err());
+
+    // Sorted and unsorted files convert alike in every store
+    let unsorted_ids: Vec<i64> = first_ids.iter().rev().copied().collect();
+    for ids in [first_ids, unsorted_ids] {
+        let input = node_blobs(&ids);
+        let input_path = input.path().to_str().unwrap();
+        let options = ConvertOptions {
+            geometry_level: GeometryLevel::Full,
+            ..ConvertOptions::default()
+        };
+        for (store, storage) in all_storages()? {
+            let output = NamedTempFile::new()?;
+            let output_path = output.path().to_str().unwrap().to_string();
+            convert_pbf_parallel_with_store(input_path, Some(&output_path), &options, storage)?;
+            let record: serde_json::Value =
+                serde_json::from_str(fs::read_to_string(&output_path)?.trim())?;
+            assert_eq!(record["centroid"]["lat"], "0.3205000", "{} store", store);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_dense_store() -> anyhow::Result<()> {
+    let dir = tempdir()?;
+    let path = dir.path().join("nodes.dense");
+    let storage = CoordinateStorage::with_store(NodeStore::Dense, Some(&path), false)?;
+
+    assert!(storage.store_nodes(&[(-5, 1.0, 1.0)]).is_err());
+
+    // The file grows to cover the largest ID but stays sparse on disk
+    storage.store_nodes(&[(1, 1.0, 1.0), (100_000_000, 2.0, 2.0)])?;
+    storage.sync()?;
+    let metadata = fs::metadata(&path)?;
+    assert!(metadata.len() >= 800_000_008);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        assert!(metadata.blocks() * 512 < 10_000_000);
+    }
+    assert_eq!(storage.get_node(100_000_000)?, Some((2.0, 2.0)));
+    Ok(())
+}
+
+#[test]
+fn test_convert_with_each_store() {
+    let input = PbfBuilder::new()
+        .node(1, 0.0, 0.0, &[])
+        .node(2, 0.0, 1.0, &[])
+        .node(3, 1.0, 1.0, &[])
+        .node(4, 1.0, 0.0, &[("amenity", "cafe")])
+        .way(10, &[1, 2, 3, 4, 1], &[("building", "yes")])
+        .way(11, &[2, 3], &[("highway", "primary")])
+        .relation(20, &[(WAY, 11, "")], &[("type", "route")])
+        .write();
+    let input_path = input.path().to_str().unwrap();
+
+    let mut outputs = Vec::new();
+    for store in STORES {
+        for parallel in [false, true] {
+            let output = NamedTempFile::new().unwrap();
+            let output_path = output.path().to_str().unwrap().to_string();
+            let options = ConvertOptions {
+                geometry_level: GeometryLevel::Full,
+                node_store: store,
+                ..ConvertOptions::default()
+            };
+            if parallel {
+                convert_pbf_parallel_with_options(input_path, Some(&output_path), &options)
+                    .unwrap();
+            } else {
+                convert_pbf_with_options(input_path, Some(&output_path), &options).unwrap();
+            }
+            let contents = fs::read_to_string(&output_path).unwrap();
+            let mut lines: Vec<String> = contents.lines().map(String::from).collect();
+            lines.sort();
+            outputs.push(lines);
+        }
+    }
+
+    assert_eq!(outputs[0].len(), 4);
+    assert!(outputs[0].iter().any(|line| line.contains("\"centroid\"")));
+    for output in &outputs[1..] {
+        assert_eq!(output, &outputs[0]);
+    }
+}
+
+#[test]
+fn test_convert_with_custom_store() {
+    let input = PbfBuilder::new()
+        .node(1, 0.0, 0.0, &[])
+        .node(2, 0.0, 1.0, &[])
+        .way(10, &[1, 2], &[("highway", "primary")])
+        .write();
+    let input_path = input.path().to_str().unwrap();
+    let options = ConvertOptions {
+        geometry_level: GeometryLevel::Full,
+        ..ConvertOptions::default()
+    };
+
+    for parallel in [false, true] {
+        let store = CountingStore::default();
+        let (puts, finalized) = (store.puts.clone(), store.finalized.clone());
+        let storage = CoordinateStorage::from_store(Box::new(store));
+
+        let output = NamedTempFile::new().unwrap();
+        let output_path = output.path().to_str().unwrap().to_string();
+        if parallel {
+            convert_pbf_parallel_with_store(input_path, Some(&output_path), &options, storage)
+                .unwrap();
+        } else {
+            convert_pbf_with_store(input_path, Some(&output_path), &options, storage).unwrap();
+        }
+
+        assert_eq!(puts.load(Ordering::SeqCst), 2);
+        assert_eq!(finalized.load(Ordering::SeqCst), 1);
+        let record: serde_json::Value =
+            serde_json::from_str(fs::read_to_string(&output_path).unwrap().trim()).unwrap();
+        assert_eq!(record["centroid"]["lon"], "0.5000000");
+    }
+}
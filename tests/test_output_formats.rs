@@ -0,0 +1,170 @@
+mod common;
+
+use common::{NODE, WAY, convert_to_file, square_building};
+use pbf2json::*;
+use serde_json::Value;
+use std::fs;
+use tempfile::NamedTempFile;
+
+/// A shop node, a closed building way and a square multipolygon with a hole
+fn fixture() -> NamedTempFile {
+    square_building()
+        .node(5, 0.25, 0.25, &[])
+        .node(6, 0.25, 0.75, &[])
+        .node(7, 0.75, 0.75, &[])
+        .node(8, 0.75, 0.25, &[])
+        .node(9, 0.5, 0.5, &[("shop", "bakery"), ("name", "Crumbs")])
+        .way(11, &[5, 6, 7, 8, 5], &[])
+        .relation(
+            20,
+            &[(WAY, 10, "outer"), (WAY, 11, "inner"), (NODE, 9, "label")],
+            &[("type", "multipolygon"), ("landuse", "grass")],
+        )
+        .write()
+}
+
+fn convert(input: &NamedTempFile, format: OutputFormat, geometry: &str, parallel: bool) -> String {
+    let options = ConvertOptions {
+        geometry_level: geometry.parse().unwrap(),
+        format,
+        ..ConvertOptions::default()
+    };
+    fs::read_to_string(convert_to_file(input, &options, parallel).path()).unwrap()
+}
+
+fn collection_features(output: &str) -> Vec<geojson::Feature> {
+    match output.parse::<geojson::GeoJson>().expect("valid GeoJSON") {
+        geojson::GeoJson::FeatureCollection(collection) => collection.features,
+        other => panic!("expected a FeatureCollection, got {:?}", other),
+    }
+}
+
+fn feature_by_id<'a>(features: &'a [geojson::Feature], id: &str) -> &'a geojson::Feature {
+    let id = geojson::feature::Id::String(id.to_string());
+    features
+        .iter()
+        .find(|feature| feature.id.as_ref() == Some(&id))
+        .unwrap_or_else(|| panic!("missing feature {:?}", id))
+}
+
+#[test]
+fn test_geojson_feature_collection_in_both_converters() {
+    let input = fixture();
+
+    for parallel in [false, true] {
+        let features =
+            collection_features(&convert(&input, OutputFormat::GeoJson, "full", parallel));
+        assert_eq!(features.len(), 3);
+
+        let node = feature_by_id(&features, "node/9");
+        assert_eq!(node.property("name"), Some(&Value::from("Crumbs")));
+        assert_eq!(
+            node.geometry.as_ref().unwrap().value,
+            geojson::Value::Point(vec![0.5, 0.5])
+        );
+
+        let way = feature_by_id(&features, "way/10");
+        assert!(matches!(
+            way.geometry.as_ref().unwrap().value,
+            geojson::Value::Polygon(_)
+        ));
+        assert_eq!(way.bbox, Some(vec![0.0, 0.0, 1.0, 1.0]));
+
+        let relation = feature_by_id(&features, "relation/20");
+        match &relation.geometry.as_ref().unwrap().value {
+            geojson::Value::MultiPolygon(polygons) => assert_eq!(polygons[0].len(), 2),
+            other => panic!("expected MultiPolygon, got {:?}", other),
+        }
+        assert_eq!(relation.property("landuse"), Some(&Value::from("grass")));
+    }
+}
+
+#[test]
+fn test_geojson_basic_mode_has_null_way_geometry() {
+    let input = fixture();
+    let features = collection_features(&convert(&input, OutputFormat::GeoJson, "basic", true));
+
+    assert_eq!(features.len(), 3);
+    assert!(feature_by_id(&features, "node/9").geometry.is_some());
+    assert!(feature_by_id(&features, "way/10").geometry.is_none());
+}
+
+#[test]
+fn test_geojsonseq_records_start_with_record_separator() {
+    let input = fixture();
+
+    for parallel in [false, true] {
+        let output = convert(&input, OutputFormat::GeoJsonSeq, "full", parallel);
+        let records: Vec<&str> = output.lines().collect();
+        assert_eq!(records.len(), 3);
+
+        for record in records {
+            let text = record
+                .strip_prefix('\u{1e}')
+                .expect("record should start with RS");
+            let feature: geojson::Feature = text
+                .parse::<geojson::GeoJson>()
+                .unwrap()
+                .try_into()
+                .unwrap();
+            assert!(feature.geometry.is_some());
+        }
+    }
+}
+
+#[test]
+fn test_jsonl_keeps_pbf2json_layout() {
+    let input = fixture();
+    let output = convert(&input, OutputFormat::Jsonl, "full", true);
+    let records: Vec<Value> = output
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 3);
+
+    let node = records.iter().find(|r| r["type"] == "node").unwrap();
+    assert_eq!(node["lat"], 0.5);
+    assert_eq!(node["tags"]["shop"], "bakery");
+
+    let way = records.iter().find(|r| r["type"] == "way").unwrap();
+    assert_eq!(way["nodes"], serde_json::json!([1, 2, 3, 4, 1]));
+    assert_eq!(way["centroid"]["type"], "centroid");
+    assert_eq!(way["bounds"]["n"], "1.0000000");
+
+    let relation = records.iter().find(|r| r["type"] == "relation").unwrap();
+    assert_eq!(relation["centroid"]["type"], "entrance");
+    assert!(relation.get("members").is_none());
+}
+
+#[test]
+fn test_output_format_names_round_trip() {
+    for name in OutputFormat::NAMES {
+        let format: OutputFormat = name.parse().unwrap();
+        assert_eq!(format.to_string(), name);
+    }
+    assert!("shapefile".parse::<OutputFormat>().is_err());
+}
+
+/// Progress goes to stderr, so output written to stdout parses cleanly
+#[test]
+fn test_convert_to_stdout() {
+    let input = fixture();
+    let input_path = input.path().to_str().unwrap();
+
+    for geometry in ["basic", "full"] {
+        for parallel in [false, true] {
+            let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_pbf2json"));
+            command.args([input_path, "-f", "geojson", "-g", geometry]);
+            if !parallel {
+                command.arg("--no-parallel");
+            }
+            let output = command.output().unwrap();
+            assert!(output.status.success());
+
+            let collection: Value = serde_json::from_slice(&output.stdout)
+                .unwrap_or_else(|e| panic!("{} geometry, parallel {}: {}", geometry, parallel, e));
+            assert_eq!(collection["type"], "FeatureCollection");
+            assert_eq!(collection["features"].as_array().unwrap().len(), 3);
+        }
+    }
+}
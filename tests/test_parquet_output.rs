@@ -0,0 +1,124 @@
+mod common;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::Int64Type;
+use arrow_array::{Array, RecordBatch};
+use common::{bakery_and_grass, convert_to_file};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use pbf2json::*;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::File;
+use tempfile::NamedTempFile;
+
+fn read_parquet(path: &str) -> (Value, usize, Vec<RecordBatch>) {
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap()).unwrap();
+    let metadata = builder.metadata().clone();
+    let geo = metadata
+        .file_metadata()
+        .key_value_metadata()
+        .and_then(|kv| kv.iter().find(|entry| entry.key == "geo"))
+        .and_then(|entry| entry.value.clone())
+        .expect("geo metadata");
+
+    let batches = builder
+        .build()
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    (
+        serde_json::from_str(&geo).unwrap(),
+        metadata.num_row_groups(),
+        batches,
+    )
+}
+
+#[test]
+fn test_to_wkb_point() {
+    let point = geojson::Geometry::new(geojson::Value::Point(vec![0.25, 0.5]));
+    let wkb = to_wkb(&point);
+
+    assert_eq!(wkb.len(), 21);
+    assert_eq!(wkb[0], 1); // Little endian
+    assert_eq!(u32::from_le_bytes(wkb[1..5].try_into().unwrap()), 1);
+    assert_eq!(f64::from_le_bytes(wkb[5..13].try_into().unwrap()), 0.25);
+    assert_eq!(f64::from_le_bytes(wkb[13..21].try_into().unwrap()), 0.5);
+}
+
+#[test]
+fn test_parquet_columns_and_geo_metadata() {
+    let input = bakery_and_grass(0.5, 0.25);
+    let options = ConvertOptions {
+        geometry_level: GeometryLevel::Full,
+        format: OutputFormat::Parquet,
+        ..ConvertOptions::default()
+    };
+
+    for parallel in [false, true] {
+        let output = convert_to_file(&input, &options, parallel);
+        let (geo, _, batches) = read_parquet(output.path().to_str().unwrap());
+        assert_eq!(geo["version"], "1.1.0");
+        assert_eq!(geo["primary_column"], "geometry");
+        assert_eq!(geo["columns"]["geometry"]["encoding"], "WKB");
+        assert_eq!(
+            geo["columns"]["geometry"]["geometry_types"],
+            serde_json::json!(["MultiPolygon", "Point", "Polygon"])
+        );
+        assert_eq!(
+            geo["columns"]["geometry"]["bbox"],
+            serde_json::json!([0.0, 0.0, 1.0, 1.0])
+        );
+
+        let schema = batches[0].schema();
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(
+            names,
+            ["id", "type", "tags", "centroid", "bbox", "geometry"]
+        );
+
+        let mut rows = HashMap::new();
+        for batch in &batches {
+            let ids = batch.column(0).as_primitive::<Int64Type>();
+            let tags = batch.column(2).as_map();
+            let geometries = batch.column(5).as_binary::<i32>();
+            for row in 0..batch.num_rows() {
+                let tag_count = tags.value(row).len();
+                rows.insert(ids.value(row), (tag_count, geometries.value(row).to_vec()));
+            }
+        }
+        assert_eq!(rows.len(), 3);
+
+        // Point(lon, lat) for the bakery node
+        let point = geojson::Geometry::new(geojson::Value::Point(vec![0.25, 0.5]));
+        assert_eq!(rows[&9], (2, to_wkb(&point)));
+        assert_eq!(u32::from_le_bytes(rows[&10].1[1..5].try_into().unwrap()), 3);
+        assert_eq!(u32::from_le_bytes(rows[&20].1[1..5].try_into().unwrap()), 6);
+    }
+}
+
+#[test]
+fn test_parquet_row_group_per_batches() {
+    let output = NamedTempFile::new().unwrap();
+    let output_path = output.path().to_str().unwrap().to_string();
+
+    let mut sink = ParquetSink::new(Box::new(File::create(&output_path).unwrap())).unwrap();
+    for id in 0..25 {
+        let feature = Feature::from_node(OsmNode {
+            id,
+            lat: 1.0,
+            lon: 2.0,
+            tags: HashMap::new(),
+        });
+        sink.write_batch(&[feature]).unwrap();
+    }
+    sink.finish().unwrap();
+
+    let (geo, row_groups, batches) = read_parquet(&output_path);
+    assert_eq!(row_groups, 25usize.div_ceil(BATCHES_PER_ROW_GROUP));
+    assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 25);
+    assert_eq!(
+        geo["columns"]["geometry"]["geometry_types"],
+        serde_json::json!(["Point"])
+    );
+    assert!(batches[0].column(3).is_valid(0));
+}
@@ -0,0 +1,165 @@
+mod common;
+
+use common::{PbfBuilder, WAY};
+use pbf2json::*;
+use std::fs;
+use std::sync::Arc;
+use tempfile::NamedTempFile;
+
+/// Cafes, one road inside the box `0,0,1,2` and one leaving it, and a route
+/// relation over each road
+fn fixture() -> NamedTempFile {
+    PbfBuilder::new()
+        .node(1, 0.5, 0.5, &[("amenity", "cafe")])
+        .node(2, 1.5, 1.5, &[("amenity", "cafe")])
+        .node(3, 0.2, 0.2, &[])
+        .node(4, 0.8, 0.8, &[])
+        .node(5, 3.0, 3.0, &[])
+        .node(8, 1.5, 0.5, &[("amenity", "cafe")])
+        .way(10, &[3, 4], &[("highway", "residential")])
+        .way(11, &[4, 5], &[("highway", "primary")])
+        .relation(20, &[(WAY, 11, "")], &[("type", "route")])
+        .relation(21, &[(WAY, 10, "")], &[("type", "route")])
+        .write()
+}
+
+fn bbox_filter(mode: SpatialMode) -> SpatialFilter {
+    SpatialFilter {
+        region: Region::Bbox("0,0,1,2".parse().unwrap()),
+        mode,
+    }
+}
+
+fn converted_ids(input: &NamedTempFile, filter: SpatialFilter, parallel: bool) -> Vec<String> {
+    let options = ConvertOptions {
+        geometry_level: GeometryLevel::Full,
+        spatial_filters: vec![filter],
+        ..ConvertOptions::default()
+    };
+    common::converted_ids(input, &options, parallel)
+}
+
+#[test]
+fn test_bbox_modes_in_both_converters() {
+    let input = fixture();
+
+    for parallel in [false, true] {
+        assert_eq!(
+            converted_ids(&input, bbox_filter(SpatialMode::Intersects), parallel),
+            [
+                "node/1",
+                "node/8",
+                "relation/20",
+                "relation/21",
+                "way/10",
+                "way/11"
+            ]
+        );
+        assert_eq!(
+            converted_ids(&input, bbox_filter(SpatialMode::Within), parallel),
+            ["node/1", "node/8", "relation/21", "way/10"]
+        );
+        assert_eq!(
+            converted_ids(&input, bbox_filter(SpatialMode::Centroid), parallel),
+            ["node/1", "node/8", "relation/21", "way/10"]
+        );
+    }
+}
+
+#[test]
+fn test_bbox_parsing() {
+    let bbox: BoundingBox = "12.4, 41.8, 12.6, 42.0".parse().unwrap();
+    assert!(bbox.contains(41.9, 12.5));
+    assert!(!bbox.contains(12.5, 41.9));
+    assert!(bbox.intersects([12.0, 41.0, 12.5, 41.9]));
+    assert!(!bbox.encloses([12.0, 41.0, 12.5, 41.9]));
+
+    assert!("12.4,41.8,12.6".parse::<BoundingBox>().is_err());
+    assert!("12.6,41.8,12.4,42.0".parse::<BoundingBox>().is_err());
+    assert!("12.4,41.8,12.6,95".parse::<BoundingBox>().is_err());
+    assert!("a,b,c,d".parse::<BoundingBox>().is_err());
+    assert!("touches".parse::<SpatialMode>().is_err());
+}
+
+/// Square 0..2.5 with a hole 1..2, as an Osmosis polygon file
+const SQUARE_WITH_HOLE: &str = "square
+outer
+   0.0E+00   0.0E+00
+   2.5E+00   0.0E+00
+   2.5E+00   2.5E+00
+   0.0E+00   2.5E+00
+   0.0E+00   0.0E+00
+END
+!hole
+   1.0 1.0
+   2.0 1.0
+   2.0 2.0
+   1.0 2.0
+END
+END
+";
+
+fn boundary_file(content: &str) -> NamedTempFile {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), content).unwrap();
+    file
+}
+
+#[test]
+fn test_boundary_files() {
+    let poly = PreparedPolygon::from_file(boundary_file(SQUARE_WITH_HOLE).path()).unwrap();
+    assert!(poly.contains(0.5, 0.5));
+    assert!(poly.contains(2.2, 0.5));
+    assert!(!poly.contains(1.5, 1.5)); // In the hole
+    assert!(!poly.contains(1.5, 3.0));
+    assert_eq!(poly.extent(), [0.0, 0.0, 2.5, 2.5]);
+
+    // Two disjoint squares as a GeoJSON MultiPolygon feature, rings left open
+    let geojson = r#"{"type": "Feature", "properties": {}, "geometry": {
+        "type": "MultiPolygon",
+        "coordinates": [
+            [[[0, 0], [1, 0], [1, 1], [0, 1]]],
+            [[[10, 10], [11, 10], [11, 11], [10, 11]]]
+        ]}}"#;
+    let multi = PreparedPolygon::from_file(boundary_file(geojson).path()).unwrap();
+    assert!(multi.contains(0.5, 0.5));
+    assert!(multi.contains(10.5, 10.5));
+    assert!(!multi.contains(5.0, 5.0));
+
+    assert!(PreparedPolygon::from_file(boundary_file("square\nouter\n0 0\n").path()).is_err());
+    assert!(
+        PreparedPolygon::from_file(
+            boundary_file(r#"{"type": "Point", "coordinates": [0, 0]}"#).path()
+        )
+        .is_err()
+    );
+}
+
+#[test]
+fn test_clip_polygon_in_both_converters() {
+    let input = fixture();
+    let polygon = PreparedPolygon::from_file(boundary_file(SQUARE_WITH_HOLE).path()).unwrap();
+    let filter = |mode| SpatialFilter {
+        region: Region::Polygon(Arc::new(polygon.clone())),
+        mode,
+    };
+
+    // Node 2 lies in the hole and the primary road ends outside
+    for parallel in [false, true] {
+        assert_eq!(
+            converted_ids(&input, filter(SpatialMode::Intersects), parallel),
+            [
+                "node/1",
+                "node/8",
+                "relation/20",
+                "relation/21",
+                "way/10",
+                "way/11"
+            ]
+        );
+        assert_eq!(
+            converted_ids(&input, filter(SpatialMode::Within), parallel),
+            ["node/1", "node/8", "relation/21", "way/10"]
+        );
+    }
+}
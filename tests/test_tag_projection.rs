@@ -0,0 +1,127 @@
+mod common;
+
+use common::PbfBuilder;
+use pbf2json::*;
+use std::collections::HashMap;
+use std::fs;
+use tempfile::NamedTempFile;
+
+fn tags_of(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_keep_drop_and_rename() {
+    let address = || {
+        tags_of(&[
+            ("addr:street", "Via Roma"),
+            ("addr:housenumber", "7"),
+            ("addr:source", "survey"),
+            ("name", "Bar Roma"),
+            ("source", "survey"),
+            ("note", "check"),
+        ])
+    };
+
+    let projection = TagProjection::parse(
+        Some("name, addr*"),
+        Some("*source"),
+        Some("addr:street=street,addr:housenumber=housenumber"),
+    )
+    .unwrap();
+    let mut tags = address();
+    projection.apply(&mut tags);
+    assert_eq!(
+        tags,
+        tags_of(&[
+            ("street", "Via Roma"),
+            ("housenumber", "7"),
+            ("name", "Bar Roma")
+        ])
+    );
+
+    // Dropping alone keeps everything else
+    let mut tags = address();
+    TagProjection::parse(None, Some("source,note,fixme"), None)
+        .unwrap()
+        .apply(&mut tags);
+    assert_eq!(tags.len(), 4);
+
+    assert!(TagProjection::parse(None, None, Some("addr:street")).is_err());
+    assert!(TagProjection::parse(None, None, Some("=street")).is_err());
+}
+
+#[test]
+fn test_rename_onto_existing_key_keeps_its_value() {
+    let projection = TagProjection::parse(None, None, Some("name:en=name,addr:city=city")).unwrap();
+    let mut tags = tags_of(&[("name", "Roma"), ("name:en", "Rome"), ("addr:city", "Roma")]);
+    projection.apply(&mut tags);
+    assert_eq!(
+        tags,
+        tags_of(&[("name", "Roma"), ("name:en", "Rome"), ("city", "Roma")])
+    );
+}
+
+#[test]
+fn test_projection_in_both_converters() {
+    let input = PbfBuilder::new()
+        .node(
+            1,
+            0.0,
+            0.0,
+            &[
+                ("addr:street", "Via Roma"),
+                ("addr:housenumber", "7"),
+                ("source", "survey"),
+            ],
+        )
+        .node(2, 0.0, 1.0, &[])
+        .way(10, &[1, 2], &[("highway", "primary"), ("note", "check")])
+        .write();
+    let input_path = input.path().to_str().unwrap();
+
+    for geometry in ["basic", "full"] {
+        for parallel in [false, true] {
+            let output = NamedTempFile::new().unwrap();
+            let output_path = output.path().to_str().unwrap().to_string();
+            let options = ConvertOptions {
+                geometry_level: geometry.parse().unwrap(),
+                tag_projection: TagProjection::parse(
+                    None,
+                    Some("source,note"),
+                    Some("addr:street=street"),
+                )
+                .unwrap(),
+                ..ConvertOptions::default()
+            };
+            if parallel {
+                convert_pbf_parallel_with_options(input_path, Some(&output_path), &options)
+                    .unwrap();
+            } else {
+                convert_pbf_with_options(input_path, Some(&output_path), &options).unwrap();
+            }
+
+            let mut tags: Vec<serde_json::Value> = fs::read_to_string(&output_path)
+                .unwrap()
+                .lines()
+                .map(|line| {
+                    serde_json::from_str::<serde_json::Value>(line).unwrap()["tags"].clone()
+                })
+                .collect();
+            tags.sort_by_key(|tags| tags.to_string());
+            assert_eq!(
+                tags,
+                [
+                    serde_json::json!({"street": "Via Roma", "addr:housenumber": "7"}),
+                    serde_json::json!({"highway": "primary"}),
+                ],
+                "geometry {} parallel {}",
+                geometry,
+                parallel
+            );
+        }
+    }
+}
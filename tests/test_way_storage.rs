@@ -0,0 +1,89 @@
+use pbf2json::*;
+use std::collections::HashMap;
+
+fn way_member(member_id: i64, role: &str) -> OsmRelationMember {
+    OsmRelationMember {
+        member_type: MemberType::Way,
+        member_id,
+        role: role.to_string(),
+    }
+}
+
+#[test]
+fn test_resolve_relation_ways_through_disk_stores() -> anyhow::Result<()> {
+    let coordinates = CoordinateStorage::new_temp()?;
+    let ways = WayStorage::new_temp()?;
+
+    // Node 5 is referenced by way 10 but never stored
+    coordinates.store_nodes(&[(1, 0.0, 0.0), (2, 0.0, 1.0), (3, 1.0, 1.0), (4, 1.0, 0.0)])?;
+    ways.store_ways(&[(10, vec![1, 2, 5, 3]), (20, vec![3, 4, 1])])?;
+
+    let mut tags = HashMap::new();
+    tags.insert("type".to_string(), "multipolygon".to_string());
+    let relation = OsmRelation {
+        id: 1,
+        members: vec![
+            way_member(10, "outer"),
+            way_member(20, "outer"),
+            way_member(30, "outer"), // Not in the way store
+            OsmRelationMember {
+                member_type: MemberType::Node,
+                member_id: 1,
+                role: "label".to_string(),
+            },
+        ],
+        tags,
+    };
+
+    assert_eq!(
+        relation_way_ids(&relation).collect::<Vec<_>>(),
+        [10, 20, 30]
+    );
+
+    let resolved = resolve_relation_ways(&relation, &ways, &coordinates)?;
+    assert_eq!(resolved.len(), 2);
+    assert_eq!(resolved[0].way_id, 10);
+    assert_eq!(resolved[0].role, "outer");
+    assert_eq!(resolved[0].node_ids, [1, 2, 3]);
+    assert_eq!(
+        resolved[0].coordinates,
+        [(0.0, 0.0), (0.0, 1.0), (1.0, 1.0)]
+    );
+    assert_eq!(resolved[1].node_ids, [3, 4, 1]);
+
+    // Resolved ways are enough to assemble the relation
+    let members: Vec<MemberWay> = resolved
+        .iter()
+        .map(|way| MemberWay {
+            way_id: way.way_id,
+            role: &way.role,
+            node_ids: &way.node_ids,
+            coordinates: &way.coordinates,
+        })
+        .collect();
+    assert!(assemble_multipolygon(&members).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_way_db_path_sits_next_to_coordinate_db() {
+    let path = way_db_path(std::path::Path::new("/tmp/nodes.lmdb"));
+    assert_eq!(path, std::path::PathBuf::from("/tmp/nodes.lmdb.ways"));
+}
+
+#[test]
+fn test_reopened_way_db_starts_empty() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("nodes.lmdb.ways");
+
+    let storage = WayStorage::new_with_cleanup(Some(&path), true)?;
+    storage.store_ways(&[(10, vec![1, 2, 3])])?;
+    assert_eq!(storage.get_way(10)?, Some(vec![1, 2, 3]));
+    drop(storage);
+
+    // Ways of an earlier run never reach the geometry of the next one
+    let storage = WayStorage::new_with_cleanup(Some(&path), true)?;
+    assert_eq!(storage.get_way(10)?, None);
+    Ok(())
+}